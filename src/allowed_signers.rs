@@ -0,0 +1,420 @@
+use anyhow::Result;
+use std::{
+    fmt::{self, Display},
+    fs,
+    io::ErrorKind,
+    path::Path,
+};
+use thiserror::Error;
+
+/// The namespace git signs commits and tags within, restricting each
+/// managed signer to verifying git signatures only
+const GIT_NAMESPACE: &str = "git";
+
+/// Prefixes the comment of each entry written by gpg-import, naming the gpg
+/// key it was exported from, so only that key's previous entries are ever
+/// replaced
+const IDENTITY_PREFIX: &str = "gpg-import:";
+
+/// Errors that can occur when parsing an allowed signers file
+#[derive(Clone, Debug, Eq, Error, PartialEq)]
+pub enum AllowedSignersError {
+    /// A line didn't contain principals followed by a public key
+    #[error("invalid allowed signers entry on line {0}: {1}")]
+    InvalidEntry(usize, String),
+}
+
+/// A single entry within an allowed signers file, mapping one or more
+/// principals (emails) onto an SSH public key, as understood by
+/// `ssh-keygen -Y verify` and git's `gpg.ssh.allowedSignersFile`
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AllowedSigner {
+    /// A comma separated list of principals, typically email addresses
+    pub principals: String,
+    /// Any options, such as `namespaces="git"`
+    pub options: Option<String>,
+    /// The public key type, e.g. `ssh-ed25519`
+    pub key_type: String,
+    /// The base64 encoded public key
+    pub key: String,
+    /// An optional trailing comment
+    pub comment: Option<String>,
+}
+
+impl AllowedSigner {
+    /// Create an entry restricted to git signatures for an email address
+    /// and an SSH public key, as exported by `gpg --export-ssh-key` from the
+    /// gpg key with the given (primary) fingerprint
+    pub fn for_git(email: &str, ssh_key: &str, fingerprint: &str) -> Option<Self> {
+        let mut fields = ssh_key.split_whitespace();
+        let key_type = fields.next()?.to_string();
+        let key = fields.next()?.to_string();
+
+        Some(Self {
+            principals: email.to_string(),
+            options: Some(format!("namespaces=\"{GIT_NAMESPACE}\"")),
+            key_type,
+            key,
+            comment: Some(format!("{IDENTITY_PREFIX}{fingerprint}")),
+        })
+    }
+
+    /// The fingerprint of the gpg key an entry written by gpg-import was
+    /// exported from. Entries added by hand have none
+    pub fn identity(&self) -> Option<&str> {
+        self.comment.as_deref()?.strip_prefix(IDENTITY_PREFIX)
+    }
+
+    /// Determines if this entry already maps every principal of the other
+    /// onto the same key, ignoring options and comments
+    fn covers(&self, other: &AllowedSigner) -> bool {
+        self.same_key(other)
+            && other
+                .principals
+                .split(',')
+                .all(|principal| self.has_principal(principal))
+    }
+
+    fn same_key(&self, other: &AllowedSigner) -> bool {
+        self.key_type == other.key_type && self.key == other.key
+    }
+
+    fn same_identity(&self, other: &AllowedSigner) -> bool {
+        match (self.identity(), other.identity()) {
+            (Some(identity), Some(other)) => identity.eq_ignore_ascii_case(other),
+            _ => false,
+        }
+    }
+
+    fn has_principal(&self, principal: &str) -> bool {
+        self.principals
+            .split(',')
+            .any(|existing| existing.trim().eq_ignore_ascii_case(principal.trim()))
+    }
+
+    fn parse(line: &str, line_no: usize) -> Result<Self, AllowedSignersError> {
+        let invalid = || AllowedSignersError::InvalidEntry(line_no, line.to_string());
+        let (principals, rest) = line
+            .trim()
+            .split_once(char::is_whitespace)
+            .ok_or_else(invalid)?;
+        let rest = rest.trim_start();
+
+        // Options are optional, and may contain quoted whitespace, so are
+        // identified by not being a recognised key type
+        let (options, rest) = if is_key_type(rest.split_whitespace().next().unwrap_or_default()) {
+            (None, rest)
+        } else {
+            let end = options_end(rest).ok_or_else(invalid)?;
+            (Some(rest[..end].to_string()), rest[end..].trim_start())
+        };
+
+        let mut fields = rest.splitn(3, char::is_whitespace);
+        let key_type = fields
+            .next()
+            .filter(|key_type| is_key_type(key_type))
+            .ok_or_else(invalid)?;
+        let key = fields
+            .next()
+            .filter(|key| !key.is_empty())
+            .ok_or_else(invalid)?;
+        let comment = fields
+            .next()
+            .map(str::trim)
+            .filter(|comment| !comment.is_empty());
+
+        Ok(Self {
+            principals: principals.to_string(),
+            options,
+            key_type: key_type.to_string(),
+            key: key.to_string(),
+            comment: comment.map(str::to_string),
+        })
+    }
+}
+
+impl Display for AllowedSigner {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.principals)?;
+        if let Some(options) = &self.options {
+            write!(f, " {options}")?;
+        }
+        write!(f, " {} {}", self.key_type, self.key)?;
+        if let Some(comment) = &self.comment {
+            write!(f, " {comment}")?;
+        }
+        Ok(())
+    }
+}
+
+fn is_key_type(value: &str) -> bool {
+    value.starts_with("ssh-") || value.starts_with("ecdsa-") || value.starts_with("sk-")
+}
+
+/// Finds the end of an options field, skipping over any whitespace within
+/// double quotes
+fn options_end(value: &str) -> Option<usize> {
+    let mut quoted = false;
+    for (idx, c) in value.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            c if c.is_whitespace() && !quoted => return Some(idx),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// A line within an allowed signers file. Comments and blank lines are
+/// retained, so a file maintained by gpg-import can still be edited by hand
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Line {
+    Verbatim(String),
+    Signer(AllowedSigner),
+}
+
+/// An allowed signers file, maintained by merging in new signers while
+/// preserving any unrelated entries
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct AllowedSigners {
+    lines: Vec<Line>,
+}
+
+impl AllowedSigners {
+    /// Loads an allowed signers file, treating a missing file as empty
+    pub fn load(path: &Path) -> Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(contents) => Ok(contents.parse()?),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Writes the allowed signers file, replacing any existing file
+    pub fn save(&self, path: &Path) -> Result<()> {
+        fs::write(path, self.to_string())?;
+        Ok(())
+    }
+
+    /// Every signer within the file
+    pub fn signers(&self) -> impl Iterator<Item = &AllowedSigner> {
+        self.lines.iter().filter_map(|line| match line {
+            Line::Signer(signer) => Some(signer),
+            Line::Verbatim(_) => None,
+        })
+    }
+
+    /// Adds a signer, unless its principals are already mapped onto the same
+    /// key. A previous key written for the same gpg key identity is dropped
+    /// from its principals, so a rotated key is no longer trusted: an entry
+    /// is replaced in place, or only loses the principal when shared with
+    /// others. Entries for other keys, including those added by hand, are
+    /// kept even when they share a principal. Returns true if the file
+    /// changed
+    pub fn add(&mut self, signer: AllowedSigner) -> bool {
+        let mut changed = false;
+        let mut position = None;
+        let mut lines = Vec::with_capacity(self.lines.len() + 1);
+
+        for line in std::mem::take(&mut self.lines) {
+            match line {
+                Line::Signer(mut existing)
+                    if !existing.same_key(&signer) && existing.same_identity(&signer) =>
+                {
+                    let remaining = existing
+                        .principals
+                        .split(',')
+                        .filter(|principal| !signer.has_principal(principal))
+                        .collect::<Vec<_>>()
+                        .join(",");
+                    if remaining.is_empty() {
+                        position.get_or_insert(lines.len());
+                        changed = true;
+                    } else {
+                        changed |= remaining != existing.principals;
+                        existing.principals = remaining;
+                        lines.push(Line::Signer(existing));
+                    }
+                }
+                line => lines.push(line),
+            }
+        }
+        self.lines = lines;
+
+        if !self.signers().any(|existing| existing.covers(&signer)) {
+            let position = position.unwrap_or(self.lines.len());
+            self.lines.insert(position, Line::Signer(signer));
+            changed = true;
+        }
+        changed
+    }
+}
+
+impl std::str::FromStr for AllowedSigners {
+    type Err = AllowedSignersError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lines = s
+            .lines()
+            .enumerate()
+            .map(|(idx, line)| {
+                let trimmed = line.trim();
+                if trimmed.is_empty() || trimmed.starts_with('#') {
+                    Ok(Line::Verbatim(line.to_string()))
+                } else {
+                    AllowedSigner::parse(line, idx + 1).map(Line::Signer)
+                }
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self { lines })
+    }
+}
+
+impl Display for AllowedSigners {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for line in &self.lines {
+            match line {
+                Line::Verbatim(line) => writeln!(f, "{line}")?,
+                Line::Signer(signer) => writeln!(f, "{signer}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROBIN_KEY: &str = "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIGJyZw2fGcbgVqdP7p6wRDZ3R4UQOLpyA8ks5Xu9NCRN openpgp:0xED483BE3";
+    const ROBIN_FPR: &str = "5F4A1D7C9B3E2A8F6C0D4B1E7A9C3F5D2B8E6A04";
+
+    fn robin() -> AllowedSigner {
+        AllowedSigner::for_git("robin@dc.com", ROBIN_KEY, ROBIN_FPR).unwrap()
+    }
+
+    #[test]
+    fn for_git_restricts_namespace_and_marks_identity() {
+        let signer = robin();
+        assert_eq!(
+            signer.to_string(),
+            format!("robin@dc.com namespaces=\"git\" ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIGJyZw2fGcbgVqdP7p6wRDZ3R4UQOLpyA8ks5Xu9NCRN gpg-import:{ROBIN_FPR}")
+        );
+        assert_eq!(signer.identity(), Some(ROBIN_FPR));
+    }
+
+    #[test]
+    fn parse_preserves_comments_and_existing_entries() {
+        let contents = "# team signers\n\
+alfred@dc.com ssh-rsa AAAAB3NzaC1yc2E\n\
+\n\
+batman@dc.com,bruce@dc.com namespaces=\"git,file\",valid-after=\"20240101\" ssh-ed25519 AAAAC3Nza bruce laptop\n";
+
+        let signers: AllowedSigners = contents.parse().unwrap();
+        assert_eq!(signers.signers().count(), 2);
+        assert_eq!(signers.to_string(), contents);
+
+        let batman = signers.signers().nth(1).unwrap();
+        assert_eq!(batman.principals, "batman@dc.com,bruce@dc.com");
+        assert_eq!(
+            batman.options.as_deref(),
+            Some("namespaces=\"git,file\",valid-after=\"20240101\"")
+        );
+        assert_eq!(batman.key_type, "ssh-ed25519");
+        assert_eq!(batman.comment.as_deref(), Some("bruce laptop"));
+    }
+
+    #[test]
+    fn parse_rejects_entry_without_key() {
+        let result = "robin@dc.com\n".parse::<AllowedSigners>();
+        assert_eq!(
+            result,
+            Err(AllowedSignersError::InvalidEntry(
+                1,
+                "robin@dc.com".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn add_skips_signer_already_present() {
+        let mut signers = AllowedSigners::default();
+        let robin = robin();
+
+        assert!(signers.add(robin.clone()));
+        assert!(!signers.add(robin.clone()));
+        assert!(signers.add(AllowedSigner {
+            principals: "nightwing@dc.com".to_string(),
+            ..robin
+        }));
+        assert_eq!(signers.signers().count(), 2);
+    }
+
+    #[test]
+    fn add_replaces_rotated_key_in_place() {
+        let contents = format!(
+            "alfred@dc.com ssh-rsa AAAAB3NzaC1yc2E\n\
+ROBIN@dc.com namespaces=\"git\" ssh-rsa AAAAB3NzaC1yc2EOLD gpg-import:{}\n\
+batman@dc.com ssh-ed25519 AAAAC3Nza\n",
+            ROBIN_FPR.to_lowercase()
+        );
+        let mut signers: AllowedSigners = contents.parse().unwrap();
+
+        let robin = robin();
+        assert!(signers.add(robin.clone()));
+        assert!(!signers.add(robin.clone()));
+        assert_eq!(
+            signers.to_string(),
+            format!(
+                "alfred@dc.com ssh-rsa AAAAB3NzaC1yc2E\n{robin}\nbatman@dc.com ssh-ed25519 AAAAC3Nza\n")
+        );
+    }
+
+    #[test]
+    fn add_drops_principal_from_shared_entry() {
+        let contents = format!(
+            "nightwing@dc.com,robin@dc.com ssh-rsa AAAAB3NzaC1yc2EOLD gpg-import:{ROBIN_FPR}\n"
+        );
+        let mut signers: AllowedSigners = contents.parse().unwrap();
+
+        let robin = robin();
+        assert!(signers.add(robin.clone()));
+        assert_eq!(
+            signers.to_string(),
+            format!(
+                "nightwing@dc.com ssh-rsa AAAAB3NzaC1yc2EOLD gpg-import:{ROBIN_FPR}\n{robin}\n"
+            )
+        );
+    }
+
+    #[test]
+    fn add_keeps_other_keys_sharing_a_principal() {
+        let contents = "robin@dc.com ssh-rsa AAAAB3NzaC1yc2EHAND\n";
+        let mut signers: AllowedSigners = contents.parse().unwrap();
+
+        // Two gpg keys imported together for the same email both stay
+        // trusted, alongside an entry added by hand
+        let robin = robin();
+        let other = AllowedSigner::for_git(
+            "robin@dc.com",
+            "ssh-rsa AAAAB3NzaC1yc2EOTHER",
+            "0D6C2E4A8B1F3D5C7E9A0B2C4D6E8F1A3B5C7D9E",
+        )
+        .unwrap();
+        assert!(signers.add(robin.clone()));
+        assert!(signers.add(other.clone()));
+        assert_eq!(
+            signers.to_string(),
+            format!("robin@dc.com ssh-rsa AAAAB3NzaC1yc2EHAND\n{robin}\n{other}\n")
+        );
+    }
+
+    #[test]
+    fn add_keeps_shared_entry_with_same_key() {
+        let contents = format!("nightwing@dc.com,robin@dc.com {ROBIN_KEY}\n");
+        let mut signers: AllowedSigners = contents.parse().unwrap();
+
+        assert!(!signers.add(robin()));
+        assert_eq!(signers.to_string(), contents);
+    }
+}
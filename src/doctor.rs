@@ -0,0 +1,608 @@
+use crate::{
+    git,
+    gpg::{self, GpgKeyDetails, GpgPrivateKey, KeyProtection},
+};
+use anyhow::{anyhow, bail, Result};
+use chrono::{TimeZone, Utc};
+use git2::Repository;
+use std::{
+    env,
+    fmt::{self, Display},
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// Signing keys expiring within this many days are reported as a warning
+const EXPIRY_WARNING_DAYS: i64 = 30;
+
+/// The outcome of a single diagnostic check
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CheckStatus {
+    /// The check passed
+    Pass,
+    /// The check passed, but something is likely to break signing soon
+    Warn,
+    /// The check failed, and signing is expected to fail
+    Fail,
+}
+
+impl Display for CheckStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CheckStatus::Pass => write!(f, "pass"),
+            CheckStatus::Warn => write!(f, "warn"),
+            CheckStatus::Fail => write!(f, "fail"),
+        }
+    }
+}
+
+/// The result of a single diagnostic check
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Check {
+    /// A short name for what was checked
+    pub name: &'static str,
+    /// Whether the check passed
+    pub status: CheckStatus,
+    /// What was found, or why the check failed
+    pub detail: String,
+}
+
+impl Check {
+    fn pass(name: &'static str, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            status: CheckStatus::Pass,
+            detail: detail.into(),
+        }
+    }
+
+    fn warn(name: &'static str, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            status: CheckStatus::Warn,
+            detail: detail.into(),
+        }
+    }
+
+    fn fail(name: &'static str, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            status: CheckStatus::Fail,
+            detail: detail.into(),
+        }
+    }
+}
+
+/// Whether git can sign with its SSH mode (gpg.format=ssh) signing key.
+/// Git then signs with `ssh-keygen` rather than gpg, so the key is looked
+/// up within the SSH agent, rather than the gpg keyring
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SshKeyStatus {
+    /// user.signingKey is the path of a private key file, signed with
+    /// directly
+    KeyFile,
+    /// The SSH agent serves the key
+    Served,
+    /// The SSH agent doesn't serve the key, so signing will fail
+    NotServed,
+}
+
+impl Display for SshKeyStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SshKeyStatus::KeyFile => write!(f, "private key file"),
+            SshKeyStatus::Served => write!(f, "served by the ssh agent"),
+            SshKeyStatus::NotServed => write!(f, "not served by the ssh agent"),
+        }
+    }
+}
+
+impl Display for Check {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[{}] {:<14} {}", self.status, self.name, self.detail)
+    }
+}
+
+/// Diagnoses why signing may fail, by checking the GnuPG installation, its
+/// configuration and agent, and the key git is configured to sign with
+pub struct Doctor<'repo> {
+    repo: Option<&'repo Repository>,
+    gpg: gpg::GpgContext,
+}
+
+impl<'repo> Doctor<'repo> {
+    /// Create a doctor reading git config from the given repository (all
+    /// config levels), or from the global and system config
+    pub fn new(repo: Option<&'repo Repository>) -> Self {
+        Self {
+            repo,
+            gpg: gpg::GpgContext::default(),
+        }
+    }
+
+    /// Check the given GnuPG installation and keyring, rather than `gpg`
+    /// from `PATH` and its default homedir
+    pub fn with_gpg_context(mut self, gpg: gpg::GpgContext) -> Self {
+        self.gpg = gpg;
+        self
+    }
+
+    /// Runs every check. Checks that depend upon an earlier one failing are
+    /// still reported, as failed, so the full checklist is always shown
+    pub fn run(&self) -> Vec<Check> {
+        let mut checks = Vec::new();
+
+        let info = gpg::detect_version(&self.gpg);
+        checks.push(match &info {
+            Ok(info) => match info.ensure_supported() {
+                Ok(()) => Check::pass(
+                    "gpg",
+                    format!("{} ({})", info.version, self.gpg.program().display()),
+                ),
+                Err(e) => Check::fail("gpg", e.to_string()),
+            },
+            Err(e) => Check::fail(
+                "gpg",
+                format!("failed to run {}: {e}", self.gpg.program().display()),
+            ),
+        });
+
+        match &info {
+            Ok(info) => {
+                checks.push(check_home_dir_permissions(&info.home_dir));
+                checks.push(check_gpg_conf(&info.home_dir));
+                checks.push(check_gpg_agent_conf(&info.home_dir));
+            }
+            Err(_) => {
+                for name in ["homedir", "gpg.conf", "gpg-agent.conf"] {
+                    checks.push(Check::fail(name, "gpg homedir unknown"));
+                }
+            }
+        }
+
+        checks.push(match gpg::agent_info(&self.gpg) {
+            Ok(agent) => Check::pass(
+                "gpg-agent",
+                format!("pid {} on {}", agent.pid, agent.socket_name.display()),
+            ),
+            Err(e) => Check::fail("gpg-agent", e.to_string()),
+        });
+
+        checks.extend(self.check_signing_key());
+        checks.push(self.check_gpg_program());
+        checks
+    }
+
+    fn check_signing_key(&self) -> Vec<Check> {
+        let signing_key = match git::signing_key(self.repo) {
+            Ok(Some(signing_key)) => signing_key,
+            Ok(None) => return unresolved_signing_key("user.signingKey is not set"),
+            Err(e) => return unresolved_signing_key(&e.to_string()),
+        };
+        match git::signing_format(self.repo) {
+            Ok(Some(format)) if format == "ssh" => {
+                return vec![check_ssh_signing_key(&self.gpg, &signing_key)]
+            }
+            Ok(_) => {}
+            Err(e) => return unresolved_signing_key(&e.to_string()),
+        }
+
+        let private_key = match gpg::extract_key_info(&self.gpg, signing_key.trim_end_matches('!'))
+        {
+            Ok(private_key) => private_key,
+            Err(e) => {
+                return unresolved_signing_key(&format!(
+                    "{signing_key} does not resolve to a usable secret key: {e}"
+                ))
+            }
+        };
+
+        let keys = signing_keys(&private_key, &signing_key);
+        if keys.is_empty() {
+            return unresolved_signing_key(&format!(
+                "{signing_key} has no signing capable key or subkey"
+            ));
+        }
+
+        vec![
+            Check::pass(
+                "signing key",
+                format!("{signing_key} ({})", private_key.primary_uid()),
+            ),
+            check_expiry(&keys, Utc::now().timestamp()),
+            check_passphrase_cached(&self.gpg, &keys),
+        ]
+    }
+
+    fn check_gpg_program(&self) -> Check {
+        let git_program = match git::effective_gpg_program(self.repo) {
+            Ok(git_program) => git_program.unwrap_or_else(|| "gpg".to_string()),
+            Err(e) => return Check::fail("gpg.program", e.to_string()),
+        };
+
+        let Some(git_program_path) = gpg::resolve_program(Path::new(&git_program)) else {
+            return Check::fail("gpg.program", format!("{git_program} could not be found"));
+        };
+
+        if gpg::resolve_program(&self.gpg.program()).as_ref() != Some(&git_program_path) {
+            return Check::warn(
+                "gpg.program",
+                format!(
+                    "git signs with {}, not {}",
+                    git_program_path.display(),
+                    self.gpg.program().display()
+                ),
+            );
+        }
+        Check::pass("gpg.program", git_program_path.display().to_string())
+    }
+}
+
+fn unresolved_signing_key(reason: &str) -> Vec<Check> {
+    vec![
+        Check::fail("signing key", reason),
+        Check::fail("expiry", "signing key unresolved"),
+        Check::fail("passphrase", "signing key unresolved"),
+    ]
+}
+
+/// The keys gpg may sign with for a `user.signingKey`: exactly the named
+/// key when forced with a `!` suffix, otherwise any signing capable key
+pub(crate) fn signing_keys<'a>(
+    private_key: &'a GpgPrivateKey,
+    signing_key: &str,
+) -> Vec<&'a GpgKeyDetails> {
+    let keys = std::iter::once(&private_key.secret_key).chain(&private_key.subkeys);
+    match signing_key.strip_suffix('!') {
+        Some(fingerprint) => keys
+            .filter(|key| key.fingerprint.eq_ignore_ascii_case(fingerprint))
+            .collect(),
+        None => keys.filter(|key| key.capabilities.sign).collect(),
+    }
+}
+
+/// Determines if git can sign with an SSH mode user.signingKey: either an
+/// SSH public key (`key::ssh-...`) or the path of a key file. A public key
+/// has to be served by the SSH agent git signs through, being the one in
+/// SSH_AUTH_SOCK, or otherwise the gpg-agent's
+pub(crate) fn ssh_key_status(ctx: &gpg::GpgContext, signing_key: &str) -> Result<SshKeyStatus> {
+    let public_key = match signing_key.strip_prefix("key::") {
+        Some(public_key) => public_key.to_string(),
+        None => {
+            let path = match (signing_key.strip_prefix("~/"), env::var_os("HOME")) {
+                (Some(rest), Some(home)) => PathBuf::from(home).join(rest),
+                _ => PathBuf::from(signing_key),
+            };
+            let contents = fs::read_to_string(&path)
+                .map_err(|e| anyhow!("failed to read {}: {e}", path.display()))?;
+            if contents.trim_start().starts_with("-----BEGIN") {
+                return Ok(SshKeyStatus::KeyFile);
+            }
+            contents
+        }
+    };
+    let material = ssh_key_material(&public_key)
+        .ok_or_else(|| anyhow!("{signing_key} is not an ssh public key"))?;
+
+    let socket = match env::var_os("SSH_AUTH_SOCK") {
+        Some(socket) => PathBuf::from(socket),
+        None => gpg::ssh_auth_sock(ctx)?,
+    };
+    let output = Command::new("ssh-add")
+        .arg("-L")
+        .env("SSH_AUTH_SOCK", &socket)
+        .output()
+        .map_err(|e| anyhow!("failed to run ssh-add: {e}"))?;
+    // ssh-add exits with 1 when the agent holds no keys, and 2 when it
+    // can't be reached
+    if output.status.code() == Some(2) {
+        bail!(
+            "ssh agent {} could not be reached: {}",
+            socket.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    let served = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .any(|line| ssh_key_material(line) == Some(material));
+    Ok(if served {
+        SshKeyStatus::Served
+    } else {
+        SshKeyStatus::NotServed
+    })
+}
+
+/// The key type and base64 encoded key of an SSH public key, ignoring any
+/// comment
+fn ssh_key_material(public_key: &str) -> Option<(&str, &str)> {
+    let mut fields = public_key.split_whitespace();
+    Some((fields.next()?, fields.next()?))
+}
+
+fn check_ssh_signing_key(ctx: &gpg::GpgContext, signing_key: &str) -> Check {
+    match ssh_key_status(ctx, signing_key) {
+        Ok(SshKeyStatus::NotServed) => Check::fail(
+            "signing key",
+            format!("{signing_key} is {}", SshKeyStatus::NotServed),
+        ),
+        Ok(status) => Check::pass("signing key", format!("{signing_key} ({status})")),
+        Err(e) => Check::fail(
+            "signing key",
+            format!("{signing_key} could not be checked: {e}"),
+        ),
+    }
+}
+
+fn check_home_dir_permissions(home_dir: &Path) -> Check {
+    let metadata = match fs::metadata(home_dir) {
+        Ok(metadata) => metadata,
+        Err(e) => return Check::fail("homedir", format!("{}: {e}", home_dir.display())),
+    };
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = metadata.permissions().mode() & 0o777;
+        if mode & 0o077 != 0 {
+            return Check::fail(
+                "homedir",
+                format!(
+                    "{} is accessible by other users ({mode:04o})",
+                    home_dir.display()
+                ),
+            );
+        }
+        Check::pass("homedir", format!("{} ({mode:04o})", home_dir.display()))
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = metadata;
+        Check::pass("homedir", home_dir.display().to_string())
+    }
+}
+
+fn check_gpg_conf(home_dir: &Path) -> Check {
+    check_conf(
+        "gpg.conf",
+        &home_dir.join("gpg.conf"),
+        &["use-agent", "pinentry-mode loopback"],
+        CheckStatus::Warn,
+    )
+}
+
+fn check_gpg_agent_conf(home_dir: &Path) -> Check {
+    check_conf(
+        "gpg-agent.conf",
+        &home_dir.join("gpg-agent.conf"),
+        &["allow-preset-passphrase", "allow-loopback-pinentry"],
+        CheckStatus::Fail,
+    )
+}
+
+/// Ensures a config file contains each of the required options, reporting
+/// any that are missing with the given status
+fn check_conf(
+    name: &'static str,
+    path: &Path,
+    required: &[&str],
+    missing_status: CheckStatus,
+) -> Check {
+    let contents = fs::read_to_string(path).unwrap_or_default();
+    let options: Vec<String> = contents
+        .lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .collect();
+
+    let missing: Vec<&str> = required
+        .iter()
+        .copied()
+        .filter(|option| !options.iter().any(|line| line == option))
+        .collect();
+    if missing.is_empty() {
+        return Check::pass(name, path.display().to_string());
+    }
+
+    Check {
+        name,
+        status: missing_status,
+        detail: format!("{} is missing: {}", path.display(), missing.join(", ")),
+    }
+}
+
+/// Checks that at least one of the signing keys hasn't expired, warning if
+/// the last of them expires soon
+fn check_expiry(keys: &[&GpgKeyDetails], now: i64) -> Check {
+    if keys.iter().any(|key| key.expiration_date.is_none()) {
+        return Check::pass("expiry", "never expires");
+    }
+
+    let Some(expires) = keys.iter().filter_map(|key| key.expiration_date).max() else {
+        return Check::pass("expiry", "never expires");
+    };
+    let expires_on = Utc.timestamp_opt(expires, 0).unwrap().to_rfc2822();
+    if expires <= now {
+        return Check::fail("expiry", format!("expired on {expires_on}"));
+    }
+
+    let days = (expires - now) / 86400;
+    if days < EXPIRY_WARNING_DAYS {
+        return Check::warn(
+            "expiry",
+            format!("expires on {expires_on} (in {days} days)"),
+        );
+    }
+    Check::pass("expiry", format!("expires on {expires_on}"))
+}
+
+/// Checks that the agent can sign without prompting, as at least one of the
+/// signing keys is unprotected or has its passphrase cached
+fn check_passphrase_cached(ctx: &gpg::GpgContext, keys: &[&GpgKeyDetails]) -> Check {
+    let agent_keys = match gpg::agent_key_info(ctx) {
+        Ok(agent_keys) => agent_keys,
+        Err(e) => return Check::fail("passphrase", e.to_string()),
+    };
+
+    let mut not_cached = Vec::new();
+    for key in keys {
+        match agent_keys.iter().find(|info| info.keygrip == key.keygrip) {
+            Some(info) if info.protection == KeyProtection::Unprotected => {
+                return Check::pass("passphrase", format!("{} is unprotected", key.keygrip))
+            }
+            Some(info) if info.cached => {
+                return Check::pass("passphrase", format!("{} is cached", key.keygrip))
+            }
+            _ => not_cached.push(key.keygrip.as_str()),
+        }
+    }
+
+    Check::fail(
+        "passphrase",
+        format!("not cached for {}", not_cached.join(", ")),
+    )
+}
+
+/// Prints every check, failing if any check failed
+pub fn report(checks: &[Check]) -> Result<()> {
+    println!("> Doctor:");
+    for check in checks {
+        println!("{check}");
+    }
+
+    let failed = checks
+        .iter()
+        .filter(|check| check.status == CheckStatus::Fail)
+        .count();
+    if failed > 0 {
+        bail!("{failed} of {} checks failed", checks.len());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gpg::{GpgCapabilities, GpgUid};
+    use tempfile::TempDir;
+
+    fn key(fingerprint: &str, sign: bool, expiration_date: Option<i64>) -> GpgKeyDetails {
+        GpgKeyDetails {
+            creation_date: 0,
+            expiration_date,
+            fingerprint: fingerprint.to_string(),
+            key_id: fingerprint.to_string(),
+            keygrip: format!("{fingerprint}GRIP"),
+            capabilities: GpgCapabilities {
+                sign,
+                ..Default::default()
+            },
+        }
+    }
+
+    fn private_key() -> GpgPrivateKey {
+        GpgPrivateKey {
+            uids: vec![GpgUid {
+                name: "batman".to_string(),
+                email: "batman@dc.com".to_string(),
+            }],
+            secret_key: key("PRIMARYFPR", true, None),
+            subkeys: vec![key("ENCRYPTFPR", false, None), key("SIGNFPR", true, None)],
+        }
+    }
+
+    #[test]
+    fn signing_keys_forced_subkey() {
+        let private_key = private_key();
+        let keys = signing_keys(&private_key, "ENCRYPTFPR!");
+        assert_eq!(keys.len(), 1);
+        assert_eq!(keys[0].fingerprint, "ENCRYPTFPR");
+    }
+
+    #[test]
+    fn signing_keys_any_signing_capable_key() {
+        let private_key = private_key();
+        let fingerprints: Vec<&str> = signing_keys(&private_key, "PRIMARYFPR")
+            .iter()
+            .map(|key| key.fingerprint.as_str())
+            .collect();
+        assert_eq!(fingerprints, vec!["PRIMARYFPR", "SIGNFPR"]);
+    }
+
+    #[test]
+    fn check_expiry_by_latest_expiring_key() {
+        let now = 1_700_000_000;
+        let expired = key("EXPIRED", true, Some(now - 1));
+        let soon = key("SOON", true, Some(now + 86400 * 7));
+        let later = key("LATER", true, Some(now + 86400 * 365));
+        let never = key("NEVER", true, None);
+
+        assert_eq!(check_expiry(&[&expired], now).status, CheckStatus::Fail);
+        assert_eq!(
+            check_expiry(&[&expired, &soon], now).status,
+            CheckStatus::Warn
+        );
+        assert_eq!(
+            check_expiry(&[&soon, &later], now).status,
+            CheckStatus::Pass
+        );
+        assert_eq!(
+            check_expiry(&[&expired, &never], now),
+            Check::pass("expiry", "never expires")
+        );
+    }
+
+    #[test]
+    fn check_conf_reports_missing_options() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("gpg-agent.conf");
+        fs::write(
+            &path,
+            "# managed by gpg-import\nallow-preset-passphrase\ndefault-cache-ttl  600\n",
+        )
+        .unwrap();
+
+        let check = check_gpg_agent_conf(temp_dir.path());
+        assert_eq!(check.status, CheckStatus::Fail);
+        assert!(
+            check
+                .detail
+                .ends_with("is missing: allow-loopback-pinentry"),
+            "Unexpected detail: {}",
+            check.detail
+        );
+
+        let check = check_gpg_conf(temp_dir.path());
+        assert_eq!(check.status, CheckStatus::Warn);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn check_home_dir_permissions_rejects_group_access() {
+        use std::os::unix::fs::PermissionsExt;
+        let temp_dir = TempDir::new().unwrap();
+
+        fs::set_permissions(temp_dir.path(), fs::Permissions::from_mode(0o700)).unwrap();
+        assert_eq!(
+            check_home_dir_permissions(temp_dir.path()).status,
+            CheckStatus::Pass
+        );
+
+        fs::set_permissions(temp_dir.path(), fs::Permissions::from_mode(0o750)).unwrap();
+        assert_eq!(
+            check_home_dir_permissions(temp_dir.path()).status,
+            CheckStatus::Fail
+        );
+    }
+
+    #[test]
+    fn display_check() {
+        let check = Check::fail("passphrase", "not cached for ABCDEF");
+        assert_eq!(
+            check.to_string(),
+            "[fail] passphrase     not cached for ABCDEF"
+        );
+    }
+}
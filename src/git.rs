@@ -1,16 +1,28 @@
-use anyhow::Result;
-use git2::{Config, Repository};
-use std::fmt::{self, Display};
+use anyhow::{anyhow, bail, Result};
+use git2::{Config, ConfigLevel, ErrorCode, Repository};
+use std::{
+    env,
+    fmt::{self, Display},
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// A pre-push hook refusing to push commits not signed by user.signingKey
+const PRE_PUSH_HOOK: &str = include_str!("hooks/pre-push");
+
+/// Identifies a hook installed by gpg-import, which is safe to replace
+const PRE_PUSH_HOOK_MARKER: &str = "# gpg-import: pre-push hook";
 
 /// Git GPG signing configuration that will written to the local
 /// .git/config of the repository
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct SigningConfig {
     /// User name associated with the signing key, maps to user.name
     pub user_name: String,
     /// User email associated with the signing key, maps to user.email
     pub user_email: String,
-    /// The shortform ID of the signing key, maps to user.signingKey
+    /// The ID of the signing key, or the fingerprint of a selected subkey
+    /// suffixed with `!`, maps to user.signingKey
     pub key_id: String,
     /// A flag to enable GPG signing of commits, maps to commit.gpgsign
     pub commit_sign: bool,
@@ -18,6 +30,8 @@ pub struct SigningConfig {
     pub tag_sign: bool,
     /// A flag to enable GPG signing of pushes, maps to push.gpgsign
     pub push_sign: bool,
+    /// The GnuPG binary git should sign with, maps to gpg.program
+    pub gpg_program: Option<String>,
 }
 
 impl Display for SigningConfig {
@@ -30,13 +44,64 @@ impl Display for SigningConfig {
         if self.push_sign {
             writeln!(f, "push.gpgsign:    if-asked")?;
         }
+        if let Some(gpg_program) = &self.gpg_program {
+            writeln!(f, "gpg.program:     {gpg_program}")?;
+        }
         Ok(())
     }
 }
 
-/// Determines if the current working directory is in fact a git repository
+/// Determines if the current working directory is within a git repository.
+/// Mirrors git's own lookup: an explicit `GIT_DIR` (and `GIT_WORK_TREE`)
+/// takes precedence, otherwise parent directories are searched until a
+/// repository, bare or not, is found
 pub fn is_repo() -> Option<Repository> {
-    Repository::open(".").ok()
+    match env::var_os("GIT_DIR") {
+        Some(git_dir) => open_git_dir(Path::new(&git_dir)).ok(),
+        None => Repository::discover(".").ok(),
+    }
+}
+
+/// Discovers the git repository containing the given path, searching
+/// parent directories in the same way as git. Unlike [`is_repo`], failing
+/// to find a repository is an error, as the path was explicitly requested
+pub fn discover_repo(path: &Path) -> Result<Repository> {
+    Repository::discover(path).map_err(|e| {
+        anyhow!(
+            "no git repository found at '{}': {}",
+            path.display(),
+            e.message()
+        )
+    })
+}
+
+/// Opens every initialised submodule of the given repository, recursing into
+/// nested submodules. Submodules are independent repositories with their own
+/// config, so they don't inherit anything written to the superproject.
+/// Submodules that haven't been checked out are skipped, as there is no
+/// repository (and config) to open
+pub fn submodule_repos(repo: &Repository) -> Result<Vec<Repository>> {
+    let mut repos = Vec::new();
+    for submodule in repo.submodules()? {
+        let Ok(sub_repo) = submodule.open() else {
+            continue;
+        };
+        let nested = submodule_repos(&sub_repo)?;
+        repos.push(sub_repo);
+        repos.extend(nested);
+    }
+    Ok(repos)
+}
+
+/// Opens the repository named by `GIT_DIR` without searching parent
+/// directories, applying `GIT_WORK_TREE` if set. libgit2 doesn't support
+/// `GIT_WORK_TREE` through `Repository::open_from_env`, hence doing this by hand
+fn open_git_dir(git_dir: &Path) -> Result<Repository, git2::Error> {
+    let repo = Repository::open(git_dir)?;
+    if let Some(work_tree) = env::var_os("GIT_WORK_TREE") {
+        repo.set_workdir(Path::new(&work_tree), false)?;
+    }
+    Ok(repo)
 }
 
 /// Configures the current repository to support GPG signing based on
@@ -61,14 +126,406 @@ fn apply_signing_config(config: &mut Config, cfg: &SigningConfig) -> Result<()>
     if cfg.tag_sign {
         config.set_str("push.gpgsign", "if-asked")?;
     }
+    if let Some(gpg_program) = &cfg.gpg_program {
+        config.set_str("gpg.program", gpg_program)?;
+    }
+    Ok(())
+}
+
+/// Reads the gpg.program git will sign with, as seen from the given
+/// repository (all config levels) or from the global and system config.
+/// Returns `None` when unset, in which case git resolves `gpg` from `PATH`
+pub fn effective_gpg_program(repo: Option<&Repository>) -> Result<Option<String>> {
+    effective_string(repo, "gpg.program")
+}
+
+/// The git config keys that affect signing, in the order they're reported
+pub const SIGNING_CONFIG_KEYS: [&str; 9] = [
+    "user.name",
+    "user.email",
+    "user.signingKey",
+    "commit.gpgSign",
+    "tag.gpgSign",
+    "push.gpgSign",
+    "gpg.format",
+    "gpg.program",
+    "gpg.ssh.allowedSignersFile",
+];
+
+/// The effective value of a git config key, along with the config level
+/// (file) it was read from
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConfigValue {
+    /// The config key, e.g. user.signingKey
+    pub key: String,
+    /// The effective value
+    pub value: String,
+    /// The level of the config file the value was read from
+    pub level: ConfigLevel,
+}
+
+impl Display for ConfigValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let origin = match self.level {
+            ConfigLevel::ProgramData => "programdata",
+            ConfigLevel::System => "system",
+            ConfigLevel::XDG => "xdg",
+            ConfigLevel::Global => "global",
+            ConfigLevel::Local => "local",
+            ConfigLevel::Worktree => "worktree",
+            ConfigLevel::App => "app",
+            ConfigLevel::Highest => "highest",
+        };
+        write!(
+            f,
+            "{:<27} {} ({origin})",
+            format!("{}:", self.key),
+            self.value
+        )
+    }
+}
+
+/// Reads the effective value of each signing related git config key, as
+/// seen from the given repository (all config levels) or from the global
+/// and system config. Unset keys are omitted
+pub fn signing_config(repo: Option<&Repository>) -> Result<Vec<ConfigValue>> {
+    let config = open_config(repo)?;
+    let mut values = Vec::new();
+    for key in SIGNING_CONFIG_KEYS {
+        let entry = match config.get_entry(key) {
+            Ok(entry) => entry,
+            Err(e) if e.code() == ErrorCode::NotFound => continue,
+            Err(e) => return Err(e.into()),
+        };
+        values.push(ConfigValue {
+            key: key.to_string(),
+            value: entry.value().unwrap_or_default().to_string(),
+            level: entry.level(),
+        });
+    }
+    Ok(values)
+}
+
+/// Reads the key git signs with (user.signingKey), as seen from the given
+/// repository (all config levels) or from the global and system config
+pub fn signing_key(repo: Option<&Repository>) -> Result<Option<String>> {
+    effective_string(repo, "user.signingKey")
+}
+
+/// Reads the signature format git signs with (gpg.format), as seen from
+/// the given repository (all config levels) or from the global and system
+/// config. Returns `None` when unset, in which case git uses openpgp
+pub fn signing_format(repo: Option<&Repository>) -> Result<Option<String>> {
+    effective_string(repo, "gpg.format")
+}
+
+/// Configures the allowed signers file git verifies SSH signatures against
+/// (gpg.ssh.allowedSignersFile), within the given repository or globally
+pub fn configure_allowed_signers(repo: Option<&Repository>, path: &Path) -> Result<()> {
+    let mut config = open_config(repo)?;
+    config.set_str("gpg.ssh.allowedSignersFile", &path.to_string_lossy())?;
     Ok(())
 }
 
+/// Resolves the directory git runs hooks from: core.hooksPath when set,
+/// otherwise the hooks directory within the common git directory, shared by
+/// every linked worktree. A relative core.hooksPath is resolved against the
+/// working tree (or the git directory of a bare repository) hooks run in,
+/// matching git
+pub fn hooks_dir(repo: &Repository) -> Result<PathBuf> {
+    let base = repo.workdir().unwrap_or_else(|| repo.path());
+
+    match effective_string(Some(repo), "core.hooksPath")? {
+        Some(hooks_path) => {
+            let hooks_path = match hooks_path.strip_prefix("~/") {
+                Some(rest) => env::var_os("HOME")
+                    .map(|home| PathBuf::from(home).join(rest))
+                    .ok_or_else(|| anyhow!("HOME is not set, cannot resolve core.hooksPath"))?,
+                None => PathBuf::from(hooks_path),
+            };
+            Ok(base.join(hooks_path))
+        }
+        None => Ok(repo.commondir().join("hooks")),
+    }
+}
+
+/// Installs a pre-push hook that refuses to push commits not signed by the
+/// configured user.signingKey, returning its path. An existing hook is only
+/// replaced if it was previously installed by gpg-import
+pub fn install_pre_push_hook(repo: &Repository) -> Result<PathBuf> {
+    let hooks_dir = hooks_dir(repo)?;
+    let hook_path = hooks_dir.join("pre-push");
+
+    if let Ok(existing) = fs::read_to_string(&hook_path) {
+        if !existing.contains(PRE_PUSH_HOOK_MARKER) {
+            bail!(
+                "a pre-push hook already exists at {}, refusing to replace it",
+                hook_path.display()
+            );
+        }
+    }
+
+    fs::create_dir_all(&hooks_dir)?;
+    fs::write(&hook_path, PRE_PUSH_HOOK)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&hook_path, fs::Permissions::from_mode(0o755))?;
+    }
+
+    Ok(hook_path)
+}
+
+fn open_config(repo: Option<&Repository>) -> Result<Config> {
+    match repo {
+        Some(repo) => Ok(repo.config()?),
+        None => Ok(Config::open_default()?),
+    }
+}
+
+fn effective_string(repo: Option<&Repository>, key: &str) -> Result<Option<String>> {
+    match open_config(repo)?.get_string(key) {
+        Ok(value) => Ok(Some(value)),
+        Err(e) if e.code() == ErrorCode::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use serial_test::serial;
+    use std::fs;
     use tempfile::TempDir;
 
+    /// Temporarily sets (or removes) environment variables for the current
+    /// process, restoring the original values when dropped
+    struct EnvGuard {
+        originals: Vec<(&'static str, Option<std::ffi::OsString>)>,
+    }
+
+    impl EnvGuard {
+        fn set(vars: &[(&'static str, Option<&Path>)]) -> Self {
+            let originals = vars
+                .iter()
+                .map(|(name, value)| {
+                    let original = env::var_os(name);
+                    match value {
+                        Some(value) => env::set_var(name, value),
+                        None => env::remove_var(name),
+                    }
+                    (*name, original)
+                })
+                .collect();
+
+            Self { originals }
+        }
+    }
+
+    impl Drop for EnvGuard {
+        fn drop(&mut self) {
+            for (name, original) in &self.originals {
+                match original {
+                    Some(value) => env::set_var(name, value),
+                    None => env::remove_var(name),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn discover_repo_from_subdirectory() {
+        let temp_dir = TempDir::new().unwrap();
+        Repository::init(temp_dir.path()).unwrap();
+        let nested = temp_dir.path().join("src").join("nested");
+        fs::create_dir_all(&nested).unwrap();
+
+        let repo = discover_repo(&nested);
+        assert!(repo.is_ok(), "Should discover the enclosing repository");
+
+        let workdir = repo.unwrap().workdir().unwrap().canonicalize().unwrap();
+        assert_eq!(workdir, temp_dir.path().canonicalize().unwrap());
+    }
+
+    #[test]
+    fn discover_repo_supports_bare_repository() {
+        let temp_dir = TempDir::new().unwrap();
+        Repository::init_bare(temp_dir.path()).unwrap();
+
+        let repo = discover_repo(temp_dir.path());
+        assert!(repo.is_ok(), "Should discover a bare repository");
+        assert!(repo.unwrap().is_bare());
+    }
+
+    #[test]
+    fn discover_repo_fails_outside_repository() {
+        let temp_dir = TempDir::new().unwrap();
+        let result = discover_repo(temp_dir.path());
+        assert!(result.is_err(), "Should fail when no repository exists");
+    }
+
+    /// Adds an (uncommitted) submodule at `path` within `repo`. libgit2
+    /// initialises an empty repository for it in place rather than cloning
+    fn add_submodule(repo: &Repository, path: &str) -> Repository {
+        repo.submodule(
+            "https://example.com/batman/utility-belt.git",
+            Path::new(path),
+            true,
+        )
+        .unwrap()
+        .open()
+        .unwrap()
+    }
+
+    #[test]
+    fn submodule_repos_walks_nested_submodules() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = Repository::init(temp_dir.path()).unwrap();
+        let batarang = add_submodule(&repo, "batarang");
+        add_submodule(&batarang, "grapple");
+        add_submodule(&repo, "batmobile");
+
+        let repos = submodule_repos(&repo).unwrap();
+        let mut workdirs: Vec<_> = repos
+            .iter()
+            .map(|r| {
+                r.workdir()
+                    .unwrap()
+                    .strip_prefix(temp_dir.path())
+                    .unwrap()
+                    .to_path_buf()
+            })
+            .collect();
+        workdirs.sort();
+
+        assert_eq!(
+            workdirs,
+            vec![
+                Path::new("batarang").to_path_buf(),
+                Path::new("batarang/grapple").to_path_buf(),
+                Path::new("batmobile").to_path_buf(),
+            ]
+        );
+    }
+
+    #[test]
+    fn submodule_repos_skips_uninitialised_submodules() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = Repository::init(temp_dir.path()).unwrap();
+        fs::write(
+            temp_dir.path().join(".gitmodules"),
+            "[submodule \"cave\"]\n\tpath = cave\n\turl = https://example.com/batman/cave.git\n",
+        )
+        .unwrap();
+
+        let repos = submodule_repos(&repo).unwrap();
+        assert!(repos.is_empty(), "Uninitialised submodules have no config");
+    }
+
+    #[test]
+    #[serial]
+    fn is_repo_honours_git_dir_and_git_work_tree() {
+        let git_dir = TempDir::new().unwrap();
+        let work_tree = TempDir::new().unwrap();
+        Repository::init_bare(git_dir.path()).unwrap();
+        let _env_guard = EnvGuard::set(&[
+            ("GIT_DIR", Some(git_dir.path())),
+            ("GIT_WORK_TREE", Some(work_tree.path())),
+        ]);
+
+        let repo = is_repo();
+        assert!(
+            repo.is_some(),
+            "Should open the repository named by GIT_DIR"
+        );
+
+        let repo = repo.unwrap();
+        assert_eq!(
+            repo.path().canonicalize().unwrap(),
+            git_dir.path().canonicalize().unwrap()
+        );
+        assert_eq!(
+            repo.workdir().unwrap().canonicalize().unwrap(),
+            work_tree.path().canonicalize().unwrap()
+        );
+    }
+
+    #[test]
+    fn hooks_dir_defaults_to_git_dir() {
+        let repo_dir = TempDir::new().unwrap();
+        let repo = Repository::init(repo_dir.path()).unwrap();
+
+        assert_eq!(hooks_dir(&repo).unwrap(), repo.path().join("hooks"));
+    }
+
+    #[test]
+    fn hooks_dir_of_linked_worktree() {
+        let repo_dir = TempDir::new().unwrap();
+        let repo = Repository::init(repo_dir.path()).unwrap();
+        let signature = git2::Signature::now("Batman", "batman@dc.com").unwrap();
+        let tree = repo
+            .find_tree(repo.index().unwrap().write_tree().unwrap())
+            .unwrap();
+        repo.commit(Some("HEAD"), &signature, &signature, "init", &tree, &[])
+            .unwrap();
+
+        let worktrees_dir = TempDir::new().unwrap();
+        let linked_dir = worktrees_dir.path().join("linked");
+        repo.worktree("linked", &linked_dir, None).unwrap();
+        let linked = Repository::open(&linked_dir).unwrap();
+
+        // Hooks are shared with the main working tree by default...
+        assert_eq!(
+            hooks_dir(&linked).unwrap().canonicalize().unwrap(),
+            repo.path().join("hooks").canonicalize().unwrap()
+        );
+
+        // ...while a relative core.hooksPath is run from the linked one
+        repo.config()
+            .unwrap()
+            .set_str("core.hooksPath", ".githooks")
+            .unwrap();
+        assert_eq!(
+            hooks_dir(&linked).unwrap(),
+            linked.workdir().unwrap().join(".githooks")
+        );
+    }
+
+    #[test]
+    fn hooks_dir_honours_relative_core_hooks_path() {
+        let repo_dir = TempDir::new().unwrap();
+        let repo = Repository::init(repo_dir.path()).unwrap();
+        repo.config()
+            .unwrap()
+            .set_str("core.hooksPath", ".githooks")
+            .unwrap();
+
+        assert_eq!(
+            hooks_dir(&repo).unwrap(),
+            repo.workdir().unwrap().join(".githooks")
+        );
+    }
+
+    #[test]
+    fn install_pre_push_hook_replaces_only_its_own_hook() {
+        let repo_dir = TempDir::new().unwrap();
+        let repo = Repository::init(repo_dir.path()).unwrap();
+
+        let hook_path = install_pre_push_hook(&repo).unwrap();
+        assert_eq!(hook_path, repo.path().join("hooks").join("pre-push"));
+        assert_eq!(fs::read_to_string(&hook_path).unwrap(), PRE_PUSH_HOOK);
+        assert!(
+            install_pre_push_hook(&repo).is_ok(),
+            "Reinstalling should replace the existing gpg-import hook"
+        );
+
+        fs::write(&hook_path, "#!/bin/sh\nexit 0\n").unwrap();
+        assert!(
+            install_pre_push_hook(&repo).is_err(),
+            "A hook not installed by gpg-import must not be replaced"
+        );
+    }
+
     #[test]
     fn configure_signing_sets_git_config() {
         let temp_dir = TempDir::new().unwrap();
@@ -81,6 +538,7 @@ mod tests {
             commit_sign: true,
             tag_sign: true,
             push_sign: true,
+            gpg_program: None,
         };
 
         let result = configure_signing(&repo, &cfg);
@@ -98,6 +556,65 @@ mod tests {
         assert_eq!(config.get_string("push.gpgsign").unwrap(), "if-asked");
     }
 
+    #[test]
+    fn configure_signing_sets_gpg_program() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = Repository::init(temp_dir.path()).unwrap();
+
+        let cfg = SigningConfig {
+            user_name: "batman".to_string(),
+            user_email: "batman@dc.com".to_string(),
+            key_id: "FDEFE8AB8796E127".to_string(),
+            commit_sign: true,
+            tag_sign: true,
+            push_sign: true,
+            gpg_program: Some("/opt/gnupg/bin/gpg2".to_string()),
+        };
+
+        let result = configure_signing(&repo, &cfg);
+        assert!(result.is_ok(), "Should configure signing");
+        assert_eq!(
+            effective_gpg_program(Some(&repo)).unwrap(),
+            Some("/opt/gnupg/bin/gpg2".to_string())
+        );
+    }
+
+    #[test]
+    fn signing_config_reports_origin() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = Repository::init(temp_dir.path()).unwrap();
+        let mut config = repo.config().unwrap();
+        config
+            .set_str("user.signingKey", "FDEFE8AB8796E127")
+            .unwrap();
+        config.set_bool("commit.gpgSign", true).unwrap();
+
+        let local: Vec<ConfigValue> = signing_config(Some(&repo))
+            .unwrap()
+            .into_iter()
+            .filter(|value| value.level == ConfigLevel::Local)
+            .collect();
+        assert_eq!(
+            local,
+            vec![
+                ConfigValue {
+                    key: "user.signingKey".to_string(),
+                    value: "FDEFE8AB8796E127".to_string(),
+                    level: ConfigLevel::Local,
+                },
+                ConfigValue {
+                    key: "commit.gpgSign".to_string(),
+                    value: "true".to_string(),
+                    level: ConfigLevel::Local,
+                },
+            ]
+        );
+        assert_eq!(
+            local[0].to_string(),
+            "user.signingKey:            FDEFE8AB8796E127 (local)"
+        );
+    }
+
     #[test]
     fn display_signing_config() {
         let cfg = SigningConfig {
@@ -107,6 +624,21 @@ mod tests {
             commit_sign: true,
             tag_sign: true,
             push_sign: true,
+            gpg_program: None,
+        };
+        insta::assert_snapshot!(cfg.to_string());
+    }
+
+    #[test]
+    fn display_signing_config_with_gpg_program() {
+        let cfg = SigningConfig {
+            user_name: "batman".to_string(),
+            user_email: "batman@dc.com".to_string(),
+            key_id: "FDEFE8AB8796E127".to_string(),
+            commit_sign: true,
+            tag_sign: true,
+            push_sign: true,
+            gpg_program: Some("/opt/gnupg/bin/gpg2".to_string()),
         };
         insta::assert_snapshot!(cfg.to_string());
     }
@@ -120,6 +652,7 @@ mod tests {
             commit_sign: true,
             tag_sign: true,
             push_sign: false,
+            gpg_program: None,
         };
         insta::assert_snapshot!(cfg.to_string());
     }
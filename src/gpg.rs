@@ -3,19 +3,23 @@ use base64::{engine::general_purpose, DecodeError, Engine as _};
 use chrono::{TimeZone, Utc};
 use nom::{
     branch::alt,
-    bytes::complete::{tag, take_until},
+    bytes::complete::{tag, take_till1, take_until},
     character::complete::not_line_ending,
-    error::Error,
-    sequence::separated_pair,
+    combinator::opt,
+    error::{Error, ErrorKind},
+    sequence::{preceded, separated_pair},
     AsChar, Finish, IResult, Parser,
 };
 use std::{
+    env,
+    ffi::OsStr,
     fmt::{self, Display},
     fs,
     io::Read,
-    path::Path,
+    path::{Path, PathBuf},
     process::Command,
     str::FromStr,
+    sync::OnceLock,
 };
 use std::{io::Write, process::Stdio};
 use thiserror::Error;
@@ -25,10 +29,32 @@ use thiserror::Error;
 pub struct GpgInfo {
     /// The GnuPG version
     pub version: String,
-    /// The version of libgcrypt used by GnuPG
-    pub libgcrypt: String,
+    /// The GnuPG version, parsed into its numeric components
+    pub semver: GpgVersion,
+    /// The version of libgcrypt used by GnuPG, not reported by gpg1
+    pub libgcrypt: Option<String>,
     /// The home directory, where configuration files are stored
-    pub home_dir: String,
+    pub home_dir: PathBuf,
+}
+
+impl GpgInfo {
+    /// The features used by gpg-import that this version of GnuPG supports
+    pub fn features(&self) -> GpgFeatures {
+        GpgFeatures::from(self.semver)
+    }
+
+    /// Ensures this version of GnuPG meets [`MINIMUM_GPG_VERSION`]. Older
+    /// versions (2.0 and gpg1) manage secret keys and passphrases without
+    /// the agent-based keygrip model that gpg-import relies on
+    pub fn ensure_supported(&self) -> Result<(), GpgError> {
+        if self.semver < MINIMUM_GPG_VERSION {
+            return Err(GpgError::UnsupportedVersion(
+                self.version.clone(),
+                MINIMUM_GPG_VERSION,
+            ));
+        }
+        Ok(())
+    }
 }
 
 impl FromStr for GpgInfo {
@@ -47,20 +73,19 @@ impl FromStr for GpgInfo {
 
 impl Display for GpgInfo {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        writeln!(
-            f,
-            "version: {} (libgcrypt: {})",
-            self.version, self.libgcrypt
-        )?;
-        writeln!(f, "homedir: {}", self.home_dir)?;
+        match &self.libgcrypt {
+            Some(libgcrypt) => writeln!(f, "version: {} (libgcrypt: {})", self.version, libgcrypt)?,
+            None => writeln!(f, "version: {}", self.version)?,
+        }
+        writeln!(f, "homedir: {}", self.home_dir.display())?;
         Ok(())
     }
 }
 
 fn parse_gpg_info(input: &str) -> IResult<&str, GpgInfo> {
-    let (i, version) = separated_pair(
+    let (i, (_, version)) = separated_pair(
         separated_pair(
-            tag("gpg"),
+            take_till1(|c| c == ' '),
             tag(" "),
             alt((tag("(GnuPG)"), tag("(GnuPG/MacGPG2)"))),
         ),
@@ -68,24 +93,246 @@ fn parse_gpg_info(input: &str) -> IResult<&str, GpgInfo> {
         not_line_ending,
     )
     .parse(input)?;
-    let (i, _) = take_until("libgcrypt")(i)?;
-    let (i, libgcrypt) = separated_pair(tag("libgcrypt"), tag(" "), not_line_ending).parse(i)?;
+    let semver = version
+        .parse::<GpgVersion>()
+        .map_err(|_| nom::Err::Error(Error::new(version, ErrorKind::Verify)))?;
+    let (i, libgcrypt) = opt(preceded(
+        take_until("libgcrypt"),
+        separated_pair(tag("libgcrypt"), tag(" "), not_line_ending),
+    ))
+    .parse(i)?;
     let (i, _) = take_until("Home: ")(i)?;
     let (i, home_dir) = separated_pair(tag("Home:"), tag(" "), not_line_ending).parse(i)?;
 
     Ok((
         i,
         GpgInfo {
-            version: version.1.into(),
-            libgcrypt: libgcrypt.1.into(),
-            home_dir: home_dir.1.into(),
+            version: version.into(),
+            semver,
+            libgcrypt: libgcrypt.map(|(_, libgcrypt)| libgcrypt.into()),
+            home_dir: PathBuf::from(home_dir.1),
         },
     ))
 }
 
+/// The oldest version of GnuPG supported by gpg-import
+pub const MINIMUM_GPG_VERSION: GpgVersion = GpgVersion::new(2, 1, 0);
+
+/// A GnuPG version, ordered by its numeric components
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct GpgVersion {
+    /// The major version
+    pub major: u32,
+    /// The minor version
+    pub minor: u32,
+    /// The patch version
+    pub patch: u32,
+}
+
+impl GpgVersion {
+    /// Creates a version from its numeric components
+    pub const fn new(major: u32, minor: u32, patch: u32) -> Self {
+        Self {
+            major,
+            minor,
+            patch,
+        }
+    }
+}
+
+impl FromStr for GpgVersion {
+    type Err = GpgError;
+
+    /// Parses a version such as `2.4.5`. Any suffix after the numeric
+    /// components (e.g. `2.5.0-beta123`) is ignored, as are missing minor
+    /// or patch components, which default to zero
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let numeric = s
+            .split(|c: char| !c.is_ascii_digit() && c != '.')
+            .next()
+            .unwrap_or_default();
+        let mut components = numeric.split('.').map(str::parse::<u32>);
+
+        let major = match components.next() {
+            Some(Ok(major)) => major,
+            _ => return Err(GpgError::InvalidVersion(s.to_string())),
+        };
+        let minor = components.next().and_then(Result::ok).unwrap_or_default();
+        let patch = components.next().and_then(Result::ok).unwrap_or_default();
+
+        Ok(Self::new(major, minor, patch))
+    }
+}
+
+impl Display for GpgVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// The GnuPG features relied upon by gpg-import, and whether a given
+/// version of GnuPG supports them
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GpgFeatures {
+    /// `--import-options show-only`, used to preview a key without
+    /// importing it (2.1.14)
+    pub import_show_only: bool,
+    /// `allow-loopback-pinentry` within gpg-agent.conf, and
+    /// `--pinentry-mode loopback` (2.1.0)
+    pub loopback_pinentry: bool,
+    /// `PRESET_PASSPHRASE` caching a passphrase against a keygrip, as
+    /// private keys are managed by the agent (2.1.0)
+    pub preset_passphrase: bool,
+    /// `--with-keygrip` reporting the keygrips the agent caches
+    /// passphrases against (2.1.0)
+    pub with_keygrip: bool,
+}
+
+impl From<GpgVersion> for GpgFeatures {
+    fn from(version: GpgVersion) -> Self {
+        let modern = version >= GpgVersion::new(2, 1, 0);
+        GpgFeatures {
+            import_show_only: version >= GpgVersion::new(2, 1, 14),
+            loopback_pinentry: modern,
+            preset_passphrase: modern,
+            with_keygrip: modern,
+        }
+    }
+}
+
+/// The GnuPG installation and keyring that gpg invocations target. The
+/// default resolves `gpg` from `PATH` and leaves gpg to pick its home
+/// directory from `GNUPGHOME` or `~/.gnupg`
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct GpgContext {
+    program: Option<PathBuf>,
+    home_dir: Option<PathBuf>,
+    version: OnceLock<GpgVersion>,
+}
+
+impl GpgContext {
+    /// Pins the GnuPG binary used by every gpg invocation, rather than
+    /// resolving `gpg` from `PATH`. Useful on hosts with multiple
+    /// installations (e.g. gpg1 alongside gpg2, or MacGPG)
+    pub fn with_program(mut self, program: Option<PathBuf>) -> Self {
+        self.program = program;
+        self.version = OnceLock::new();
+        self
+    }
+
+    /// Pins the home directory passed through `--homedir` to every gpg,
+    /// gpg-connect-agent and gpgconf invocation, targeting a specific
+    /// keyring rather than `GNUPGHOME` or `~/.gnupg`. Relative paths are
+    /// made absolute, as the agent and its sockets are tied to the path
+    pub fn with_home_dir(mut self, home_dir: Option<PathBuf>) -> Self {
+        self.home_dir = home_dir.map(|home_dir| std::path::absolute(&home_dir).unwrap_or(home_dir));
+        self
+    }
+
+    /// The GnuPG binary used for every gpg invocation, either pinned through
+    /// [`GpgContext::with_program`] or `gpg` from `PATH`
+    pub fn program(&self) -> PathBuf {
+        self.program.clone().unwrap_or_else(|| PathBuf::from("gpg"))
+    }
+
+    /// The GnuPG binary pinned through [`GpgContext::with_program`], if any
+    pub fn pinned_program(&self) -> Option<&Path> {
+        self.program.as_deref()
+    }
+
+    /// The home directory pinned through [`GpgContext::with_home_dir`], if any
+    pub fn pinned_home_dir(&self) -> Option<&Path> {
+        self.home_dir.as_deref()
+    }
+
+    /// The features of the GnuPG binary in use, detecting its version on
+    /// first use. [`detect_version`] records the version it detects, so
+    /// later calls don't run gpg again
+    pub fn features(&self) -> Result<GpgFeatures> {
+        let version = match self.version.get() {
+            Some(version) => *version,
+            None => detect_version(self)?.semver,
+        };
+        Ok(GpgFeatures::from(version))
+    }
+
+    /// Resolves a companion GnuPG tool (e.g. `gpg-connect-agent`) from the
+    /// same directory as a pinned GnuPG binary, so both belong to one
+    /// installation. Falls back to resolving the tool from `PATH`
+    fn companion_program(&self, name: &str) -> PathBuf {
+        self.program
+            .as_deref()
+            .and_then(resolve_program)
+            .and_then(|program| program.parent().map(|dir| dir.join(name)))
+            .filter(|companion| companion.is_file())
+            .unwrap_or_else(|| PathBuf::from(name))
+    }
+
+    /// Builds a `Command` for a GnuPG tool, passing the pinned home
+    /// directory as its leading `--homedir` option
+    fn tool_command(&self, program: impl AsRef<OsStr>) -> Command {
+        let mut cmd = gpg_command(program);
+        if let Some(home_dir) = &self.home_dir {
+            cmd.arg("--homedir").arg(home_dir);
+        }
+        cmd
+    }
+
+    /// Builds a `Command` for the GnuPG binary in use, see
+    /// [`GpgContext::program`]
+    fn gpg(&self) -> Command {
+        self.tool_command(self.program())
+    }
+
+    /// Builds a `Command` for the `gpg-connect-agent` belonging to the GnuPG
+    /// binary in use
+    fn gpg_connect_agent(&self) -> Command {
+        self.tool_command(self.companion_program("gpg-connect-agent"))
+    }
+
+    /// Builds a `Command` for the `gpgconf` belonging to the GnuPG binary in
+    /// use
+    fn gpgconf(&self) -> Command {
+        self.tool_command(self.companion_program("gpgconf"))
+    }
+
+    /// The home directory gpg uses when known without asking gpg itself:
+    /// the pinned home directory, then `GNUPGHOME`
+    fn effective_home_dir(&self) -> Option<PathBuf> {
+        self.home_dir.clone().or_else(|| {
+            env::var_os("GNUPGHOME")
+                .filter(|home_dir| !home_dir.is_empty())
+                .map(PathBuf::from)
+                .map(|home_dir| std::path::absolute(&home_dir).unwrap_or(home_dir))
+        })
+    }
+}
+
+/// Locates a program, returning the absolute path of the binary that would
+/// be executed. A bare name is searched for within `PATH` in the same way as
+/// a shell, while any other path is used as is. Symlinks aren't followed, so
+/// the path survives upgrades that repoint them (e.g. Homebrew's Cellar)
+pub fn locate_program(program: &Path) -> Option<PathBuf> {
+    let candidate = if program.components().count() > 1 {
+        Some(program.to_path_buf()).filter(|program| program.is_file())
+    } else {
+        env::split_paths(&env::var_os("PATH")?)
+            .map(|dir| dir.join(program))
+            .find(|candidate| candidate.is_file())
+    };
+    candidate.and_then(|candidate| std::path::absolute(&candidate).ok())
+}
+
+/// Resolves a program to the absolute, canonical path of the binary that
+/// would be executed, see [`locate_program`]. Two programs resolving to the
+/// same path are the same installation
+pub fn resolve_program(program: &Path) -> Option<PathBuf> {
+    locate_program(program)?.canonicalize().ok()
+}
+
 /// Builds a `Command` for the given GPG binary, pinned to the `C` locale so
 /// that any human-oriented output it produces is deterministic and untranslated
-fn gpg_command(program: &str) -> Command {
+fn gpg_command(program: impl AsRef<OsStr>) -> Command {
     let mut cmd = Command::new(program);
     cmd.env("LC_ALL", "C").env_remove("LANGUAGE");
     cmd
@@ -93,49 +340,639 @@ fn gpg_command(program: &str) -> Command {
 
 /// Inspects the OS for a GPG client and retrieves details about the
 /// currently installed version
-pub fn detect_version() -> Result<GpgInfo> {
-    let gpg_details = gpg_command("gpg").arg("--version").output()?;
+pub fn detect_version(ctx: &GpgContext) -> Result<GpgInfo> {
+    let gpg_details = ctx.gpg().arg("--version").output()?;
+
+    let output = String::from_utf8_lossy(&gpg_details.stdout);
+    let mut gpg_info = output.parse::<GpgInfo>()?;
+    let _ = ctx.version.set(gpg_info.semver);
 
-    let output = String::from_utf8(gpg_details.stdout)?;
-    let gpg_info = output.parse::<GpgInfo>()?;
+    // The Home: line is lossy for paths that aren't valid UTF-8, so prefer
+    // the exact path gpg was given, either pinned or through GNUPGHOME
+    if let Some(home_dir) = ctx.effective_home_dir() {
+        gpg_info.home_dir = home_dir;
+    }
 
     Ok(gpg_info)
 }
 
 /// Configure GPG with sensible defaults
-pub fn configure_defaults(home_dir: &str) -> Result<()> {
-    let path = Path::new(home_dir).join("gpg.conf");
-    fs::create_dir_all(home_dir)?;
-    fs::write(
-        path,
+pub fn configure_defaults(home_dir: &Path) -> Result<()> {
+    let path = home_dir.join("gpg.conf");
+    create_home_dir(home_dir)?;
+    write_private_file(
+        &path,
         b"use-agent
 pinentry-mode loopback",
     )?;
     Ok(())
 }
 
+/// Config files within the homedir that gpg-import writes, or that gpg and
+/// its agent read, which should only be accessible by their owner
+const HOME_DIR_CONF_FILES: [&str; 6] = [
+    "gpg.conf",
+    "gpg-agent.conf",
+    "common.conf",
+    "dirmngr.conf",
+    "scdaemon.conf",
+    "sshcontrol",
+];
+
+/// Keyrings of public keys, which are safe for other users to read
+const HOME_DIR_PUBLIC_FILES: [&str; 3] = ["pubring.kbx", "pubring.kbx~", "pubring.gpg"];
+
+/// A permission fixed, or a problem found, when securing the gpg homedir
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum HomeDirFinding {
+    /// The permissions of the homedir or one of its config files were
+    /// tightened
+    Fixed {
+        /// The homedir or config file
+        path: PathBuf,
+        /// The original permissions
+        from: u32,
+        /// The permissions it now has
+        to: u32,
+    },
+    /// A file or directory within the homedir is accessible by other users
+    Exposed {
+        /// The file or directory
+        path: PathBuf,
+        /// Its permissions
+        mode: u32,
+    },
+    /// A file or directory within the homedir is owned by another user
+    ForeignOwner {
+        /// The file or directory
+        path: PathBuf,
+        /// The uid of the owner
+        uid: u32,
+    },
+}
+
+impl Display for HomeDirFinding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HomeDirFinding::Fixed { path, from, to } => {
+                write!(f, "fixed:   {} ({from:04o} -> {to:04o})", path.display())
+            }
+            HomeDirFinding::Exposed { path, mode } => write!(
+                f,
+                "warning: {} is accessible by other users ({mode:04o})",
+                path.display()
+            ),
+            HomeDirFinding::ForeignOwner { path, uid } => write!(
+                f,
+                "warning: {} is owned by another user (uid {uid})",
+                path.display()
+            ),
+        }
+    }
+}
+
+/// Creates the homedir if needed and restricts it to 0700 and its config
+/// files to 0600, avoiding gpg's "unsafe permissions" warnings. Any other
+/// file that's accessible by, or owned by, another user is reported but
+/// left untouched
+pub fn secure_home_dir(home_dir: &Path) -> Result<Vec<HomeDirFinding>> {
+    create_home_dir(home_dir)?;
+
+    #[cfg(unix)]
+    {
+        secure_home_dir_unix(home_dir)
+    }
+
+    #[cfg(not(unix))]
+    {
+        Ok(Vec::new())
+    }
+}
+
+#[cfg(unix)]
+fn secure_home_dir_unix(home_dir: &Path) -> Result<Vec<HomeDirFinding>> {
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+    // SAFETY: geteuid has no preconditions and always succeeds
+    let uid = unsafe { libc::geteuid() };
+    let mut findings = Vec::new();
+
+    let mut fix = |path: &Path, metadata: &fs::Metadata, to: u32| -> Result<()> {
+        let from = metadata.permissions().mode() & 0o777;
+        if from & 0o077 != 0 {
+            fs::set_permissions(path, fs::Permissions::from_mode(to))?;
+            findings.push(HomeDirFinding::Fixed {
+                path: path.to_path_buf(),
+                from,
+                to,
+            });
+        }
+        Ok(())
+    };
+
+    fix(home_dir, &fs::metadata(home_dir)?, 0o700)?;
+    for name in HOME_DIR_CONF_FILES {
+        let path = home_dir.join(name);
+        if let Ok(metadata) = fs::symlink_metadata(&path) {
+            if metadata.is_file() {
+                fix(&path, &metadata, 0o600)?;
+            }
+        }
+    }
+
+    let owner = fs::metadata(home_dir)?.uid();
+    if owner != uid {
+        findings.push(HomeDirFinding::ForeignOwner {
+            path: home_dir.to_path_buf(),
+            uid: owner,
+        });
+    }
+
+    // The agent may remove lock files, sockets or temporary files while the
+    // homedir is walked, so anything that disappears is skipped
+    let mut pending = vec![home_dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound && dir != home_dir => continue,
+            Err(e) => return Err(e.into()),
+        };
+        for entry in entries {
+            let entry = entry?;
+            let path = entry.path();
+            let metadata = match entry.metadata() {
+                Ok(metadata) => metadata,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            };
+            if metadata.uid() != uid {
+                findings.push(HomeDirFinding::ForeignOwner {
+                    path: path.clone(),
+                    uid: metadata.uid(),
+                });
+            }
+
+            // Sockets are created by the agent, and only reachable through
+            // the homedir itself
+            let public = dir == home_dir
+                && HOME_DIR_PUBLIC_FILES
+                    .iter()
+                    .any(|name| entry.file_name() == *name);
+            let mode = metadata.permissions().mode() & 0o777;
+            if (metadata.is_file() || metadata.is_dir()) && !public && mode & 0o077 != 0 {
+                findings.push(HomeDirFinding::Exposed {
+                    path: path.clone(),
+                    mode,
+                });
+            }
+            if metadata.is_dir() {
+                pending.push(path);
+            }
+        }
+    }
+    Ok(findings)
+}
+
+/// Creates the homedir, and any missing parents, accessible only by the
+/// current user
+fn create_home_dir(home_dir: &Path) -> Result<()> {
+    let mut builder = fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        builder.mode(0o700);
+    }
+    builder.create(home_dir)?;
+    Ok(())
+}
+
+/// Options for writing a file that, if created, is only accessible by the
+/// current user
+fn private_file_options() -> fs::OpenOptions {
+    let mut options = fs::OpenOptions::new();
+    options.create(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options
+}
+
+/// Opens a file only accessible by the current user. The mode set on
+/// creation doesn't apply to an existing file (or the target of a symlink),
+/// so its permissions are restricted through the handle before anything is
+/// written to it
+fn open_private_file(options: &fs::OpenOptions, path: &Path) -> Result<fs::File> {
+    let file = options.open(path)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(fs::Permissions::from_mode(0o600))?;
+    }
+    Ok(file)
+}
+
+/// Writes a file only accessible by the current user
+pub(crate) fn write_private_file(path: &Path, contents: &[u8]) -> Result<()> {
+    open_private_file(private_file_options().write(true).truncate(true), path)?
+        .write_all(contents)?;
+    Ok(())
+}
+
+/// The default time, in seconds, a cached passphrase remains valid since
+/// it was last used
+pub const DEFAULT_CACHE_TTL: u64 = 21600;
+
+/// The default maximum time, in seconds, a passphrase remains cached
+pub const MAX_CACHE_TTL: u64 = 31536000;
+
+/// Options written to `gpg-agent.conf`. Preset and loopback passphrases are
+/// always allowed, as gpg-import depends upon both
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AgentConfig {
+    /// Seconds a cached passphrase remains valid since it was last used
+    pub default_cache_ttl: u64,
+    /// Seconds a passphrase remains cached, regardless of use
+    pub max_cache_ttl: u64,
+    /// Whether passphrases may be cached by an external password manager
+    pub allow_external_cache: bool,
+    /// A custom pinentry program, used whenever gpg isn't in loopback mode
+    pub pinentry_program: Option<PathBuf>,
+    /// Whether the agent also acts as an OpenSSH agent
+    pub enable_ssh_support: bool,
+}
+
+impl Default for AgentConfig {
+    fn default() -> Self {
+        Self {
+            default_cache_ttl: DEFAULT_CACHE_TTL,
+            max_cache_ttl: MAX_CACHE_TTL,
+            allow_external_cache: true,
+            pinentry_program: None,
+            enable_ssh_support: false,
+        }
+    }
+}
+
+impl AgentConfig {
+    /// Ensures the options are consistent and the pinentry program exists,
+    /// and can be written to a line of `gpg-agent.conf` as is, so a broken
+    /// `gpg-agent.conf` is never written
+    pub fn validate(&self) -> Result<(), GpgError> {
+        if self.default_cache_ttl > self.max_cache_ttl {
+            return Err(GpgError::InvalidCacheTtl(
+                self.default_cache_ttl,
+                self.max_cache_ttl,
+            ));
+        }
+        if let Some(pinentry_program) = &self.pinentry_program {
+            match pinentry_program.to_str() {
+                Some(path) if !path.contains(['\n', '\r']) => {}
+                _ => {
+                    return Err(GpgError::UnsupportedPinentryProgram(
+                        pinentry_program.clone(),
+                    ))
+                }
+            }
+            if !pinentry_program.is_absolute() || !pinentry_program.is_file() {
+                return Err(GpgError::InvalidPinentryProgram(pinentry_program.clone()));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Display for AgentConfig {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "default-cache-ttl {}", self.default_cache_ttl)?;
+        writeln!(f, "max-cache-ttl {}", self.max_cache_ttl)?;
+        if !self.allow_external_cache {
+            writeln!(f, "no-allow-external-cache")?;
+        }
+        if let Some(pinentry_program) = &self.pinentry_program {
+            writeln!(f, "pinentry-program {}", pinentry_program.display())?;
+        }
+        if self.enable_ssh_support {
+            writeln!(f, "enable-ssh-support")?;
+        }
+        writeln!(f, "allow-preset-passphrase")?;
+        write!(f, "allow-loopback-pinentry")
+    }
+}
+
 /// Configure the GPG agent with sensible defaults
-pub fn configure_agent_defaults(home_dir: &str) -> Result<()> {
-    let path = Path::new(home_dir).join("gpg-agent.conf");
-    fs::create_dir_all(home_dir)?;
-    fs::write(
-        path,
-        b"default-cache-ttl 21600
-max-cache-ttl 31536000
-allow-preset-passphrase
-allow-loopback-pinentry",
-    )?;
-    reload_agent()
+pub fn configure_agent_defaults(ctx: &GpgContext, home_dir: &Path) -> Result<AgentInfo> {
+    configure_agent(ctx, home_dir, &AgentConfig::default())
+}
+
+/// Configure the GPG agent with the given options, validating them before
+/// `gpg-agent.conf` is written, and reload the agent so they take effect
+pub fn configure_agent(
+    ctx: &GpgContext,
+    home_dir: &Path,
+    config: &AgentConfig,
+) -> Result<AgentInfo> {
+    config.validate()?;
+
+    let path = home_dir.join("gpg-agent.conf");
+    create_home_dir(home_dir)?;
+    write_private_file(&path, config.to_string().as_bytes())?;
+    reload_agent(ctx)
+}
+
+/// Permits the agent to offer a key to SSH clients by adding its keygrip to
+/// `sshcontrol`, re-enabling an entry disabled with `!`. Returns false if
+/// the keygrip was already listed and enabled
+pub fn add_ssh_control(home_dir: &Path, keygrip: &str) -> Result<bool> {
+    let path = home_dir.join("sshcontrol");
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e.into()),
+    };
+
+    // Entries are a keygrip, optionally prefixed by `!` when disabled and
+    // followed by a cache TTL and flags
+    let entry = |line: &str| {
+        line.split_whitespace()
+            .next()
+            .filter(|grip| grip.trim_start_matches('!').eq_ignore_ascii_case(keygrip))
+            .map(|grip| !grip.starts_with('!'))
+    };
+    let entries: Vec<bool> = contents.lines().filter_map(entry).collect();
+    if entries.contains(&true) {
+        return Ok(false);
+    }
+    if !entries.is_empty() {
+        let enabled: String = contents
+            .split_inclusive('\n')
+            .map(|line| match entry(line) {
+                Some(false) => line.trim_start().trim_start_matches('!').to_string(),
+                _ => line.to_string(),
+            })
+            .collect();
+        write_private_file(&path, enabled.as_bytes())?;
+        return Ok(true);
+    }
+
+    let mut file = open_private_file(private_file_options().append(true), &path)?;
+    if !contents.is_empty() && !contents.ends_with('\n') {
+        writeln!(file)?;
+    }
+    writeln!(file, "{keygrip} 0")?;
+    Ok(true)
+}
+
+/// The socket the agent listens on for SSH clients, to be exported as
+/// `SSH_AUTH_SOCK`
+pub fn ssh_auth_sock(ctx: &GpgContext) -> Result<PathBuf> {
+    let output = ctx
+        .gpgconf()
+        .args(["--list-dirs", "agent-ssh-socket"])
+        .output()?;
+    let socket = parse_gpgconf_dir(&output.stdout);
+    if !output.status.success() || socket.as_os_str().is_empty() {
+        bail!("gpgconf did not report an ssh agent socket");
+    }
+    Ok(socket)
+}
+
+/// Parses a directory or socket reported by `gpgconf --list-dirs`, which
+/// percent-escapes any `%`, `:` or `,` within it. Other bytes are kept as
+/// is, so paths that aren't valid UTF-8 survive
+fn parse_gpgconf_dir(stdout: &[u8]) -> PathBuf {
+    let escaped = stdout.trim_ascii();
+    let mut bytes = Vec::with_capacity(escaped.len());
+    let mut i = 0;
+    while i < escaped.len() {
+        let decoded = (escaped[i] == b'%')
+            .then(|| escaped.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match decoded {
+            Some(byte) => {
+                bytes.push(byte);
+                i += 3;
+            }
+            None => {
+                bytes.push(escaped[i]);
+                i += 1;
+            }
+        }
+    }
+    path_from_bytes(bytes)
+}
+
+#[cfg(unix)]
+fn path_from_bytes(bytes: Vec<u8>) -> PathBuf {
+    use std::os::unix::ffi::OsStringExt;
+    PathBuf::from(std::ffi::OsString::from_vec(bytes))
+}
+
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: Vec<u8>) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// The `GETINFO` requests answered by the agent to build an [`AgentInfo`]
+const AGENT_GETINFO: [&str; 3] = ["GETINFO version", "GETINFO pid", "GETINFO socket_name"];
+
+/// The agent options gpg-import relies on, each paired with a request the
+/// agent rejects as not supported while that option isn't in force. A bare
+/// `PRESET_PASSPHRASE` is refused before its (missing) arguments are parsed,
+/// so probing never caches anything
+const AGENT_OPTION_PROBES: [(&str, &str); 2] = [
+    ("allow-loopback-pinentry", "OPTION pinentry-mode=loopback"),
+    ("allow-preset-passphrase", "PRESET_PASSPHRASE"),
+];
+
+/// The code of `GPG_ERR_NOT_SUPPORTED`, within the low 16 bits of an `ERR`
+const GPG_ERR_NOT_SUPPORTED: u32 = 60;
+
+/// Details reported by a running gpg-agent via `GETINFO`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AgentInfo {
+    /// The version of the agent
+    pub version: String,
+    /// The process id of the agent
+    pub pid: u32,
+    /// The socket the agent is listening on
+    pub socket_name: PathBuf,
+}
+
+impl Display for AgentInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "version: {}", self.version)?;
+        writeln!(f, "pid:     {}", self.pid)?;
+        writeln!(f, "socket:  {}", self.socket_name.display())?;
+        Ok(())
+    }
+}
+
+/// Reloads the agent so a rewritten `gpg-agent.conf` takes effect, starting
+/// the agent first if it isn't running. Fails unless the agent acknowledges
+/// the reload with `allow-preset-passphrase` and `allow-loopback-pinentry`
+/// in force, returning the details of the agent that did
+pub fn reload_agent(ctx: &GpgContext) -> Result<AgentInfo> {
+    let launch = ctx.gpgconf().args(["--launch", "gpg-agent"]).output()?;
+    if !launch.status.success() {
+        bail!(
+            "failed to launch gpg-agent: {}",
+            String::from_utf8_lossy(&launch.stderr).trim()
+        );
+    }
+
+    let output = ctx
+        .gpg_connect_agent()
+        .arg("RELOADAGENT")
+        .args(AGENT_GETINFO)
+        .arg("/bye")
+        .stdin(Stdio::null())
+        .output()?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    if !output.status.success() {
+        bail!(
+            "failed to connect to gpg-agent: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    let mut agent = parse_reload_agent(&stdout)?;
+
+    // The agent that answered must be the one gpg itself will talk to,
+    // otherwise the reloaded options never apply to signing. Its reply is
+    // decoded lossily, so compare the same way and keep the exact path
+    let expected = ctx
+        .gpgconf()
+        .args(["--list-dirs", "agent-socket"])
+        .output()?;
+    let expected = parse_gpgconf_dir(&expected.stdout);
+    if agent.socket_name.to_string_lossy() != expected.to_string_lossy() {
+        bail!(
+            "gpg-agent is listening on {} rather than {}",
+            agent.socket_name.display(),
+            expected.display()
+        );
+    }
+    agent.socket_name = expected;
+
+    verify_agent_options(ctx)?;
+    Ok(agent)
 }
 
-fn reload_agent() -> Result<()> {
-    gpg_command("gpg-connect-agent")
-        .args(vec!["RELOADAGENT", "/bye"])
+/// Ensures the agent permits presetting passphrases and loopback pinentry,
+/// as these can be overridden (e.g. by `no-allow-loopback-pinentry` or a
+/// system wide `gpgconf.conf`) despite being written to `gpg-agent.conf`
+fn verify_agent_options(ctx: &GpgContext) -> Result<()> {
+    let output = ctx
+        .gpg_connect_agent()
+        .args(AGENT_OPTION_PROBES.map(|(_, request)| request))
+        .arg("/bye")
+        .stdin(Stdio::null())
         .output()?;
+    if !output.status.success() {
+        bail!(
+            "failed to connect to gpg-agent: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    parse_agent_option_probes(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// Parses the replies to [`AGENT_OPTION_PROBES`], failing on the first
+/// option the agent reports as not in force
+fn parse_agent_option_probes(output: &str) -> Result<()> {
+    let replies: Vec<&str> = output
+        .lines()
+        .filter(|line| *line == "OK" || line.starts_with("OK ") || line.starts_with("ERR "))
+        .collect();
+    if replies.len() != AGENT_OPTION_PROBES.len() {
+        bail!(
+            "gpg-agent answered {} of {} option probes",
+            replies.len(),
+            AGENT_OPTION_PROBES.len()
+        );
+    }
 
+    for ((option, _), reply) in AGENT_OPTION_PROBES.iter().zip(replies) {
+        let code = reply
+            .strip_prefix("ERR ")
+            .and_then(|err| err.split_whitespace().next())
+            .and_then(|code| code.parse::<u32>().ok());
+        if code.is_some_and(|code| code & 0xffff == GPG_ERR_NOT_SUPPORTED) {
+            bail!(GpgError::AgentOptionNotInForce(option.to_string()));
+        }
+    }
     Ok(())
 }
 
+/// Queries the details of a running agent, without starting one
+pub fn agent_info(ctx: &GpgContext) -> Result<AgentInfo> {
+    let output = ctx
+        .gpg_connect_agent()
+        .arg("--no-autostart")
+        .args(AGENT_GETINFO)
+        .arg("/bye")
+        .stdin(Stdio::null())
+        .output()?;
+    if !output.status.success() {
+        bail!(
+            "gpg-agent is not running: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    agent_info_from(&parse_agent_responses(&String::from_utf8_lossy(
+        &output.stdout,
+    ))?)
+}
+
+/// Parses the responses to `RELOADAGENT` followed by [`AGENT_GETINFO`]
+fn parse_reload_agent(output: &str) -> Result<AgentInfo> {
+    let responses = parse_agent_responses(output)?;
+    let Some((_reload, getinfo)) = responses.split_first() else {
+        bail!("gpg-agent did not acknowledge the reload");
+    };
+    agent_info_from(getinfo)
+}
+
+/// Splits the output of `gpg-connect-agent` into the data returned for
+/// each command. Each response is a (possibly empty) run of `D` data lines
+/// terminated by `OK` or `ERR`
+fn parse_agent_responses(output: &str) -> Result<Vec<String>> {
+    let mut responses = Vec::new();
+    let mut data = String::new();
+    for line in output.lines() {
+        if let Some(value) = line.strip_prefix("D ") {
+            data.push_str(value);
+        } else if line == "OK" || line.starts_with("OK ") {
+            responses.push(std::mem::take(&mut data));
+        } else if let Some(err) = line.strip_prefix("ERR ") {
+            bail!("gpg-agent rejected request {}: {err}", responses.len() + 1);
+        }
+    }
+    Ok(responses)
+}
+
+fn agent_info_from(responses: &[String]) -> Result<AgentInfo> {
+    let [version, pid, socket_name] = responses else {
+        bail!(
+            "gpg-agent answered {} of {} GETINFO requests",
+            responses.len(),
+            AGENT_GETINFO.len()
+        );
+    };
+    Ok(AgentInfo {
+        version: version.clone(),
+        pid: pid
+            .parse()
+            .map_err(|_| anyhow::anyhow!("gpg-agent reported an invalid pid: {pid}"))?,
+        socket_name: PathBuf::from(socket_name),
+    })
+}
+
 /// A GPG private key
 #[derive(Debug)]
 pub struct GpgPrivateKey {
@@ -158,7 +995,7 @@ impl GpgPrivateKey {
 }
 
 /// A user identity associated with a GPG key
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct GpgUid {
     /// The name portion of the user id
     pub name: String,
@@ -166,6 +1003,16 @@ pub struct GpgUid {
     pub email: String,
 }
 
+impl Display for GpgUid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.email.is_empty() {
+            write!(f, "{}", self.name)
+        } else {
+            write!(f, "{} <{}>", self.name, self.email)
+        }
+    }
+}
+
 /// Contains internal details of a GPG private key
 #[derive(Debug)]
 pub struct GpgKeyDetails {
@@ -210,6 +1057,41 @@ impl From<&str> for GpgCapabilities {
     }
 }
 
+/// A GPG public key, imported to verify the signatures of others
+#[derive(Debug)]
+pub struct GpgPublicKey {
+    /// The user identities associated with the public key
+    pub uids: Vec<GpgUid>,
+    /// A fingerprint used for verification of the public key
+    pub fingerprint: String,
+    /// An 8 digit hexadecimal identifier for the public key
+    pub key_id: String,
+    /// The date for when the public key will expire
+    pub expiration_date: Option<i64>,
+}
+
+impl Display for GpgPublicKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for uid in &self.uids {
+            if uid.email.is_empty() {
+                writeln!(f, "user:           {}", uid.name)?;
+            } else {
+                writeln!(f, "user:           {} <{}>", uid.name, uid.email)?;
+            }
+        }
+        writeln!(f, "fingerprint:    {}", self.fingerprint)?;
+        writeln!(f, "key_id:         {}", self.key_id)?;
+        if let Some(expiration_date) = self.expiration_date {
+            writeln!(
+                f,
+                "expires_on:     {}",
+                format_expiration_in_days(expiration_date)
+            )?;
+        }
+        Ok(())
+    }
+}
+
 impl FromStr for GpgPrivateKey {
     type Err = GpgError;
 
@@ -269,7 +1151,7 @@ fn format_timestamp(secs_since_epoch: i64) -> String {
     dt.to_rfc2822()
 }
 
-fn format_expiration_in_days(secs_since_epoch: i64) -> String {
+pub(crate) fn format_expiration_in_days(secs_since_epoch: i64) -> String {
     let expires_on = Utc.timestamp_opt(secs_since_epoch, 0).unwrap();
     let now = Utc::now();
     let days_until_expiry = (expires_on - now).num_days();
@@ -299,9 +1181,27 @@ fn parse_status_import(status: &str) -> Option<String> {
         .map(String::from)
 }
 
-/// Field indices (0-based, after splitting a colon-format line on `:`),
-/// verified against real `gpg --with-colons --with-keygrip` output.
-mod colon_field {
+/// Extracts the fingerprint of every successfully imported key from
+/// `--status-file` records, in the order they were imported. A key can be
+/// reported more than once (e.g. when listed twice within the input), but
+/// only appears once here
+fn parse_status_imports(status: &str) -> Vec<String> {
+    let mut fingerprints: Vec<String> = Vec::new();
+    for fingerprint in status
+        .lines()
+        .filter_map(|line| line.strip_prefix("[GNUPG:] IMPORT_OK "))
+        .filter_map(|rest| rest.split_whitespace().nth(1))
+    {
+        if !fingerprints.iter().any(|f| f == fingerprint) {
+            fingerprints.push(fingerprint.to_string());
+        }
+    }
+    fingerprints
+}
+
+/// Field indices (0-based, after splitting a colon-format line on `:`),
+/// verified against real `gpg --with-colons --with-keygrip` output.
+mod colon_field {
     pub const KEY_ID: usize = 4;
     pub const CREATION_DATE: usize = 5;
     pub const EXPIRATION_DATE: usize = 6;
@@ -392,6 +1292,67 @@ fn parse_gpg_key_details(input: &str) -> Result<GpgPrivateKey, GpgError> {
     })
 }
 
+/// Parses gpg `--with-colons` output listing one or more public keys into
+/// [`GpgPublicKey`]s. Subkey (`sub`) records, and the `fpr` records that
+/// follow them, are skipped as only the primary key identifies the owner
+fn parse_gpg_public_keys(input: &str) -> Result<Vec<GpgPublicKey>, GpgError> {
+    let mut keys: Vec<GpgPublicKey> = Vec::new();
+    let mut in_subkey = false;
+
+    for (line_no, line) in input.lines().enumerate() {
+        let fields: Vec<&str> = line.split(':').collect();
+
+        match fields.first().copied().unwrap_or_default() {
+            "pub" => {
+                in_subkey = false;
+                keys.push(GpgPublicKey {
+                    uids: Vec::new(),
+                    fingerprint: String::new(),
+                    key_id: field(&fields, colon_field::KEY_ID, line_no, line)?.to_string(),
+                    expiration_date: parse_optional_timestamp(
+                        field(&fields, colon_field::EXPIRATION_DATE, line_no, line)?,
+                        line_no,
+                        line,
+                    )?,
+                });
+            }
+            "sub" => in_subkey = true,
+            "fpr" if !in_subkey => {
+                keys.last_mut()
+                    .ok_or_else(|| GpgError::MalformedKeyRecord(line_no + 1, line.to_string()))?
+                    .fingerprint =
+                    field(&fields, colon_field::RECORD_VALUE, line_no, line)?.to_string();
+            }
+            "uid" => {
+                let uid = parse_uid(
+                    field(&fields, colon_field::RECORD_VALUE, line_no, line)?,
+                    line_no,
+                    line,
+                )?;
+                keys.last_mut()
+                    .ok_or_else(|| GpgError::MalformedKeyRecord(line_no + 1, line.to_string()))?
+                    .uids
+                    .push(uid);
+            }
+            _ => {}
+        }
+    }
+
+    if keys.is_empty() {
+        return Err(GpgError::InvalidGpgKeyData(
+            "missing pub record".to_string(),
+        ));
+    }
+    if let Some(key) = keys.iter().find(|key| key.fingerprint.is_empty()) {
+        return Err(GpgError::InvalidGpgKeyData(format!(
+            "public key {} is missing its fingerprint (no matching fpr record)",
+            key.key_id
+        )));
+    }
+
+    Ok(keys)
+}
+
 /// Ensures a `sec`/`ssb` record was followed by its `fpr` and `grp` records;
 /// without them the fingerprint/keygrip are silently empty, which surfaces
 /// as a confusing failure much later (e.g. presetting the passphrase for an
@@ -526,6 +1487,57 @@ pub enum GpgError {
     /// The specified fingerprint was not found in the key
     #[error("fingerprint not found in key: {0}")]
     FingerprintNotFound(String),
+
+    /// The GnuPG version could not be parsed
+    #[error("invalid gpg version: {0}")]
+    InvalidVersion(String),
+
+    /// The trust level is outside of the supported 1-5 range
+    #[error("invalid trust level {0}; expected a value between 1 and 5")]
+    InvalidTrustLevel(u8),
+
+    /// The trust level is neither a number (1-5) nor a recognised name
+    #[error("invalid trust level '{0}'; expected 1-5 or one of undefined, never, marginal, full, ultimate")]
+    InvalidTrustLevelValue(String),
+
+    /// A key trust wasn't provided as `FINGERPRINT=LEVEL`
+    #[error(
+        "invalid key trust '{0}'; expected FINGERPRINT=LEVEL with a 40 or 64 hex digit fingerprint"
+    )]
+    InvalidKeyTrust(String),
+
+    /// The trustdb didn't reflect the trust level after assigning it
+    #[error("trust level {1} was not applied to key {0}")]
+    TrustLevelNotApplied(String, u8),
+
+    /// The installed GnuPG is older than the minimum supported version
+    #[error("gpg {0} is not supported; gpg-import requires GnuPG {1} or later")]
+    UnsupportedVersion(String, GpgVersion),
+
+    /// Secret key material was found where only public keys are expected
+    #[error("public key data contains secret key material")]
+    UnexpectedSecretKey,
+
+    /// The default cache TTL exceeds the maximum cache TTL
+    #[error("default cache ttl {0}s exceeds the max cache ttl {1}s")]
+    InvalidCacheTtl(u64, u64),
+
+    /// The pinentry program isn't an absolute path to an existing file
+    #[error("pinentry program '{}' is not an absolute path to an existing file", .0.display())]
+    InvalidPinentryProgram(PathBuf),
+
+    /// The pinentry program's path can't be written to `gpg-agent.conf`
+    #[error(
+        "pinentry program '{}' is not valid UTF-8 or contains a line break, so can't be \
+         written to gpg-agent.conf",
+        .0.display()
+    )]
+    UnsupportedPinentryProgram(PathBuf),
+
+    /// An agent option gpg-import relies on is disabled, despite being
+    /// written to `gpg-agent.conf`
+    #[error("gpg-agent is running without {0}, which is required to preset passphrases")]
+    AgentOptionNotInForce(String),
 }
 
 /// Detects the key format and returns the raw key bytes.
@@ -552,14 +1564,15 @@ fn decode_key_input(key: &str) -> Result<Vec<u8>> {
 
 /// Previews a GPG private key without importing it.
 /// Returns key details by parsing the key data without adding it to the keyring.
-pub fn preview_key(key: &str) -> Result<GpgPrivateKey> {
+pub fn preview_key(ctx: &GpgContext, key: &str) -> Result<GpgPrivateKey> {
     let decoded = decode_key_input(key)?;
 
     let temp_dir = tempfile::tempdir()?;
     let key_path = temp_dir.path().join("key.asc");
     fs::write(&key_path, &decoded)?;
 
-    let gpg_preview = gpg_command("gpg")
+    let gpg_preview = ctx
+        .gpg()
         .args([
             "--import-options",
             "show-only",
@@ -581,18 +1594,91 @@ pub fn preview_key(key: &str) -> Result<GpgPrivateKey> {
     Ok(key_details)
 }
 
-/// Attempts to import a GPG private key
-pub fn import_secret_key(key: &str) -> Result<String> {
+/// Previews a GPG private key without importing it into the keyring in use,
+/// for versions of GnuPG without `--import-options show-only` (see
+/// [`GpgFeatures::import_show_only`]). The key is instead imported into a
+/// throwaway home directory, which is discarded along with its agent
+pub fn preview_key_isolated(ctx: &GpgContext, key: &str) -> Result<GpgPrivateKey> {
     let decoded = decode_key_input(key)?;
 
+    let home_dir = tempfile::tempdir()?;
+    let key_path = home_dir.path().join("key.asc");
+    fs::write(&key_path, &decoded)?;
+
+    // The throwaway home directory replaces any pinned one, so the helpers
+    // passing --homedir aren't used
+    let gpg_import = gpg_command(ctx.program())
+        .arg("--homedir")
+        .arg(home_dir.path())
+        .args(["--batch", "--import"])
+        .arg(&key_path)
+        .output()?;
+
+    let gpg_preview = if gpg_import.status.success() {
+        Some(
+            gpg_command(ctx.program())
+                .arg("--homedir")
+                .arg(home_dir.path())
+                .args([
+                    "--batch",
+                    "--with-colons",
+                    "--with-keygrip",
+                    "--list-secret-keys",
+                    "--fixed-list-mode",
+                ])
+                .output()?,
+        )
+    } else {
+        None
+    };
+
+    // Importing a secret key starts an agent for the throwaway home directory
+    let _ = gpg_command(ctx.companion_program("gpgconf"))
+        .arg("--homedir")
+        .arg(home_dir.path())
+        .args(["--kill", "gpg-agent"])
+        .output();
+
+    let Some(gpg_preview) = gpg_preview else {
+        let stderr = String::from_utf8_lossy(&gpg_import.stderr);
+        bail!(GpgError::InvalidGpgKeyData(stderr.trim().to_string()));
+    };
+
+    let output = String::from_utf8(gpg_preview.stdout)?;
+    let key_details = output.parse::<GpgPrivateKey>()?;
+
+    Ok(key_details)
+}
+
+/// The result of running `gpg --import` over decoded key material
+struct ImportOutcome {
+    success: bool,
+    stderr: String,
+    status_records: String,
+}
+
+impl ImportOutcome {
+    /// Describes why the import failed, preferring gpg's own explanation on
+    /// stderr over its raw status records
+    fn error(&self) -> GpgError {
+        let detail = self.stderr.trim();
+        let detail = if detail.is_empty() {
+            self.status_records.trim()
+        } else {
+            detail
+        };
+        GpgError::InvalidGpgKeyData(detail.to_string())
+    }
+}
+
+fn run_import(ctx: &GpgContext, decoded: &[u8]) -> Result<ImportOutcome> {
     let status_file = tempfile::NamedTempFile::new()?;
-    let status_path = status_file
-        .path()
-        .to_str()
-        .ok_or_else(|| anyhow::anyhow!("status file path is not valid UTF-8"))?;
 
-    let mut gpg_import = gpg_command("gpg")
-        .args(["--status-file", status_path, "--import", "--batch", "--yes"])
+    let mut gpg_import = ctx
+        .gpg()
+        .arg("--status-file")
+        .arg(status_file.path())
+        .args(["--import", "--batch", "--yes"])
         .stdin(Stdio::piped())
         .stdout(Stdio::null())
         .stderr(Stdio::piped())
@@ -602,35 +1688,256 @@ pub fn import_secret_key(key: &str) -> Result<String> {
         .stdin
         .take()
         .ok_or_else(|| anyhow::anyhow!("failed to open stdin for gpg process"))?
-        .write_all(&decoded)?;
+        .write_all(decoded)?;
 
-    let mut stderr = String::default();
+    // Both mention the keyring by path, which may not be valid UTF-8
+    let mut stderr = Vec::new();
     gpg_import
         .stderr
         .take()
         .ok_or_else(|| anyhow::anyhow!("failed to open stderr for gpg process"))?
-        .read_to_string(&mut stderr)?;
+        .read_to_end(&mut stderr)?;
 
     let status = gpg_import.wait()?;
-    let status_records = fs::read_to_string(status_file.path())?;
+    let status_records = fs::read(status_file.path())?;
+
+    Ok(ImportOutcome {
+        success: status.success(),
+        stderr: String::from_utf8_lossy(&stderr).into_owned(),
+        status_records: String::from_utf8_lossy(&status_records).into_owned(),
+    })
+}
 
-    match (status.success(), parse_status_import(&status_records)) {
+/// Attempts to import a GPG private key
+pub fn import_secret_key(ctx: &GpgContext, key: &str) -> Result<String> {
+    let decoded = decode_key_input(key)?;
+    let outcome = run_import(ctx, &decoded)?;
+
+    match (
+        outcome.success,
+        parse_status_import(&outcome.status_records),
+    ) {
         (true, Some(fingerprint)) => Ok(fingerprint),
-        _ => {
-            let detail = stderr.trim();
-            let detail = if detail.is_empty() {
-                status_records.trim()
-            } else {
-                detail
-            };
-            Err(GpgError::InvalidGpgKeyData(detail.to_string()).into())
+        _ => Err(outcome.error().into()),
+    }
+}
+
+/// Attempts to import one or more GPG public keys, such as those of
+/// colleagues whose signatures need verifying. Returns the fingerprint of
+/// every key imported, including those already within the keyring
+pub fn import_public_keys(ctx: &GpgContext, key: &str) -> Result<Vec<String>> {
+    import_public_key_data(ctx, &decode_key_input(key)?)
+}
+
+/// Attempts to import one or more GPG public keys from raw key data, which
+/// may be binary (e.g. as served by a Web Key Directory) or ASCII armored
+pub fn import_public_key_data(ctx: &GpgContext, data: &[u8]) -> Result<Vec<String>> {
+    // gpg imports whatever it's given, so secret keys are rejected up front
+    // rather than silently added to the keyring alongside the public keys
+    preview_public_key_data(ctx, data)?;
+    let outcome = run_import(ctx, data)?;
+
+    let fingerprints = parse_status_imports(&outcome.status_records);
+    if !outcome.success || fingerprints.is_empty() {
+        bail!(outcome.error());
+    }
+    Ok(fingerprints)
+}
+
+/// Previews one or more GPG public keys without importing them
+pub fn preview_public_keys(ctx: &GpgContext, key: &str) -> Result<Vec<GpgPublicKey>> {
+    preview_public_key_data(ctx, &decode_key_input(key)?)
+}
+
+/// Previews one or more GPG public keys from raw key data, which may be
+/// binary or ASCII armored, without importing them. Fails if the data
+/// contains any secret key material
+pub fn preview_public_key_data(ctx: &GpgContext, data: &[u8]) -> Result<Vec<GpgPublicKey>> {
+    if !ctx.features()?.import_show_only {
+        return preview_public_key_data_isolated(ctx, data);
+    }
+
+    let temp_dir = tempfile::tempdir()?;
+    let key_path = temp_dir.path().join("key.asc");
+    fs::write(&key_path, data)?;
+
+    let gpg_preview = ctx
+        .gpg()
+        .args(["--import-options", "show-only", "--with-colons", "--import"])
+        .arg(&key_path)
+        .output()?;
+
+    if !gpg_preview.status.success() {
+        let stderr = String::from_utf8_lossy(&gpg_preview.stderr);
+        bail!(GpgError::InvalidGpgKeyData(stderr.trim().to_string()));
+    }
+
+    parse_public_key_preview(&String::from_utf8(gpg_preview.stdout)?)
+}
+
+/// Previews one or more GPG public keys without importing them into the
+/// keyring in use, for versions of GnuPG without `--import-options
+/// show-only` (see [`GpgFeatures::import_show_only`]). The keys are instead
+/// imported into a throwaway home directory, which is then discarded
+pub fn preview_public_key_data_isolated(
+    ctx: &GpgContext,
+    data: &[u8],
+) -> Result<Vec<GpgPublicKey>> {
+    let home_dir = tempfile::tempdir()?;
+    let key_path = home_dir.path().join("key.asc");
+    fs::write(&key_path, data)?;
+
+    // The throwaway home directory replaces any pinned one, so the helpers
+    // passing --homedir aren't used
+    let gpg_isolated = || {
+        let mut cmd = gpg_command(ctx.program());
+        cmd.arg("--homedir").arg(home_dir.path());
+        cmd
+    };
+    let gpg_import = gpg_isolated()
+        .args(["--batch", "--import"])
+        .arg(&key_path)
+        .output()?;
+
+    let mut listing = String::new();
+    if gpg_import.status.success() {
+        for list in ["--list-keys", "--list-secret-keys"] {
+            let output = gpg_isolated()
+                .args(["--batch", "--with-colons", "--fixed-list-mode", list])
+                .output()?;
+            listing.push_str(&String::from_utf8(output.stdout)?);
         }
     }
+
+    // Importing a secret key starts an agent for the throwaway home directory
+    let _ = gpg_command(ctx.companion_program("gpgconf"))
+        .arg("--homedir")
+        .arg(home_dir.path())
+        .args(["--kill", "gpg-agent"])
+        .output();
+
+    if !gpg_import.status.success() {
+        let stderr = String::from_utf8_lossy(&gpg_import.stderr);
+        bail!(GpgError::InvalidGpgKeyData(stderr.trim().to_string()));
+    }
+    parse_public_key_preview(&listing)
+}
+
+/// Narrows public key data down to the given keys, so data served by a
+/// remote host can't smuggle extra keys into the keyring alongside the one
+/// requested. The data is imported into a throwaway home directory, from
+/// which only the given fingerprints are exported (binary)
+pub fn select_public_key_data(
+    ctx: &GpgContext,
+    data: &[u8],
+    fingerprints: &[String],
+) -> Result<Vec<u8>> {
+    let home_dir = tempfile::tempdir()?;
+    let key_path = home_dir.path().join("key.asc");
+    fs::write(&key_path, data)?;
+
+    // The throwaway home directory replaces any pinned one, so the helpers
+    // passing --homedir aren't used
+    let gpg_isolated = || {
+        let mut cmd = gpg_command(ctx.program());
+        cmd.arg("--homedir").arg(home_dir.path());
+        cmd
+    };
+    let gpg_import = gpg_isolated()
+        .args(["--batch", "--import"])
+        .arg(&key_path)
+        .output()?;
+    if !gpg_import.status.success() {
+        let stderr = String::from_utf8_lossy(&gpg_import.stderr);
+        bail!(GpgError::InvalidGpgKeyData(stderr.trim().to_string()));
+    }
+
+    let export = gpg_isolated()
+        .args(["--batch", "--export"])
+        .args(fingerprints)
+        .output()?;
+    if !export.status.success() || export.stdout.is_empty() {
+        bail!(GpgError::KeyNotFound(fingerprints.join(", ")));
+    }
+    Ok(export.stdout)
+}
+
+/// Parses the public keys from a colon listing, rejecting any secret keys
+fn parse_public_key_preview(output: &str) -> Result<Vec<GpgPublicKey>> {
+    if output
+        .lines()
+        .any(|line| line.starts_with("sec:") || line.starts_with("ssb:"))
+    {
+        bail!(GpgError::UnexpectedSecretKey);
+    }
+    Ok(parse_gpg_public_keys(output)?)
+}
+
+/// Extracts details for a given GPG public key within the keyring
+pub fn extract_public_key_info(ctx: &GpgContext, fingerprint: &str) -> Result<GpgPublicKey> {
+    let gpg_key_details = ctx
+        .gpg()
+        .args([
+            "--batch",
+            "--with-colons",
+            "--list-keys",
+            "--fixed-list-mode",
+            fingerprint,
+        ])
+        .output()?;
+
+    if !gpg_key_details.status.success() {
+        bail!(GpgError::KeyNotFound(fingerprint.to_string()));
+    }
+
+    let output = String::from_utf8(gpg_key_details.stdout)?;
+    parse_gpg_public_keys(&output)?
+        .into_iter()
+        .next()
+        .ok_or_else(|| GpgError::KeyNotFound(fingerprint.to_string()).into())
+}
+
+/// Exports the public part of a key within the keyring as ASCII armor,
+/// e.g. for publishing to a keyserver or registering with a forge. A
+/// fingerprint suffixed with `!` exports only that subkey alongside its
+/// primary key. A minimal export strips all signatures except the most
+/// recent self-signature on each uid
+pub fn export_public_key(ctx: &GpgContext, fingerprint: &str, minimal: bool) -> Result<String> {
+    let mut command = ctx.gpg();
+    command.args(["--batch", "--armor"]);
+    if minimal {
+        command.args(["--export-options", "export-minimal"]);
+    }
+    let export = command.args(["--export", fingerprint]).output()?;
+
+    if !export.status.success() || export.stdout.is_empty() {
+        bail!(GpgError::KeyNotFound(fingerprint.to_string()));
+    }
+
+    Ok(String::from_utf8(export.stdout)?)
+}
+
+/// Exports a key (or subkey) within the keyring as an SSH public key, as
+/// used within an allowed signers file. The key is exported explicitly, so
+/// doesn't need to be authentication capable
+pub fn export_ssh_key(ctx: &GpgContext, fingerprint: &str) -> Result<String> {
+    let export = ctx
+        .gpg()
+        .args(["--batch", "--export-ssh-key"])
+        .arg(format!("{}!", fingerprint.trim_end_matches('!')))
+        .output()?;
+
+    if !export.status.success() || export.stdout.is_empty() {
+        bail!(GpgError::KeyNotFound(fingerprint.to_string()));
+    }
+
+    Ok(String::from_utf8(export.stdout)?.trim().to_string())
 }
 
 /// Extracts internal details for a given GPG private key and verifies its validity
-pub fn extract_key_info(key_id: &str) -> Result<GpgPrivateKey> {
-    let gpg_key_details = gpg_command("gpg")
+pub fn extract_key_info(ctx: &GpgContext, key_id: &str) -> Result<GpgPrivateKey> {
+    let gpg_key_details = ctx
+        .gpg()
         .args(vec![
             "--batch",
             "--with-colons",
@@ -669,8 +1976,9 @@ pub fn extract_key_info(key_id: &str) -> Result<GpgPrivateKey> {
 
 /// Presets the passphrase for a given keygrip, ensuring it is cached for any
 /// subsequent signing request
-pub fn preset_passphrase(keygrip: &str, passphrase: &str) -> Result<()> {
-    let set_passphrase = gpg_command("gpg-connect-agent")
+pub fn preset_passphrase(ctx: &GpgContext, keygrip: &str, passphrase: &str) -> Result<()> {
+    let set_passphrase = ctx
+        .gpg_connect_agent()
         .stdin(Stdio::piped())
         .stdout(Stdio::null())
         .spawn()?;
@@ -691,117 +1999,965 @@ pub fn preset_passphrase(keygrip: &str, passphrase: &str) -> Result<()> {
     Ok(())
 }
 
-/// Assign a trust level to an imported key
-pub fn assign_trust_level(key_id: &str, trust_level: u8) -> Result<()> {
-    let set_trust = gpg_command("gpg")
-        .args(vec![
-            "--batch",
-            "--no-tty",
-            "--command-fd",
-            "0",
-            "--edit-key",
-            key_id,
-            "trust",
-            "quit",
-        ])
+/// Changes the passphrase protecting the secret key with the given keygrip,
+/// answering the agent's inquiries in loopback mode. An empty new passphrase
+/// removes the protection entirely. Both passphrases are answered from
+/// private files, removed once done, so they're kept out of the process
+/// arguments and sent exactly, rather than being parsed as part of the
+/// gpg-connect-agent script
+pub fn change_passphrase(
+    ctx: &GpgContext,
+    keygrip: &str,
+    passphrase: &str,
+    new_passphrase: &str,
+) -> Result<()> {
+    let inquiries = tempfile::tempdir()?;
+    let inquiry_file = |name: &str, contents: &str| -> Result<String> {
+        let path = inquiries.path().join(name);
+        write_private_file(&path, contents.as_bytes())?;
+        match path.to_str() {
+            Some(path) if !path.contains(['\n', '\r']) => Ok(path.to_string()),
+            _ => bail!("unsupported temporary path: {}", path.display()),
+        }
+    };
+    let passphrase_file = inquiry_file("passphrase", passphrase)?;
+    let new_passphrase_file = inquiry_file("new_passphrase", new_passphrase)?;
+
+    let mut change = ctx
+        .gpg_connect_agent()
         .stdin(Stdio::piped())
-        .stdout(Stdio::null())
+        .stdout(Stdio::piped())
         .spawn()?;
 
-    set_trust
+    change
         .stdin
-        .as_ref()
-        .ok_or_else(|| anyhow::anyhow!("failed to open stdin for gpg process"))?
-        .write_all(format!("{trust_level}\ny\n").as_bytes())?;
-    set_trust.wait_with_output()?;
+        .take()
+        .ok_or_else(|| anyhow::anyhow!("failed to open stdin for gpg-connect-agent"))?
+        .write_all(
+            format!(
+                "OPTION pinentry-mode=loopback
+/definqfile PASSPHRASE {passphrase_file}
+/definqfile NEW_PASSPHRASE {new_passphrase_file}
+PASSWD {keygrip}
+/bye
+"
+            )
+            .as_bytes(),
+        )?;
+
+    let output = change.wait_with_output()?;
+    let stdout = String::from_utf8(output.stdout)?;
+    if let Some(err) = stdout.lines().find_map(|line| line.strip_prefix("ERR ")) {
+        bail!("gpg-agent failed to change the passphrase of {keygrip}: {err}");
+    }
     Ok(())
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// Whether the agent holds a secret key protected by a passphrase, as
+/// reported by the protection field of `KEYINFO`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyProtection {
+    /// The key is protected by a passphrase
+    Protected,
+    /// The key is stored in the clear, so presetting a passphrase is useless
+    Unprotected,
+    /// The agent couldn't determine the protection, e.g. a smartcard stub
+    Unknown,
+}
 
-    use chrono::Duration;
-    use std::ffi::OsStr;
-    use tempfile::TempDir;
+/// Details of a secret key held by the agent, identified by its keygrip
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AgentKeyInfo {
+    /// The keygrip of the secret key
+    pub keygrip: String,
+    /// Whether a passphrase for the key is currently cached
+    pub cached: bool,
+    /// Whether the key is protected by a passphrase
+    pub protection: KeyProtection,
+}
 
-    #[test]
-    fn gpg_command_pins_c_locale() {
-        let cmd = gpg_command("gpg");
-        let envs: Vec<_> = cmd.get_envs().collect();
+/// Lists every secret key held by the agent via `KEYINFO --list`
+pub fn agent_key_info(ctx: &GpgContext) -> Result<Vec<AgentKeyInfo>> {
+    let output = ctx
+        .gpg_connect_agent()
+        .args(["KEYINFO --list", "/bye"])
+        .stdin(Stdio::null())
+        .output()?;
+    parse_keyinfo(&String::from_utf8(output.stdout)?)
+}
 
-        assert!(envs.contains(&(OsStr::new("LC_ALL"), Some(OsStr::new("C")))));
-        assert!(envs.contains(&(OsStr::new("LANGUAGE"), None)));
-    }
+/// Parses the status lines returned by `KEYINFO`, of the form
+/// `S KEYINFO <keygrip> <type> <serialno> <idstr> <cached> <protection> ...`
+fn parse_keyinfo(output: &str) -> Result<Vec<AgentKeyInfo>> {
+    let mut keys = Vec::new();
+    for line in output.lines() {
+        if let Some(err) = line.strip_prefix("ERR ") {
+            bail!("gpg-agent failed to list keys: {err}");
+        }
+        let Some(record) = line.strip_prefix("S KEYINFO ") else {
+            continue;
+        };
 
-    #[test]
-    fn parse_status_import_extracts_fingerprint() {
-        let status = "[GNUPG:] KEY_CONSIDERED BEEA4CDB4B0A80CBABB99B45FDEFE8AB8796E127 0
-[GNUPG:] IMPORT_OK 17 BEEA4CDB4B0A80CBABB99B45FDEFE8AB8796E127
-[GNUPG:] IMPORTED FDEFE8AB8796E127 batman <batman@dc.com>
-[GNUPG:] IMPORT_RES 1 0 1 0 0 0 0 0 0 0 1 0 0 0 0";
+        let fields: Vec<&str> = record.split_whitespace().collect();
+        let (Some(keygrip), Some(cached), Some(protection)) =
+            (fields.first(), fields.get(4), fields.get(5))
+        else {
+            bail!("malformed KEYINFO record: {line}");
+        };
 
-        let result = parse_status_import(status);
-        assert_eq!(
-            result,
-            Some("BEEA4CDB4B0A80CBABB99B45FDEFE8AB8796E127".to_string())
-        );
+        keys.push(AgentKeyInfo {
+            keygrip: keygrip.to_string(),
+            cached: *cached == "1",
+            protection: match *protection {
+                "P" => KeyProtection::Protected,
+                "C" => KeyProtection::Unprotected,
+                _ => KeyProtection::Unknown,
+            },
+        });
     }
+    Ok(keys)
+}
 
-    #[test]
-    fn parse_status_import_ignores_localised_noise() {
-        // Regression: previously anchored on gettext-translated "gpg: key " stderr
-        // prose; status-fd records are locale-stable regardless of surrounding output.
-        let status = "gpg: Schlüssel BEEA4CDB4B0A80CB: geheimer Schlüssel importiert
-[GNUPG:] IMPORT_OK 17 BEEA4CDB4B0A80CBABB99B45FDEFE8AB8796E127";
+/// A level of trust in the owner of a key, as presented by `gpg --edit-key`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TrustLevel {
+    /// I don't know or won't say
+    Undefined,
+    /// I do NOT trust
+    Never,
+    /// I trust marginally
+    Marginal,
+    /// I trust fully
+    Full,
+    /// I trust ultimately
+    Ultimate,
+}
 
-        let result = parse_status_import(status);
-        assert_eq!(
-            result,
-            Some("BEEA4CDB4B0A80CBABB99B45FDEFE8AB8796E127".to_string())
-        );
+impl TrustLevel {
+    /// The numeric trust level (1-5), as presented by `gpg --edit-key`
+    pub fn trust_db_value(&self) -> u8 {
+        match self {
+            TrustLevel::Undefined => 1,
+            TrustLevel::Never => 2,
+            TrustLevel::Marginal => 3,
+            TrustLevel::Full => 4,
+            TrustLevel::Ultimate => 5,
+        }
     }
+}
 
-    #[test]
-    fn parse_status_import_returns_none_without_import_ok() {
-        let status = "[GNUPG:] NODATA 1
-[GNUPG:] IMPORT_RES 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0";
+impl FromStr for TrustLevel {
+    type Err = GpgError;
 
-        assert_eq!(parse_status_import(status), None);
+    /// Parses a numeric trust level (1-5) or its name (e.g. `ultimate`)
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "1" | "undefined" => Ok(TrustLevel::Undefined),
+            "2" | "never" => Ok(TrustLevel::Never),
+            "3" | "marginal" => Ok(TrustLevel::Marginal),
+            "4" | "full" => Ok(TrustLevel::Full),
+            "5" | "ultimate" => Ok(TrustLevel::Ultimate),
+            _ => Err(GpgError::InvalidTrustLevelValue(s.to_string())),
+        }
     }
+}
 
-    #[test]
-    fn parse_status_import_returns_none_for_empty_input() {
-        assert_eq!(parse_status_import(""), None);
-    }
+/// A level of trust to assign to a specific key, identified by its
+/// fingerprint. Parsed from `FINGERPRINT=LEVEL`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeyTrust {
+    /// The fingerprint of the key to trust
+    pub fingerprint: String,
+    /// The level of trust to assign
+    pub trust_level: TrustLevel,
+}
 
-    #[test]
-    fn parse_gpg_version_output() {
-        let gpg_output = "gpg (GnuPG) 2.4.5
-libgcrypt 1.10.3
-Copyright (C) 2024 g10 Code GmbH
-License GNU GPL-3.0-or-later <https://gnu.org/licenses/gpl.html>
-This is free software: you are free to change and redistribute it.
-There is NO WARRANTY, to the extent permitted by law.
+impl FromStr for KeyTrust {
+    type Err = GpgError;
 
-Home: /home/user/.gnupg
-Supported algorithms:
-Pubkey: RSA, ELG, DSA, ECDH, ECDSA, EDDSA";
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((fingerprint, level)) = s.split_once('=') else {
+            return Err(GpgError::InvalidKeyTrust(s.to_string()));
+        };
 
-        let result = gpg_output.parse::<GpgInfo>();
-        assert!(result.is_ok(), "Should parse GPG version output");
+        let fingerprint: String = fingerprint
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect::<String>()
+            .to_uppercase();
+        // Ownertrust is keyed by the full fingerprint of a v4 (40 hex digits)
+        // or v5 (64 hex digits) key, never a key id
+        if !matches!(fingerprint.len(), 40 | 64)
+            || !fingerprint.chars().all(|c| c.is_ascii_hexdigit())
+        {
+            return Err(GpgError::InvalidKeyTrust(s.to_string()));
+        }
 
-        let info = result.unwrap();
-        assert_eq!(info.version, "2.4.5");
-        assert_eq!(info.libgcrypt, "1.10.3");
-        assert_eq!(info.home_dir, "/home/user/.gnupg");
+        Ok(KeyTrust {
+            fingerprint,
+            trust_level: level.trim().parse()?,
+        })
     }
+}
+
+/// Maps a trust level (1-5), as presented by `gpg --edit-key`, onto the
+/// ownertrust value stored in the trustdb: 1 (undefined) through to 5
+/// (ultimate) are stored as 2 through to 6
+fn ownertrust_value(trust_level: u8) -> Result<u8, GpgError> {
+    match trust_level {
+        1..=5 => Ok(trust_level + 1),
+        _ => Err(GpgError::InvalidTrustLevel(trust_level)),
+    }
+}
+
+/// Extracts the ownertrust value for a fingerprint from
+/// `--export-ownertrust` output, e.g.:
+///
+/// ```text
+/// # List of assigned trustvalues, created Mon 01 Jan 2024 00:00:00 UTC
+/// BEEA4CDB4B0A80CBABB99B45FDEFE8AB8796E127:6:
+/// ```
+fn parse_ownertrust(output: &str, fingerprint: &str) -> Option<u8> {
+    output
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| line.split_once(':'))
+        .find(|(fpr, _)| fpr.eq_ignore_ascii_case(fingerprint))
+        .and_then(|(_, rest)| rest.split(':').next())
+        .and_then(|value| value.parse::<u8>().ok())
+        // Upper bits carry flags (e.g. disabled) rather than the trust itself
+        .map(|value| value & 0x0f)
+}
+
+/// Reads the ownertrust value assigned to a key, as stored in the trustdb.
+/// Returns `None` if no ownertrust has been assigned
+pub fn read_ownertrust(ctx: &GpgContext, fingerprint: &str) -> Result<Option<u8>> {
+    let export = ctx
+        .gpg()
+        .args(["--batch", "--export-ownertrust"])
+        .output()?;
+
+    if !export.status.success() {
+        let stderr = String::from_utf8_lossy(&export.stderr);
+        bail!("failed to export ownertrust: {}", stderr.trim());
+    }
+
+    let output = String::from_utf8(export.stdout)?;
+    Ok(parse_ownertrust(&output, fingerprint))
+}
+
+/// Assign a trust level (1-5) to an imported key by importing a generated
+/// ownertrust record, then confirming the trustdb reflects it. Unlike
+/// scripting `--edit-key`, this doesn't depend on gpg's interactive prompts,
+/// which differ between versions and locales
+pub fn assign_trust_level(ctx: &GpgContext, fingerprint: &str, trust_level: u8) -> Result<()> {
+    let ownertrust = ownertrust_value(trust_level)?;
+
+    let mut set_trust = ctx
+        .gpg()
+        .args(["--batch", "--import-ownertrust"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    set_trust
+        .stdin
+        .take()
+        .ok_or_else(|| anyhow::anyhow!("failed to open stdin for gpg process"))?
+        .write_all(format!("{fingerprint}:{ownertrust}:\n").as_bytes())?;
+
+    let output = set_trust.wait_with_output()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        bail!("failed to import ownertrust: {}", stderr.trim());
+    }
+
+    if read_ownertrust(ctx, fingerprint)? != Some(ownertrust) {
+        bail!(GpgError::TrustLevelNotApplied(
+            fingerprint.to_string(),
+            trust_level
+        ));
+    }
+
+    Ok(())
+}
+
+/// The outcome of verifying a signature, as reported by gpg
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SignatureStatus {
+    /// A good signature from a valid key
+    Good,
+    /// A good signature that has since expired
+    ExpiredSignature,
+    /// A good signature from a key that has since expired
+    ExpiredKey,
+    /// A good signature from a key that has been revoked
+    RevokedKey,
+    /// The signature doesn't match the signed data
+    Bad,
+    /// The signing key isn't within the keyring
+    MissingKey,
+    /// The signature couldn't be checked for any other reason
+    Error,
+}
+
+impl Display for SignatureStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SignatureStatus::Good => write!(f, "good"),
+            SignatureStatus::ExpiredSignature => write!(f, "expired signature"),
+            SignatureStatus::ExpiredKey => write!(f, "expired key"),
+            SignatureStatus::RevokedKey => write!(f, "revoked key"),
+            SignatureStatus::Bad => write!(f, "bad"),
+            SignatureStatus::MissingKey => write!(f, "missing key"),
+            SignatureStatus::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// Details of a verified signature, parsed from gpg's status output
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GpgSignature {
+    /// The outcome of the verification
+    pub status: SignatureStatus,
+    /// The long key id of the signing key
+    pub key_id: Option<String>,
+    /// The fingerprint of the (sub)key that made the signature
+    pub fingerprint: Option<String>,
+    /// The fingerprint of the primary key of the signing key
+    pub primary_fingerprint: Option<String>,
+    /// The primary uid of the signing key
+    pub uid: Option<String>,
+    /// The validity of the signing key within the web of trust, e.g. `full`
+    pub validity: Option<String>,
+}
+
+impl GpgSignature {
+    /// Determines if the signature was made by the given (sub)key or by any
+    /// subkey of the given primary key
+    pub fn signed_by(&self, fingerprint: &str) -> bool {
+        [&self.fingerprint, &self.primary_fingerprint]
+            .into_iter()
+            .flatten()
+            .any(|signer| signer.eq_ignore_ascii_case(fingerprint))
+    }
+}
+
+/// Parses the `--status-fd` output of `gpg --verify`, e.g.:
+///
+/// ```text
+/// [GNUPG:] GOODSIG B8527C5AED483BE3 batman <batman@dc.com>
+/// [GNUPG:] VALIDSIG 24DA...3BE3 2024-01-01 1704067200 0 4 0 1 10 00 24DA...3BE3
+/// [GNUPG:] TRUST_ULTIMATE 0 pgp
+/// ```
+fn parse_verify_status(status: &str) -> GpgSignature {
+    let mut signature = GpgSignature {
+        status: SignatureStatus::Error,
+        key_id: None,
+        fingerprint: None,
+        primary_fingerprint: None,
+        uid: None,
+        validity: None,
+    };
+
+    for line in status.lines() {
+        let Some(record) = line.strip_prefix("[GNUPG:] ") else {
+            continue;
+        };
+        let (keyword, args) = record.split_once(' ').unwrap_or((record, ""));
+        let mut fields = args.split(' ');
+
+        let status = match keyword {
+            "GOODSIG" => Some(SignatureStatus::Good),
+            "EXPSIG" => Some(SignatureStatus::ExpiredSignature),
+            "EXPKEYSIG" => Some(SignatureStatus::ExpiredKey),
+            "REVKEYSIG" => Some(SignatureStatus::RevokedKey),
+            "BADSIG" => Some(SignatureStatus::Bad),
+            "ERRSIG" => Some(SignatureStatus::Error),
+            "NO_PUBKEY" => Some(SignatureStatus::MissingKey),
+            _ => None,
+        };
+
+        match (keyword, status) {
+            // NO_PUBKEY accompanies ERRSIG, and is the more specific cause
+            ("ERRSIG", Some(_)) if signature.status == SignatureStatus::MissingKey => {
+                signature.key_id = fields.next().map(str::to_string);
+            }
+            (_, Some(status)) => {
+                signature.status = status;
+                signature.key_id = fields.next().map(str::to_string);
+                let uid = fields.collect::<Vec<_>>().join(" ");
+                if !uid.is_empty() && status != SignatureStatus::Error {
+                    signature.uid = Some(uid);
+                }
+            }
+            ("VALIDSIG", None) => {
+                signature.fingerprint = fields.next().map(str::to_string);
+                signature.primary_fingerprint = fields.nth(8).map(str::to_string);
+            }
+            (trust, None) if trust.starts_with("TRUST_") => {
+                signature.validity = Some(match &trust["TRUST_".len()..] {
+                    "FULLY" => "full".to_string(),
+                    level => level.to_lowercase(),
+                });
+            }
+            _ => {}
+        }
+    }
+
+    signature
+}
+
+/// Verifies a detached signature over the given data against the keyring
+pub fn verify_detached_signature(
+    ctx: &GpgContext,
+    signature: &[u8],
+    data: &[u8],
+) -> Result<GpgSignature> {
+    let temp_dir = tempfile::tempdir()?;
+    let signature_path = temp_dir.path().join("data.sig");
+    let data_path = temp_dir.path().join("data");
+    fs::write(&signature_path, signature)?;
+    fs::write(&data_path, data)?;
+
+    let verify = ctx
+        .gpg()
+        .args(["--batch", "--status-fd", "1", "--verify"])
+        .arg(&signature_path)
+        .arg(&data_path)
+        .output()?;
+
+    let status = String::from_utf8_lossy(&verify.stdout);
+    Ok(parse_verify_status(&status))
+}
+
+/// The kind of signature produced when signing a file
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SignatureKind {
+    /// An ASCII armored detached signature, written to `<file>.asc`
+    ArmoredDetached,
+    /// A binary detached signature, written to `<file>.sig`
+    BinaryDetached,
+    /// A cleartext signed copy of the file (e.g. a checksums file), written
+    /// to `<file>.asc`
+    Clearsigned,
+}
+
+impl SignatureKind {
+    fn extension(&self) -> &'static str {
+        match self {
+            SignatureKind::ArmoredDetached | SignatureKind::Clearsigned => "asc",
+            SignatureKind::BinaryDetached => "sig",
+        }
+    }
+}
+
+/// The path a signature of the given kind is written to, alongside the file
+pub fn signature_path(path: &Path, kind: SignatureKind) -> PathBuf {
+    let mut signature_path = path.as_os_str().to_owned();
+    signature_path.push(".");
+    signature_path.push(kind.extension());
+    PathBuf::from(signature_path)
+}
+
+/// Signs a file with the given key, replacing any existing signature, and
+/// returns the path of the signature. The key may be suffixed with `!` to
+/// sign with that exact (sub)key. Relies on the passphrase having already
+/// been preset within the agent, as no pinentry is available
+pub fn sign_file(
+    ctx: &GpgContext,
+    path: &Path,
+    local_user: &str,
+    kind: SignatureKind,
+) -> Result<PathBuf> {
+    let output_path = signature_path(path, kind);
+
+    let mut command = ctx.gpg();
+    command.args(["--batch", "--yes", "--local-user", local_user, "--output"]);
+    command.arg(&output_path);
+    match kind {
+        SignatureKind::ArmoredDetached => command.args(["--armor", "--detach-sign"]),
+        SignatureKind::BinaryDetached => command.arg("--detach-sign"),
+        SignatureKind::Clearsigned => command.arg("--clearsign"),
+    };
+    let sign = command.arg(path).output()?;
+
+    if !sign.status.success() {
+        let stderr = String::from_utf8_lossy(&sign.stderr);
+        bail!("failed to sign {}: {}", path.display(), stderr.trim());
+    }
+
+    Ok(output_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+    use tempfile::TempDir;
+
+    #[test]
+    fn parse_keyinfo_protection_and_cache() {
+        let output = "S KEYINFO 77D457694F393281152ADDA3A7829E93C21C0869 D - - - P - - -
+S KEYINFO 91830C93DDF5FDF1190FED2EF17590EF63A963DA D - - 1 C - - -
+S KEYINFO 346B78A6CCD91F41BFC16384B1E4C37926EEF8FF T D2760001240103040006 OPENPGP.1 - - - - -
+OK
+";
+        assert_eq!(
+            parse_keyinfo(output).unwrap(),
+            vec![
+                AgentKeyInfo {
+                    keygrip: "77D457694F393281152ADDA3A7829E93C21C0869".to_string(),
+                    cached: false,
+                    protection: KeyProtection::Protected,
+                },
+                AgentKeyInfo {
+                    keygrip: "91830C93DDF5FDF1190FED2EF17590EF63A963DA".to_string(),
+                    cached: true,
+                    protection: KeyProtection::Unprotected,
+                },
+                AgentKeyInfo {
+                    keygrip: "346B78A6CCD91F41BFC16384B1E4C37926EEF8FF".to_string(),
+                    cached: false,
+                    protection: KeyProtection::Unknown,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_keyinfo_agent_error() {
+        assert!(parse_keyinfo("ERR 67108891 Not found <GPG Agent>\n").is_err());
+    }
+
+    #[test]
+    fn parse_verify_status_good_signature() {
+        let status = "[GNUPG:] NEWSIG
+[GNUPG:] KEY_CONSIDERED 24DA69B1615F5F3C6A9C3A60B8527C5AED483BE3 0
+[GNUPG:] SIG_ID 6wqMDBLrsmvR3pqzLuoI0MbUB/A 2024-01-01 1704067200
+[GNUPG:] GOODSIG 9C3A60B8527C5AED batman <batman@dc.com>
+[GNUPG:] VALIDSIG 1C4E2A3F9D8B7C6A5E4F3A2B9C3A60B8527C5AED 2024-01-01 1704067200 0 4 0 1 10 00 24DA69B1615F5F3C6A9C3A60B8527C5AED483BE3
+[GNUPG:] TRUST_FULLY 0 pgp
+";
+        assert_eq!(
+            parse_verify_status(status),
+            GpgSignature {
+                status: SignatureStatus::Good,
+                key_id: Some("9C3A60B8527C5AED".to_string()),
+                fingerprint: Some("1C4E2A3F9D8B7C6A5E4F3A2B9C3A60B8527C5AED".to_string()),
+                primary_fingerprint: Some("24DA69B1615F5F3C6A9C3A60B8527C5AED483BE3".to_string()),
+                uid: Some("batman <batman@dc.com>".to_string()),
+                validity: Some("full".to_string()),
+            }
+        );
+    }
+
+    #[test]
+    fn parse_verify_status_missing_key() {
+        let status = "[GNUPG:] NEWSIG
+[GNUPG:] ERRSIG B8527C5AED483BE3 1 10 00 1704067200 9 -
+[GNUPG:] NO_PUBKEY B8527C5AED483BE3
+";
+        let signature = parse_verify_status(status);
+        assert_eq!(signature.status, SignatureStatus::MissingKey);
+        assert_eq!(signature.key_id.as_deref(), Some("B8527C5AED483BE3"));
+        assert_eq!(signature.uid, None);
+    }
+
+    #[test]
+    fn parse_verify_status_bad_signature() {
+        let status = "[GNUPG:] BADSIG B8527C5AED483BE3 batman <batman@dc.com>\n";
+        let signature = parse_verify_status(status);
+        assert_eq!(signature.status, SignatureStatus::Bad);
+        assert_eq!(signature.fingerprint, None);
+    }
+
+    #[test]
+    fn signature_signed_by_subkey_or_primary_key() {
+        let signature = GpgSignature {
+            status: SignatureStatus::Good,
+            key_id: None,
+            fingerprint: Some("SUBKEYFPR".to_string()),
+            primary_fingerprint: Some("PRIMARYFPR".to_string()),
+            uid: None,
+            validity: None,
+        };
+        assert!(signature.signed_by("subkeyfpr"));
+        assert!(signature.signed_by("PRIMARYFPR"));
+        assert!(!signature.signed_by("OTHERFPR"));
+    }
+
+    #[test]
+    fn gpg_command_pins_c_locale() {
+        let cmd = gpg_command("gpg");
+        let envs: Vec<_> = cmd.get_envs().collect();
+
+        assert!(envs.contains(&(OsStr::new("LC_ALL"), Some(OsStr::new("C")))));
+        assert!(envs.contains(&(OsStr::new("LANGUAGE"), None)));
+    }
+
+    #[test]
+    fn gpg_command_uses_pinned_program() {
+        let pinned = GpgContext::default()
+            .with_program(Some(PathBuf::from("/opt/gnupg/bin/gpg2")))
+            .gpg();
+        let default = GpgContext::default().gpg();
+
+        assert_eq!(pinned.get_program(), OsStr::new("/opt/gnupg/bin/gpg2"));
+        assert_eq!(default.get_program(), OsStr::new("gpg"));
+    }
+
+    #[test]
+    fn gpg_command_passes_pinned_home_dir() {
+        let home_dir = std::path::absolute("gnupg").unwrap();
+        let pinned = GpgContext::default()
+            .with_home_dir(Some(PathBuf::from("gnupg")))
+            .gpgconf();
+        let default = GpgContext::default().gpgconf();
+
+        assert_eq!(
+            pinned.get_args().collect::<Vec<_>>(),
+            vec![OsStr::new("--homedir"), home_dir.as_os_str()]
+        );
+        assert_eq!(default.get_args().count(), 0);
+    }
+
+    #[test]
+    fn features_reuse_detected_version() {
+        let ctx = GpgContext::default().with_program(Some(PathBuf::from("/does/not/exist/gpg")));
+        assert!(ctx.features().is_err());
+
+        ctx.version.set(GpgVersion::new(2, 1, 13)).unwrap();
+        assert_eq!(
+            ctx.features().unwrap(),
+            GpgFeatures::from(GpgVersion::new(2, 1, 13))
+        );
+        assert!(ctx
+            .with_program(Some(PathBuf::from("/does/not/exist/gpg2")))
+            .features()
+            .is_err());
+    }
+
+    #[test]
+    fn companion_program_resolves_alongside_pinned_program() {
+        let temp_dir = TempDir::new().unwrap();
+        let gpg = temp_dir.path().join("gpg2");
+        let connect_agent = temp_dir.path().join("gpg-connect-agent");
+        fs::write(&gpg, "").unwrap();
+        fs::write(&connect_agent, "").unwrap();
+
+        let ctx = GpgContext::default().with_program(Some(gpg));
+        let companion = ctx.companion_program("gpg-connect-agent");
+        let missing = ctx.companion_program("gpgconf");
+
+        assert_eq!(companion, connect_agent.canonicalize().unwrap());
+        assert_eq!(missing, PathBuf::from("gpgconf"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn locate_program_keeps_symlinks() {
+        let temp_dir = TempDir::new().unwrap();
+        let cellar = temp_dir.path().join("gpg-2.4.5");
+        let link = temp_dir.path().join("gpg");
+        fs::write(&cellar, "").unwrap();
+        std::os::unix::fs::symlink(&cellar, &link).unwrap();
+
+        assert_eq!(locate_program(&link), Some(link.clone()));
+        assert_eq!(resolve_program(&link), Some(cellar.canonicalize().unwrap()));
+    }
+
+    #[test]
+    fn resolve_program_searches_path_for_bare_names() {
+        let resolved = resolve_program(Path::new("sh"));
+        assert!(resolved.is_some_and(|path| path.is_absolute()));
+        assert_eq!(resolve_program(Path::new("no-such-gpg-binary")), None);
+    }
+
+    #[test]
+    fn parse_status_import_extracts_fingerprint() {
+        let status = "[GNUPG:] KEY_CONSIDERED BEEA4CDB4B0A80CBABB99B45FDEFE8AB8796E127 0
+[GNUPG:] IMPORT_OK 17 BEEA4CDB4B0A80CBABB99B45FDEFE8AB8796E127
+[GNUPG:] IMPORTED FDEFE8AB8796E127 batman <batman@dc.com>
+[GNUPG:] IMPORT_RES 1 0 1 0 0 0 0 0 0 0 1 0 0 0 0";
+
+        let result = parse_status_import(status);
+        assert_eq!(
+            result,
+            Some("BEEA4CDB4B0A80CBABB99B45FDEFE8AB8796E127".to_string())
+        );
+    }
+
+    #[test]
+    fn parse_status_import_ignores_localised_noise() {
+        // Regression: previously anchored on gettext-translated "gpg: key " stderr
+        // prose; status-fd records are locale-stable regardless of surrounding output.
+        let status = "gpg: Schlüssel BEEA4CDB4B0A80CB: geheimer Schlüssel importiert
+[GNUPG:] IMPORT_OK 17 BEEA4CDB4B0A80CBABB99B45FDEFE8AB8796E127";
+
+        let result = parse_status_import(status);
+        assert_eq!(
+            result,
+            Some("BEEA4CDB4B0A80CBABB99B45FDEFE8AB8796E127".to_string())
+        );
+    }
+
+    #[test]
+    fn parse_status_import_returns_none_without_import_ok() {
+        let status = "[GNUPG:] NODATA 1
+[GNUPG:] IMPORT_RES 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0";
+
+        assert_eq!(parse_status_import(status), None);
+    }
+
+    #[test]
+    fn parse_status_import_returns_none_for_empty_input() {
+        assert_eq!(parse_status_import(""), None);
+    }
+
+    #[test]
+    fn parse_status_imports_extracts_every_fingerprint() {
+        let status = "[GNUPG:] IMPORT_OK 1 BEEA4CDB4B0A80CBABB99B45FDEFE8AB8796E127
+[GNUPG:] IMPORTED FDEFE8AB8796E127 batman <batman@dc.com>
+[GNUPG:] IMPORT_OK 0 53C53C910B205E504F69EEA3CA953C1735BEEB77
+[GNUPG:] IMPORT_OK 0 BEEA4CDB4B0A80CBABB99B45FDEFE8AB8796E127
+[GNUPG:] IMPORT_RES 2 0 1 0 1 0 0 0 0 0 0 0 0 0 0";
+
+        assert_eq!(
+            parse_status_imports(status),
+            vec![
+                "BEEA4CDB4B0A80CBABB99B45FDEFE8AB8796E127".to_string(),
+                "53C53C910B205E504F69EEA3CA953C1735BEEB77".to_string(),
+            ]
+        );
+        assert!(parse_status_imports("[GNUPG:] NODATA 1").is_empty());
+    }
+
+    #[test]
+    fn parse_multiple_public_keys() {
+        let gpg_colon_format =
+            "pub:-:4096:1:FDEFE8AB8796E127:1700000000:1900000000::-:::scESC:::::23::0:
+fpr:::::::::BEEA4CDB4B0A80CBABB99B45FDEFE8AB8796E127:
+uid:-::::1700000000::0E9C7598797E7F7A380A72A58B9B7FA28160AB06::batman <batman@dc.com>::::::::::0:
+sub:-:4096:1:BE6663F6A323FBE8:1700000000::::::e:::::23:
+fpr:::::::::F36BE03211AF1D3CE26D8B3ABE6663F6A323FBE8:
+pub:-:4096:1:CA953C1735BEEB77:1700000000:::-:::scSC:::::23::0:
+fpr:::::::::53C53C910B205E504F69EEA3CA953C1735BEEB77:
+uid:-::::1700000000::96652C75728C60697573C3570C362BED95E6544::robin <robin@dc.com>::::::::::0:
+uid:-::::1700000000::16652C75728C60697573C3570C362BED95E6545::dick grayson::::::::::0:";
+
+        let result = parse_gpg_public_keys(gpg_colon_format);
+        assert!(result.is_ok(), "Should parse multiple public keys");
+
+        let keys = result.unwrap();
+        assert_eq!(keys.len(), 2);
+        assert_eq!(
+            keys[0].fingerprint, "BEEA4CDB4B0A80CBABB99B45FDEFE8AB8796E127",
+            "A subkey fingerprint must not replace the primary key's"
+        );
+        assert_eq!(keys[0].expiration_date, Some(1900000000));
+        assert_eq!(keys[0].uids[0].email, "batman@dc.com");
+        assert_eq!(keys[1].key_id, "CA953C1735BEEB77");
+        assert_eq!(keys[1].uids.len(), 2);
+        assert_eq!(keys[1].uids[1].name, "dick grayson");
+    }
+
+    #[test]
+    fn parse_public_key_missing_fpr_fails() {
+        let result = parse_gpg_public_keys(
+            "pub:-:4096:1:FDEFE8AB8796E127:1700000000:::-:::scESC:::::23::0:
+uid:-::::1700000000::0E9C7598797E7F7A380A72A58B9B7FA28160AB06::batman <batman@dc.com>::::::::::0:",
+        );
+        assert!(matches!(result, Err(GpgError::InvalidGpgKeyData(_))));
+        assert!(matches!(
+            parse_gpg_public_keys(""),
+            Err(GpgError::InvalidGpgKeyData(_))
+        ));
+    }
+
+    #[test]
+    fn ownertrust_value_offsets_trust_level() {
+        assert_eq!(ownertrust_value(1), Ok(2));
+        assert_eq!(ownertrust_value(5), Ok(6));
+        assert_eq!(ownertrust_value(0), Err(GpgError::InvalidTrustLevel(0)));
+        assert_eq!(ownertrust_value(6), Err(GpgError::InvalidTrustLevel(6)));
+    }
+
+    #[test]
+    fn parse_trust_level_by_number_or_name() {
+        assert_eq!("5".parse::<TrustLevel>(), Ok(TrustLevel::Ultimate));
+        assert_eq!("Marginal".parse::<TrustLevel>(), Ok(TrustLevel::Marginal));
+        assert_eq!(
+            "6".parse::<TrustLevel>(),
+            Err(GpgError::InvalidTrustLevelValue("6".to_string()))
+        );
+    }
+
+    #[test]
+    fn parse_key_trust() {
+        let result = "beea 4cdb 4b0a 80cb abb9  9b45 fdef e8ab 8796 e127=4".parse::<KeyTrust>();
+        assert_eq!(
+            result,
+            Ok(KeyTrust {
+                fingerprint: "BEEA4CDB4B0A80CBABB99B45FDEFE8AB8796E127".to_string(),
+                trust_level: TrustLevel::Full,
+            })
+        );
+
+        assert!(matches!(
+            "BEEA4CDB4B0A80CB".parse::<KeyTrust>(),
+            Err(GpgError::InvalidKeyTrust(_))
+        ));
+        assert!(matches!(
+            "batman@dc.com=5".parse::<KeyTrust>(),
+            Err(GpgError::InvalidKeyTrust(_))
+        ));
+        assert!(matches!(
+            "ABCD=5".parse::<KeyTrust>(),
+            Err(GpgError::InvalidKeyTrust(_))
+        ));
+        assert!(matches!(
+            "BEEA4CDB4B0A80CB=5".parse::<KeyTrust>(),
+            Err(GpgError::InvalidKeyTrust(_))
+        ));
+        assert!(matches!(
+            "BEEA4CDB4B0A80CBABB99B45FDEFE8AB8796E127=trusty".parse::<KeyTrust>(),
+            Err(GpgError::InvalidTrustLevelValue(_))
+        ));
+        assert!(
+            "A9F1F8E3BEEA4CDB4B0A80CBABB99B45FDEFE8AB8796E127D296F98C9B778875=ultimate"
+                .parse::<KeyTrust>()
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn parse_ownertrust_extracts_value() {
+        let output = "# List of assigned trustvalues, created Mon 01 Jan 2024 00:00:00 UTC
+# (Use \"gpg --import-ownertrust\" to restore them)
+24DA69B1615F5F3C6A9C3A60B8527C5AED483BE3:4:
+BEEA4CDB4B0A80CBABB99B45FDEFE8AB8796E127:6:
+53C53C910B205E504F69EEA3CA953C1735BEEB77:131:
+";
+
+        assert_eq!(
+            parse_ownertrust(output, "BEEA4CDB4B0A80CBABB99B45FDEFE8AB8796E127"),
+            Some(6)
+        );
+        assert_eq!(
+            parse_ownertrust(output, "beea4cdb4b0a80cbabb99b45fdefe8ab8796e127"),
+            Some(6)
+        );
+        assert_eq!(
+            parse_ownertrust(output, "53C53C910B205E504F69EEA3CA953C1735BEEB77"),
+            Some(3),
+            "The disabled flag should be masked out"
+        );
+        assert_eq!(
+            parse_ownertrust(output, "F36BE03211AF1D3CE26D8B3ABE6663F6A323FBE8"),
+            None
+        );
+    }
+
+    #[test]
+    fn parse_gpg_version_output() {
+        let gpg_output = "gpg (GnuPG) 2.4.5
+libgcrypt 1.10.3
+Copyright (C) 2024 g10 Code GmbH
+License GNU GPL-3.0-or-later <https://gnu.org/licenses/gpl.html>
+This is free software: you are free to change and redistribute it.
+There is NO WARRANTY, to the extent permitted by law.
+
+Home: /home/user/.gnupg
+Supported algorithms:
+Pubkey: RSA, ELG, DSA, ECDH, ECDSA, EDDSA";
+
+        let result = gpg_output.parse::<GpgInfo>();
+        assert!(result.is_ok(), "Should parse GPG version output");
+
+        let info = result.unwrap();
+        assert_eq!(info.version, "2.4.5");
+        assert_eq!(info.semver, GpgVersion::new(2, 4, 5));
+        assert_eq!(info.libgcrypt, Some("1.10.3".to_string()));
+        assert_eq!(info.home_dir, PathBuf::from("/home/user/.gnupg"));
+        assert!(info.ensure_supported().is_ok());
+    }
+
+    #[test]
+    fn parse_gpg1_version_output() {
+        let gpg_output = "gpg (GnuPG) 1.4.23
+Copyright (C) 2015 Free Software Foundation, Inc.
+License GPLv3+: GNU GPL version 3 or later <https://gnu.org/licenses/gpl.html>
+This is free software: you are free to change and redistribute it.
+There is NO WARRANTY, to the extent permitted by law.
+
+Home: ~/.gnupg
+Supported algorithms:
+Pubkey: RSA, RSA-E, RSA-S, ELG-E, DSA";
+
+        let result = gpg_output.parse::<GpgInfo>();
+        assert!(result.is_ok(), "Should parse gpg1 version output");
+
+        let info = result.unwrap();
+        assert_eq!(info.semver, GpgVersion::new(1, 4, 23));
+        assert_eq!(info.libgcrypt, None);
+        assert!(matches!(
+            info.ensure_supported(),
+            Err(GpgError::UnsupportedVersion(version, MINIMUM_GPG_VERSION)) if version == "1.4.23"
+        ));
+    }
+
+    #[test]
+    fn parse_gpg2_binary_version_output() {
+        let gpg_output = "gpg2 (GnuPG) 2.0.22
+libgcrypt 1.5.3
+Copyright (C) 2013 Free Software Foundation, Inc.
+
+Home: ~/.gnupg";
+
+        let info = gpg_output.parse::<GpgInfo>().unwrap();
+        assert_eq!(info.semver, GpgVersion::new(2, 0, 22));
+        assert!(info.ensure_supported().is_err(), "GnuPG 2.0 is unsupported");
+    }
+
+    #[test]
+    fn parse_gpg_version_components() {
+        assert_eq!("2.4.5".parse::<GpgVersion>(), Ok(GpgVersion::new(2, 4, 5)));
+        assert_eq!(
+            "2.5.0-beta123".parse::<GpgVersion>(),
+            Ok(GpgVersion::new(2, 5, 0))
+        );
+        assert_eq!("2.1".parse::<GpgVersion>(), Ok(GpgVersion::new(2, 1, 0)));
+        assert!(matches!(
+            "unknown".parse::<GpgVersion>(),
+            Err(GpgError::InvalidVersion(_))
+        ));
+        assert!(GpgVersion::new(2, 1, 14) > GpgVersion::new(2, 1, 9));
+    }
+
+    #[test]
+    fn gpg_features_by_version() {
+        let gpg1 = GpgFeatures::from(GpgVersion::new(1, 4, 23));
+        assert!(!gpg1.preset_passphrase && !gpg1.loopback_pinentry && !gpg1.with_keygrip);
+        assert!(!gpg1.import_show_only);
+
+        let early_modern = GpgFeatures::from(GpgVersion::new(2, 1, 11));
+        assert!(early_modern.preset_passphrase && early_modern.loopback_pinentry);
+        assert!(
+            !early_modern.import_show_only,
+            "show-only arrived in 2.1.14"
+        );
+
+        let current = GpgFeatures::from(GpgVersion::new(2, 4, 5));
+        assert!(current.import_show_only && current.preset_passphrase);
+    }
+
+    #[test]
+    fn configure_defaults_creates_gpg_conf() {
+        let temp_dir = TempDir::new().unwrap();
+        let home_dir = temp_dir.path();
 
-    #[test]
-    fn configure_defaults_creates_gpg_conf() {
-        let temp_dir = TempDir::new().unwrap();
-        let home_dir = temp_dir.path().to_str().unwrap();
-
         let result = configure_defaults(home_dir);
         assert!(result.is_ok(), "Should create gpg.conf");
 
@@ -812,12 +2968,95 @@ Pubkey: RSA, ELG, DSA, ECDH, ECDSA, EDDSA";
         insta::assert_snapshot!(content);
     }
 
+    #[cfg(unix)]
+    #[test]
+    fn secure_home_dir_creates_private_home_dir() {
+        use std::os::unix::fs::PermissionsExt;
+        let temp_dir = TempDir::new().unwrap();
+        let home_path = temp_dir.path().join("nested").join("gnupg");
+        let pinned = PinnedHomeDir::new(&home_path);
+
+        assert_eq!(secure_home_dir(&home_path).unwrap(), vec![]);
+        configure_defaults(&home_path).unwrap();
+        configure_agent_defaults(&pinned.0, &home_path).unwrap();
+
+        let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode(&home_path), 0o700);
+        assert_eq!(mode(&home_path.join("gpg.conf")), 0o600);
+        assert_eq!(mode(&home_path.join("gpg-agent.conf")), 0o600);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn secure_home_dir_fixes_conf_files_and_warns_about_others() {
+        use std::os::unix::fs::PermissionsExt;
+        let temp_dir = TempDir::new().unwrap();
+        let home_path = temp_dir.path();
+        fs::set_permissions(home_path, fs::Permissions::from_mode(0o755)).unwrap();
+
+        for (name, mode) in [
+            ("gpg.conf", 0o644),
+            ("pubring.kbx", 0o644),
+            ("trustdb.gpg", 0o640),
+        ] {
+            let path = home_path.join(name);
+            fs::write(&path, "").unwrap();
+            fs::set_permissions(&path, fs::Permissions::from_mode(mode)).unwrap();
+        }
+
+        let findings = secure_home_dir(home_path).unwrap();
+        assert_eq!(
+            findings,
+            vec![
+                HomeDirFinding::Fixed {
+                    path: home_path.to_path_buf(),
+                    from: 0o755,
+                    to: 0o700,
+                },
+                HomeDirFinding::Fixed {
+                    path: home_path.join("gpg.conf"),
+                    from: 0o644,
+                    to: 0o600,
+                },
+                HomeDirFinding::Exposed {
+                    path: home_path.join("trustdb.gpg"),
+                    mode: 0o640,
+                },
+            ]
+        );
+        assert_eq!(
+            findings[1].to_string(),
+            format!("fixed:   {}/gpg.conf (0644 -> 0600)", home_path.display())
+        );
+        assert_eq!(
+            secure_home_dir(home_path).unwrap(),
+            vec![findings[2].clone()]
+        );
+    }
+
+    /// A context pinned to a test's home directory, so the agent reloaded is
+    /// the one reading its `gpg-agent.conf`. The agent is killed on drop
+    struct PinnedHomeDir(GpgContext);
+
+    impl PinnedHomeDir {
+        fn new(home_dir: &Path) -> Self {
+            Self(GpgContext::default().with_home_dir(Some(home_dir.to_path_buf())))
+        }
+    }
+
+    impl Drop for PinnedHomeDir {
+        fn drop(&mut self) {
+            let _ = self.0.gpgconf().args(["--kill", "gpg-agent"]).output();
+        }
+    }
+
     #[test]
     fn configure_agent_defaults_creates_gpg_agent_conf() {
         let temp_dir = TempDir::new().unwrap();
-        let home_dir = temp_dir.path().to_str().unwrap();
+        let home_dir = temp_dir.path();
+        let pinned = PinnedHomeDir::new(home_dir);
 
-        let result = configure_agent_defaults(home_dir);
+        let result = configure_agent_defaults(&pinned.0, home_dir);
         assert!(result.is_ok(), "Should create gpg-agent.conf");
 
         let agent_conf = temp_dir.path().join("gpg-agent.conf");
@@ -827,12 +3066,244 @@ Pubkey: RSA, ELG, DSA, ECDH, ECDSA, EDDSA";
         insta::assert_snapshot!(content);
     }
 
+    #[test]
+    fn configure_agent_writes_custom_options() {
+        let temp_dir = TempDir::new().unwrap();
+        let pinentry = temp_dir.path().join("pinentry-batman");
+        fs::write(&pinentry, "#!/bin/sh\n").unwrap();
+
+        let config = AgentConfig {
+            default_cache_ttl: 600,
+            max_cache_ttl: 7200,
+            allow_external_cache: false,
+            pinentry_program: Some(pinentry.clone()),
+            enable_ssh_support: true,
+        };
+        assert_eq!(
+            config.to_string(),
+            format!(
+                "default-cache-ttl 600
+max-cache-ttl 7200
+no-allow-external-cache
+pinentry-program {}
+enable-ssh-support
+allow-preset-passphrase
+allow-loopback-pinentry",
+                pinentry.display()
+            )
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn private_files_restrict_existing_files() {
+        use std::os::unix::fs::PermissionsExt;
+        let temp_dir = TempDir::new().unwrap();
+        let home_dir = temp_dir.path();
+        let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+
+        // A symlink is followed, restricting the file it points at
+        let agent_conf = home_dir.join("agent.conf");
+        fs::write(&agent_conf, "").unwrap();
+        fs::set_permissions(&agent_conf, fs::Permissions::from_mode(0o644)).unwrap();
+        std::os::unix::fs::symlink(&agent_conf, home_dir.join("gpg-agent.conf")).unwrap();
+        write_private_file(
+            &home_dir.join("gpg-agent.conf"),
+            b"allow-preset-passphrase\n",
+        )
+        .unwrap();
+        assert_eq!(mode(&agent_conf), 0o600);
+
+        let sshcontrol = home_dir.join("sshcontrol");
+        fs::write(&sshcontrol, "").unwrap();
+        fs::set_permissions(&sshcontrol, fs::Permissions::from_mode(0o644)).unwrap();
+        add_ssh_control(home_dir, "4AC8E7E7FD8B405DF2761726D296F98C9B778875").unwrap();
+        assert_eq!(mode(&sshcontrol), 0o600);
+    }
+
+    #[test]
+    fn add_ssh_control_appends_keygrip_once() {
+        let temp_dir = TempDir::new().unwrap();
+        let home_dir = temp_dir.path();
+        let sshcontrol = temp_dir.path().join("sshcontrol");
+        fs::write(
+            &sshcontrol,
+            "# List of allowed ssh keys\n!C4403DA4AF911084480BA46743E707CCDD082A24 0",
+        )
+        .unwrap();
+
+        assert!(add_ssh_control(home_dir, "4AC8E7E7FD8B405DF2761726D296F98C9B778875").unwrap());
+        assert!(!add_ssh_control(home_dir, "4ac8e7e7fd8b405df2761726d296f98c9b778875").unwrap());
+        assert_eq!(
+            fs::read_to_string(sshcontrol).unwrap(),
+            "# List of allowed ssh keys
+!C4403DA4AF911084480BA46743E707CCDD082A24 0
+4AC8E7E7FD8B405DF2761726D296F98C9B778875 0
+"
+        );
+    }
+
+    #[test]
+    fn add_ssh_control_reenables_disabled_keygrip() {
+        let temp_dir = TempDir::new().unwrap();
+        let home_dir = temp_dir.path();
+        let sshcontrol = temp_dir.path().join("sshcontrol");
+        fs::write(
+            &sshcontrol,
+            "# List of allowed ssh keys\n!C4403DA4AF911084480BA46743E707CCDD082A24 0\n\
+4AC8E7E7FD8B405DF2761726D296F98C9B778875 0",
+        )
+        .unwrap();
+
+        assert!(add_ssh_control(home_dir, "c4403da4af911084480ba46743e707ccdd082a24").unwrap());
+        assert!(!add_ssh_control(home_dir, "C4403DA4AF911084480BA46743E707CCDD082A24").unwrap());
+        assert_eq!(
+            fs::read_to_string(sshcontrol).unwrap(),
+            "# List of allowed ssh keys
+C4403DA4AF911084480BA46743E707CCDD082A24 0
+4AC8E7E7FD8B405DF2761726D296F98C9B778875 0"
+        );
+    }
+
+    #[test]
+    fn agent_config_rejects_default_ttl_above_max() {
+        let config = AgentConfig {
+            default_cache_ttl: 7200,
+            max_cache_ttl: 600,
+            ..Default::default()
+        };
+        assert_eq!(config.validate(), Err(GpgError::InvalidCacheTtl(7200, 600)));
+    }
+
+    #[test]
+    fn configure_agent_rejects_missing_pinentry_program_before_writing() {
+        let temp_dir = TempDir::new().unwrap();
+        let home_dir = temp_dir.path();
+
+        for pinentry in ["pinentry-curses", "/does/not/exist/pinentry"] {
+            let config = AgentConfig {
+                pinentry_program: Some(PathBuf::from(pinentry)),
+                ..Default::default()
+            };
+            assert_eq!(
+                config.validate(),
+                Err(GpgError::InvalidPinentryProgram(PathBuf::from(pinentry)))
+            );
+            assert!(configure_agent(&GpgContext::default(), home_dir, &config).is_err());
+        }
+        assert!(!temp_dir.path().join("gpg-agent.conf").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn agent_config_rejects_pinentry_program_unfit_for_agent_conf() {
+        use std::os::unix::ffi::OsStrExt;
+        let temp_dir = TempDir::new().unwrap();
+
+        // Both exist, so would otherwise be accepted
+        for name in [
+            std::ffi::OsStr::new("pinentry\nenable-ssh-support"),
+            std::ffi::OsStr::from_bytes(b"pinentry-\xff"),
+        ] {
+            let pinentry = temp_dir.path().join(name);
+            fs::write(&pinentry, "").unwrap();
+            let config = AgentConfig {
+                pinentry_program: Some(pinentry.clone()),
+                ..Default::default()
+            };
+            assert_eq!(
+                config.validate(),
+                Err(GpgError::UnsupportedPinentryProgram(pinentry))
+            );
+        }
+    }
+
+    #[test]
+    fn parse_gpgconf_dir_unescapes_percent_escapes() {
+        assert_eq!(
+            parse_gpgconf_dir(b"/run/user/1000/gnupg/d.kx3j/S.gpg-agent\n"),
+            PathBuf::from("/run/user/1000/gnupg/d.kx3j/S.gpg-agent")
+        );
+        assert_eq!(
+            parse_gpgconf_dir(b"C%3a\\gotham city\\100%25\\S.gpg-agent"),
+            PathBuf::from("C:\\gotham city\\100%\\S.gpg-agent")
+        );
+        assert_eq!(
+            parse_gpgconf_dir(b"/tmp/50%/S.gpg-agent"),
+            PathBuf::from("/tmp/50%/S.gpg-agent")
+        );
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::ffi::OsStrExt;
+            assert_eq!(
+                parse_gpgconf_dir(b"/tmp/gotham \xff/S.gpg-agent")
+                    .as_os_str()
+                    .as_bytes(),
+                b"/tmp/gotham \xff/S.gpg-agent"
+            );
+        }
+    }
+
+    #[test]
+    fn parse_reload_agent_extracts_getinfo() {
+        let output = "OK\nD 2.4.5\nOK\nD 4242\nOK\nD /run/user/1000/gnupg/S.gpg-agent\nOK\n";
+        assert_eq!(
+            parse_reload_agent(output).unwrap(),
+            AgentInfo {
+                version: "2.4.5".to_string(),
+                pid: 4242,
+                socket_name: PathBuf::from("/run/user/1000/gnupg/S.gpg-agent"),
+            }
+        );
+    }
+
+    #[test]
+    fn parse_agent_option_probes_reports_disabled_option() {
+        let allowed = "OK\nERR 67108992 Missing value <GPG Agent>\n";
+        assert!(parse_agent_option_probes(allowed).is_ok());
+
+        for (output, option) in [
+            (
+                "ERR 67108924 Not supported <GPG Agent>\nERR 67108992 Missing value <GPG Agent>\n",
+                "allow-loopback-pinentry",
+            ),
+            (
+                "OK\nERR 67108924 Not supported <GPG Agent> - no --allow-preset-passphrase\n",
+                "allow-preset-passphrase",
+            ),
+        ] {
+            assert_eq!(
+                parse_agent_option_probes(output)
+                    .unwrap_err()
+                    .downcast_ref::<GpgError>(),
+                Some(&GpgError::AgentOptionNotInForce(option.to_string()))
+            );
+        }
+        assert!(parse_agent_option_probes("OK\n").is_err());
+    }
+
+    #[test]
+    fn parse_reload_agent_requires_acknowledgement() {
+        for output in [
+            "",
+            "ERR 67109139 Unknown IPC command <GPG Agent>\n",
+            "OK\nD 2.4.5\nOK\n",
+        ] {
+            assert!(
+                parse_reload_agent(output).is_err(),
+                "{output:?} should not be treated as a reload"
+            );
+        }
+    }
+
     #[test]
     fn display_gpg_info() {
         let info = GpgInfo {
             version: "2.4.5".to_string(),
-            libgcrypt: "1.10.3".to_string(),
-            home_dir: "/home/user/.gnupg".to_string(),
+            semver: GpgVersion::new(2, 4, 5),
+            libgcrypt: Some("1.10.3".to_string()),
+            home_dir: PathBuf::from("/home/user/.gnupg"),
         };
         insta::assert_snapshot!(info.to_string());
     }
@@ -0,0 +1,209 @@
+use crate::gpg;
+use anyhow::{bail, Result};
+use std::{io::Read, time::Duration};
+use thiserror::Error;
+
+/// The default port of a keyserver addressed using the `hkp://` scheme
+const HKP_PORT: u16 = 11371;
+
+/// The largest key accepted from a keyserver
+const MAX_KEY_SIZE: u64 = 1024 * 1024;
+
+/// Errors that can occur when communicating with a keyserver
+#[derive(Clone, Debug, Eq, Error, PartialEq)]
+pub enum HkpError {
+    /// The keyserver URL uses an unsupported scheme or has no host
+    #[error("invalid keyserver url: {0}")]
+    InvalidUrl(String),
+
+    /// Keys can only be looked up by their full (v4 or v5) fingerprint
+    #[error("invalid fingerprint for keyserver lookup: {0}")]
+    InvalidFingerprint(String),
+
+    /// The keyserver has no key with the given fingerprint
+    #[error("no key found for {0} on keyserver: {1}")]
+    KeyNotFound(String, String),
+
+    /// The key returned by the keyserver doesn't have the requested fingerprint
+    #[error("key returned by keyserver for {0} does not match its fingerprint")]
+    FingerprintMismatch(String),
+
+    /// The response was larger than any key that would be accepted
+    #[error("keyserver response exceeds {0} bytes")]
+    ResponseTooLarge(u64),
+
+    /// The keyserver rejected an uploaded key
+    #[error("keyserver rejected key {0}: {1}")]
+    PublishFailed(String, String),
+}
+
+/// A client for a keyserver speaking the HTTP Keyserver Protocol (HKP)
+pub struct Keyserver {
+    agent: ureq::Agent,
+    base_url: String,
+    gpg: gpg::GpgContext,
+}
+
+impl Keyserver {
+    /// Create a client for the keyserver at the given URL. The `hkp://` and
+    /// `hkps://` schemes are mapped onto http (port 11371 by default) and
+    /// https respectively
+    pub fn new(url: &str) -> Result<Self, HkpError> {
+        Ok(Self {
+            agent: ureq::AgentBuilder::new()
+                .timeout(Duration::from_secs(10))
+                .build(),
+            base_url: normalize_url(url)?,
+            gpg: gpg::GpgContext::default(),
+        })
+    }
+
+    /// Run the gpg that verifies retrieved keys from the given installation,
+    /// rather than `gpg` from `PATH`
+    pub fn with_gpg_context(mut self, gpg: gpg::GpgContext) -> Self {
+        self.gpg = gpg;
+        self
+    }
+
+    /// The http(s) base URL of the keyserver
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// Retrieves the public key with the given fingerprint via
+    /// `/pks/lookup`. The key is verified to have the requested fingerprint,
+    /// and any other keys in the response are dropped before it is returned
+    pub fn fetch_key(&self, fingerprint: &str) -> Result<Vec<u8>> {
+        let fingerprint = normalize_fingerprint(fingerprint)?;
+
+        let response = self
+            .agent
+            .get(&format!("{}/pks/lookup", self.base_url))
+            .query("op", "get")
+            .query("options", "mr")
+            .query("search", &format!("0x{fingerprint}"))
+            .call()
+            .map_err(|e| HkpError::KeyNotFound(fingerprint.clone(), e.to_string()))?;
+
+        // Read one byte beyond the limit, so an oversized response is
+        // rejected rather than truncated into a partial key
+        let mut data = Vec::new();
+        response
+            .into_reader()
+            .take(MAX_KEY_SIZE + 1)
+            .read_to_end(&mut data)?;
+        if data.len() as u64 > MAX_KEY_SIZE {
+            bail!(HkpError::ResponseTooLarge(MAX_KEY_SIZE));
+        }
+
+        let keys = gpg::preview_public_key_data(&self.gpg, &data)?;
+        if !keys.iter().any(|key| key.fingerprint == fingerprint) {
+            bail!(HkpError::FingerprintMismatch(fingerprint));
+        }
+        if keys.len() == 1 {
+            return Ok(data);
+        }
+        gpg::select_public_key_data(&self.gpg, &data, &[fingerprint])
+    }
+
+    /// Uploads an ASCII armored public key via `/pks/add`
+    pub fn publish_key(&self, fingerprint: &str, armored: &str) -> Result<()> {
+        self.agent
+            .post(&format!("{}/pks/add", self.base_url))
+            .send_form(&[("keytext", armored)])
+            .map_err(|e| HkpError::PublishFailed(fingerprint.to_string(), e.to_string()))?;
+        Ok(())
+    }
+}
+
+/// Maps a keyserver URL onto an http(s) base URL, without a trailing slash
+fn normalize_url(url: &str) -> Result<String, HkpError> {
+    let invalid = || HkpError::InvalidUrl(url.to_string());
+    let (scheme, rest) = url.trim().split_once("://").ok_or_else(invalid)?;
+    let rest = rest.trim_end_matches('/');
+    let host = rest.split('/').next().unwrap_or_default();
+    if host.is_empty() {
+        return Err(invalid());
+    }
+
+    match scheme.to_lowercase().as_str() {
+        "http" | "https" => Ok(format!("{}://{rest}", scheme.to_lowercase())),
+        "hkps" => Ok(format!("https://{rest}")),
+        "hkp" if host.contains(':') => Ok(format!("http://{rest}")),
+        "hkp" => Ok(format!("http://{host}:{HKP_PORT}{}", &rest[host.len()..])),
+        _ => Err(invalid()),
+    }
+}
+
+/// Normalises a fingerprint into uppercase hex without spaces or a `0x`
+/// prefix, rejecting anything that isn't a full fingerprint
+fn normalize_fingerprint(fingerprint: &str) -> Result<String, HkpError> {
+    let normalized: String = fingerprint
+        .trim()
+        .trim_start_matches("0x")
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>()
+        .to_uppercase();
+
+    if !matches!(normalized.len(), 40 | 64) || !normalized.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(HkpError::InvalidFingerprint(fingerprint.to_string()));
+    }
+    Ok(normalized)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_url_maps_hkp_schemes() {
+        assert_eq!(
+            normalize_url("hkps://keys.openpgp.org").unwrap(),
+            "https://keys.openpgp.org"
+        );
+        assert_eq!(
+            normalize_url("hkp://keyserver.ubuntu.com/").unwrap(),
+            "http://keyserver.ubuntu.com:11371"
+        );
+        assert_eq!(
+            normalize_url("hkp://127.0.0.1:8080").unwrap(),
+            "http://127.0.0.1:8080"
+        );
+        assert_eq!(
+            normalize_url("HTTP://127.0.0.1:8080/keys/").unwrap(),
+            "http://127.0.0.1:8080/keys"
+        );
+    }
+
+    #[test]
+    fn normalize_url_rejects_invalid_url() {
+        for url in ["keys.openpgp.org", "ldap://keys.dc.com", "hkps://"] {
+            assert_eq!(
+                normalize_url(url),
+                Err(HkpError::InvalidUrl(url.to_string()))
+            );
+        }
+    }
+
+    #[test]
+    fn normalize_fingerprint_strips_prefix_and_spaces() {
+        assert_eq!(
+            normalize_fingerprint("0x24da 69b1 615f 5f3c 6a9c  3a60 b852 7c5a ed48 3be3").unwrap(),
+            "24DA69B1615F5F3C6A9C3A60B8527C5AED483BE3"
+        );
+    }
+
+    #[test]
+    fn normalize_fingerprint_rejects_key_ids() {
+        for fingerprint in [
+            "B8527C5AED483BE3",
+            "ZZDA69B1615F5F3C6A9C3A60B8527C5AED483BE3",
+        ] {
+            assert_eq!(
+                normalize_fingerprint(fingerprint),
+                Err(HkpError::InvalidFingerprint(fingerprint.to_string()))
+            );
+        }
+    }
+}
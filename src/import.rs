@@ -1,18 +1,54 @@
-use crate::{git, gpg};
+use crate::{
+    allowed_signers::{AllowedSigner, AllowedSigners},
+    git, gpg, hkp,
+    passphrase::{self, KeyPassphrase, PassphraseChange},
+    wkd,
+};
 use anyhow::{bail, Result};
 use chrono::{TimeZone, Utc};
 use git2::Repository;
+use std::{
+    collections::HashSet,
+    env,
+    ffi::OsStr,
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+};
 
 /// A builder for importing GPG keys with optional configuration.
 pub struct GpgImport {
     key: String,
+    public_keys: Vec<String>,
+    wkd_emails: Vec<String>,
+    wkd_base_url: Option<String>,
+    keyserver: Option<String>,
+    keyserver_keys: Vec<String>,
+    publish: bool,
     passphrase: Option<String>,
+    key_passphrases: Vec<KeyPassphrase>,
+    passphrase_change: Option<PassphraseChange>,
+    rotated_passphrase_file: Option<PathBuf>,
     fingerprint: Option<String>,
-    trust_level: Option<u8>,
+    trust_level: Option<gpg::TrustLevel>,
+    auto_trust: bool,
+    key_trust: Vec<gpg::KeyTrust>,
     skip_git: bool,
     git_global_config: bool,
+    repos: Vec<PathBuf>,
+    recurse_submodules: bool,
     git_committer_name: Option<String>,
     git_committer_email: Option<String>,
+    gpg: gpg::GpgContext,
+    agent_config: gpg::AgentConfig,
+    export_public: Option<PathBuf>,
+    export_public_output: Option<String>,
+    export_minimal: bool,
+    allowed_signers: Option<PathBuf>,
+    install_pre_push_hook: bool,
+    detached_signatures: Vec<PathBuf>,
+    binary_signatures: bool,
+    clearsigned: Vec<PathBuf>,
     dry_run: bool,
 }
 
@@ -21,35 +57,135 @@ impl GpgImport {
     pub fn new(key: String) -> Self {
         Self {
             key,
+            public_keys: Vec::new(),
+            wkd_emails: Vec::new(),
+            wkd_base_url: None,
+            keyserver: None,
+            keyserver_keys: Vec::new(),
+            publish: false,
             passphrase: None,
+            key_passphrases: Vec::new(),
+            passphrase_change: None,
+            rotated_passphrase_file: None,
             fingerprint: None,
             trust_level: None,
+            auto_trust: false,
+            key_trust: Vec::new(),
             skip_git: false,
             git_global_config: false,
+            repos: Vec::new(),
+            recurse_submodules: false,
             git_committer_name: None,
             git_committer_email: None,
+            gpg: gpg::GpgContext::default(),
+            agent_config: gpg::AgentConfig::default(),
+            export_public: None,
+            export_public_output: None,
+            export_minimal: false,
+            allowed_signers: None,
+            install_pre_push_hook: false,
+            detached_signatures: Vec::new(),
+            binary_signatures: false,
+            clearsigned: Vec::new(),
             dry_run: false,
         }
     }
 
+    /// Set public keys to import alongside the key, each ASCII armored
+    /// (optionally base64 encoded) and containing one or more keys.
+    pub fn with_public_keys(mut self, public_keys: Vec<String>) -> Self {
+        self.public_keys = public_keys;
+        self
+    }
+
+    /// Set email addresses whose public keys are retrieved via Web Key
+    /// Directory and imported alongside the key.
+    pub fn with_wkd_emails(mut self, emails: Vec<String>) -> Self {
+        self.wkd_emails = emails;
+        self
+    }
+
+    /// Retrieve Web Key Directory keys from the given base URL, rather than
+    /// the domain of each email address.
+    pub fn with_wkd_base_url(mut self, base_url: Option<String>) -> Self {
+        self.wkd_base_url = base_url;
+        self
+    }
+
+    /// Set the URL of the keyserver (`hkp://`, `hkps://`, `http://` or
+    /// `https://`) used to retrieve and publish keys.
+    pub fn with_keyserver(mut self, url: Option<String>) -> Self {
+        self.keyserver = url;
+        self
+    }
+
+    /// Set the fingerprints of public keys retrieved from the keyserver and
+    /// imported alongside the key.
+    pub fn with_keyserver_keys(mut self, fingerprints: Vec<String>) -> Self {
+        self.keyserver_keys = fingerprints;
+        self
+    }
+
+    /// Publish the public part of the imported key to the keyserver.
+    pub fn publish(mut self, enabled: bool) -> Self {
+        self.publish = enabled;
+        self
+    }
+
     /// Set the passphrase for the key.
     pub fn with_passphrase(mut self, passphrase: Option<String>) -> Self {
         self.passphrase = passphrase;
         self
     }
 
+    /// Set passphrases for specific keys or subkeys, identified by their
+    /// keygrip, fingerprint or key id. These take precedence over the
+    /// passphrase set for the key as a whole.
+    pub fn with_key_passphrases(mut self, key_passphrases: Vec<KeyPassphrase>) -> Self {
+        self.key_passphrases = key_passphrases;
+        self
+    }
+
+    /// Strip or rotate the passphrase protecting the key once imported, so
+    /// signing doesn't depend on the agent cache outliving the job.
+    pub fn with_passphrase_change(mut self, change: Option<PassphraseChange>) -> Self {
+        self.passphrase_change = change;
+        self
+    }
+
+    /// Write a rotated passphrase to this file, only readable by the current
+    /// user, so the key can still be unlocked once the agent cache is
+    /// flushed.
+    pub fn with_rotated_passphrase_file(mut self, path: Option<PathBuf>) -> Self {
+        self.rotated_passphrase_file = path;
+        self
+    }
+
     /// Set the fingerprint of a specific key or subkey to use for signing.
     pub fn with_fingerprint(mut self, fingerprint: Option<String>) -> Self {
         self.fingerprint = fingerprint;
         self
     }
 
-    /// Set the trust level for the key (1-5).
-    pub fn with_trust_level(mut self, level: Option<u8>) -> Self {
+    /// Set the trust level for the key.
+    pub fn with_trust_level(mut self, level: Option<gpg::TrustLevel>) -> Self {
         self.trust_level = level;
         self
     }
 
+    /// Ultimately trust the imported key, unless a trust level is set.
+    pub fn auto_trust(mut self, enabled: bool) -> Self {
+        self.auto_trust = enabled;
+        self
+    }
+
+    /// Set trust levels for other keys within the keyring, such as public
+    /// keys imported alongside.
+    pub fn with_key_trust(mut self, key_trust: Vec<gpg::KeyTrust>) -> Self {
+        self.key_trust = key_trust;
+        self
+    }
+
     /// Skip git repository configuration.
     pub fn skip_git(mut self, skip: bool) -> Self {
         self.skip_git = skip;
@@ -62,6 +198,19 @@ impl GpgImport {
         self
     }
 
+    /// Set the paths used to discover the git repositories to configure,
+    /// rather than the current working directory.
+    pub fn with_repos(mut self, paths: Vec<PathBuf>) -> Self {
+        self.repos = paths;
+        self
+    }
+
+    /// Also configure every (nested) submodule of each git repository.
+    pub fn recurse_submodules(mut self, recurse: bool) -> Self {
+        self.recurse_submodules = recurse;
+        self
+    }
+
     /// Override the committer name instead of using the value from the GPG key.
     pub fn with_git_committer_name(mut self, name: Option<String>) -> Self {
         self.git_committer_name = name;
@@ -74,6 +223,82 @@ impl GpgImport {
         self
     }
 
+    /// Pin the GnuPG binary used for the import and by git for signing,
+    /// rather than resolving `gpg` from `PATH`.
+    pub fn with_gpg_program(mut self, program: Option<PathBuf>) -> Self {
+        self.gpg = self.gpg.with_program(program);
+        self
+    }
+
+    /// Import into, and configure, the given gpg home directory rather than
+    /// `GNUPGHOME` or `~/.gnupg`.
+    pub fn with_home_dir(mut self, home_dir: Option<PathBuf>) -> Self {
+        self.gpg = self.gpg.with_home_dir(home_dir);
+        self
+    }
+
+    /// Set the options written to `gpg-agent.conf`, such as cache TTLs.
+    pub fn with_agent_config(mut self, config: gpg::AgentConfig) -> Self {
+        self.agent_config = config;
+        self
+    }
+
+    /// Write the ASCII armored public key of the imported key (or selected
+    /// subkey) to a file, e.g. for registering with GitHub or GitLab.
+    pub fn with_export_public(mut self, path: Option<PathBuf>) -> Self {
+        self.export_public = path;
+        self
+    }
+
+    /// Write the ASCII armored public key of the imported key (or selected
+    /// subkey) to a named GitHub Actions step output.
+    pub fn with_export_public_output(mut self, name: Option<String>) -> Self {
+        self.export_public_output = name;
+        self
+    }
+
+    /// Strip all but the most recent self-signatures from the exported
+    /// public key.
+    pub fn export_minimal(mut self, enabled: bool) -> Self {
+        self.export_minimal = enabled;
+        self
+    }
+
+    /// Maintain an allowed signers file mapping the email of every uid of the
+    /// key, and of any public keys, onto their SSH public key. Configured as
+    /// git's `gpg.ssh.allowedSignersFile` when signing in SSH mode.
+    pub fn with_allowed_signers(mut self, path: Option<PathBuf>) -> Self {
+        self.allowed_signers = path;
+        self
+    }
+
+    /// Install a pre-push hook into each git repository, refusing to push
+    /// commits that aren't signed by the configured signing key.
+    pub fn install_pre_push_hook(mut self, enabled: bool) -> Self {
+        self.install_pre_push_hook = enabled;
+        self
+    }
+
+    /// Set files to produce detached signatures for with the imported key
+    /// (or selected subkey), e.g. release tarballs.
+    pub fn with_detached_signatures(mut self, paths: Vec<PathBuf>) -> Self {
+        self.detached_signatures = paths;
+        self
+    }
+
+    /// Produce binary, rather than ASCII armored, detached signatures.
+    pub fn binary_signatures(mut self, enabled: bool) -> Self {
+        self.binary_signatures = enabled;
+        self
+    }
+
+    /// Set files to clearsign with the imported key (or selected subkey),
+    /// e.g. checksum files.
+    pub fn with_clearsigned(mut self, paths: Vec<PathBuf>) -> Self {
+        self.clearsigned = paths;
+        self
+    }
+
     /// Enable dry-run mode (preview without making changes).
     pub fn dry_run(mut self, enabled: bool) -> Self {
         self.dry_run = enabled;
@@ -85,25 +310,91 @@ impl GpgImport {
         if self.dry_run {
             println!("No changes will be made will running in dry-run mode\n");
         }
+        self.agent_config.validate()?;
+        if self.rotated_passphrase_file.is_some()
+            && self.passphrase_change != Some(PassphraseChange::Rotate)
+        {
+            bail!("A rotated passphrase file requires the passphrase to be rotated");
+        }
+        self.ensure_git_reaches_home_dir()?;
 
-        let info = gpg::detect_version()?;
+        let info = gpg::detect_version(&self.gpg)?;
         println!("> Detected GnuPG:");
         println!("{info}");
+        info.ensure_supported()?;
 
+        self.secure_home_dir(&info)?;
         let private_key = self.import_gpg_key(&info)?;
+        self.export_home_dir(&info)?;
         self.configure_gpg_passphrase(&private_key)?;
+        self.configure_ssh_support(&info, &private_key)?;
+        let public_keys = self.import_public_keys()?;
         self.configure_gpg_trust_level(&private_key)?;
+        self.publish_public_key(&private_key)?;
+        self.export_public_key(&private_key)?;
+        self.write_allowed_signers(&private_key, &public_keys)?;
+        self.sign_artifacts(&private_key)?;
         self.configure_git_signing(&private_key)?;
 
         Ok(())
     }
 
+    /// git runs gpg without `--homedir`, so signing with a key imported into
+    /// a pinned homedir relies on `GNUPGHOME` pointing at it, either already
+    /// or by exporting it to later steps through GitHub Actions. Fails
+    /// before importing anything if neither is the case
+    fn ensure_git_reaches_home_dir(&self) -> Result<()> {
+        let Some(home_dir) = self.gpg.pinned_home_dir() else {
+            return Ok(());
+        };
+        if self.skip_git || env::var_os("GITHUB_ENV").is_some() {
+            return Ok(());
+        }
+        let gnupghome = env::var_os("GNUPGHOME")
+            .filter(|gnupghome| !gnupghome.is_empty())
+            .map(PathBuf::from)
+            .map(|gnupghome| std::path::absolute(&gnupghome).unwrap_or(gnupghome));
+        if gnupghome.as_deref() == Some(home_dir) {
+            return Ok(());
+        }
+        if !self.git_global_config && self.discover_repos()?.is_empty() {
+            return Ok(());
+        }
+
+        bail!(
+            "git runs gpg without --homedir, so it wouldn't find the key within {}; set GNUPGHOME to it, or skip configuring git",
+            home_dir.display()
+        );
+    }
+
+    /// Creates the homedir before gpg imports into it, as gpg won't create
+    /// a homedir other than its default
+    fn secure_home_dir(&self, info: &gpg::GpgInfo) -> Result<()> {
+        if self.dry_run {
+            return Ok(());
+        }
+
+        let findings = gpg::secure_home_dir(&info.home_dir)?;
+        if !findings.is_empty() {
+            println!("> Secured gpg homedir:");
+            for finding in findings {
+                println!("{finding}");
+            }
+            println!();
+        }
+        Ok(())
+    }
+
     fn import_gpg_key(&self, info: &gpg::GpgInfo) -> Result<gpg::GpgPrivateKey> {
         let private_key = if self.dry_run {
-            gpg::preview_key(self.key.trim())?
+            if info.features().import_show_only {
+                gpg::preview_key(&self.gpg, self.key.trim())?
+            } else {
+                gpg::preview_key_isolated(&self.gpg, self.key.trim())?
+            }
         } else {
-            let key_id = gpg::import_secret_key(self.key.trim())?;
-            gpg::extract_key_info(&key_id)?
+            let key_id = gpg::import_secret_key(&self.gpg, self.key.trim())?;
+            gpg::extract_key_info(&self.gpg, &key_id)?
         };
 
         println!("> Imported GPG key:");
@@ -111,52 +402,505 @@ impl GpgImport {
 
         if !self.dry_run {
             gpg::configure_defaults(&info.home_dir)?;
-            gpg::configure_agent_defaults(&info.home_dir)?;
+            let agent = gpg::configure_agent(&self.gpg, &info.home_dir, &self.agent_config)?;
+            println!("> Reloaded gpg-agent:");
+            println!("{agent}");
         }
 
         Ok(private_key)
     }
 
+    /// git runs gpg without `--homedir`, so a pinned home directory is
+    /// exported as `GNUPGHOME` for signing within later workflow steps
+    fn export_home_dir(&self, info: &gpg::GpgInfo) -> Result<()> {
+        if self.gpg.pinned_home_dir().is_none() || self.dry_run {
+            return Ok(());
+        }
+
+        append_github_env("GNUPGHOME", info.home_dir.as_os_str())?;
+        println!("> Pinned gpg homedir:");
+        println!("GNUPGHOME={}", info.home_dir.display());
+        println!();
+        Ok(())
+    }
+
     fn configure_gpg_passphrase(&self, private_key: &gpg::GpgPrivateKey) -> Result<()> {
-        let Some(passphrase) = &self.passphrase else {
+        let passphrases = self.resolve_key_passphrases(private_key)?;
+        if passphrases.is_empty() {
             return Ok(());
+        }
+
+        // Keys are only known to the agent once imported, so a dry run
+        // assumes every key is protected
+        let unprotected: HashSet<String> = if self.dry_run {
+            HashSet::new()
+        } else {
+            gpg::agent_key_info(&self.gpg)?
+                .into_iter()
+                .filter(|info| info.protection == gpg::KeyProtection::Unprotected)
+                .map(|info| info.keygrip)
+                .collect()
         };
 
-        let passphrase_cleaned = passphrase.trim();
+        println!("> Setting Passphrase:");
+        let mut protected = Vec::new();
+        for (key, passphrase) in passphrases {
+            if unprotected.contains(&key.keygrip) {
+                println!(
+                    "keygrip: {} [{}] (unprotected, skipped)",
+                    key.keygrip, key.key_id
+                );
+                continue;
+            }
+            if !self.dry_run {
+                gpg::preset_passphrase(&self.gpg, &key.keygrip, &passphrase)?;
+            }
+            println!("keygrip: {} [{}]", key.keygrip, key.key_id);
+            protected.push((key, passphrase));
+        }
 
-        if !self.dry_run {
-            gpg::preset_passphrase(&private_key.secret_key.keygrip, passphrase_cleaned)?;
-            for subkey in &private_key.subkeys {
-                gpg::preset_passphrase(&subkey.keygrip, passphrase_cleaned)?;
+        self.change_gpg_passphrase(&protected)
+    }
+
+    fn change_gpg_passphrase(&self, keys: &[(&gpg::GpgKeyDetails, String)]) -> Result<()> {
+        let Some(change) = self.passphrase_change else {
+            return Ok(());
+        };
+        if keys.is_empty() {
+            return Ok(());
+        }
+
+        // Every key is rotated to the same passphrase, which is written out
+        // before any key is changed, so it can't be lost part way through
+        let new_passphrase = match change {
+            PassphraseChange::Strip => String::new(),
+            PassphraseChange::Rotate if self.dry_run => String::new(),
+            PassphraseChange::Rotate => {
+                let new_passphrase = passphrase::generate_passphrase()?;
+                if let Some(path) = &self.rotated_passphrase_file {
+                    gpg::write_private_file(path, format!("{new_passphrase}\n").as_bytes())?;
+                }
+                new_passphrase
             }
+        };
+
+        match change {
+            PassphraseChange::Strip => println!("\n> Stripped Passphrase:"),
+            PassphraseChange::Rotate => println!("\n> Rotated Passphrase:"),
+        }
+        for (key, passphrase) in keys {
+            if !self.dry_run {
+                gpg::change_passphrase(&self.gpg, &key.keygrip, passphrase, &new_passphrase)?;
+                if change == PassphraseChange::Rotate {
+                    gpg::preset_passphrase(&self.gpg, &key.keygrip, &new_passphrase)?;
+                }
+            }
+            println!("keygrip: {} [{}]", key.keygrip, key.key_id);
         }
 
-        println!("> Setting Passphrase:");
-        println!(
-            "keygrip: {} [{}]",
-            private_key.secret_key.keygrip, private_key.secret_key.key_id
-        );
-        for subkey in &private_key.subkeys {
-            println!("keygrip: {} [{}]", subkey.keygrip, subkey.key_id);
+        if change == PassphraseChange::Rotate {
+            match &self.rotated_passphrase_file {
+                Some(path) => println!("passphrase file: {}", path.display()),
+                None => eprintln!(
+                    "warning: the rotated passphrase is only held by the agent, so the key can't \
+                     be unlocked once its cache is flushed"
+                ),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Pairs the primary key and each subkey with its passphrase, preferring
+    /// a passphrase set for that specific key over the key as a whole. Keys
+    /// without any passphrase are omitted
+    fn resolve_key_passphrases<'a>(
+        &self,
+        private_key: &'a gpg::GpgPrivateKey,
+    ) -> Result<Vec<(&'a gpg::GpgKeyDetails, String)>> {
+        let keys: Vec<&gpg::GpgKeyDetails> = std::iter::once(&private_key.secret_key)
+            .chain(&private_key.subkeys)
+            .collect();
+
+        let mut specific = Vec::new();
+        for key_passphrase in &self.key_passphrases {
+            let selector = normalize_key_selector(&key_passphrase.key);
+            let key = keys
+                .iter()
+                .find(|key| key.keygrip.to_uppercase() == selector || matches_key(key, &selector))
+                .ok_or_else(|| gpg::GpgError::FingerprintNotFound(key_passphrase.key.clone()))?;
+            specific.push((key.keygrip.as_str(), key_passphrase.source.read()?));
+        }
+
+        Ok(keys
+            .into_iter()
+            .filter_map(|key| {
+                let passphrase = specific
+                    .iter()
+                    .find(|(keygrip, _)| *keygrip == key.keygrip)
+                    .map(|(_, passphrase)| passphrase)
+                    .or(self.passphrase.as_ref())?;
+                Some((key, passphrase.trim().to_string()))
+            })
+            .collect())
+    }
+
+    fn import_public_keys(&self) -> Result<Vec<gpg::GpgPublicKey>> {
+        if self.public_keys.is_empty()
+            && self.wkd_emails.is_empty()
+            && self.keyserver_keys.is_empty()
+        {
+            return Ok(Vec::new());
+        }
+
+        let mut public_keys = Vec::new();
+        for public_key in &self.public_keys {
+            if self.dry_run {
+                public_keys.extend(gpg::preview_public_keys(&self.gpg, public_key.trim())?);
+            } else {
+                for fingerprint in gpg::import_public_keys(&self.gpg, public_key.trim())? {
+                    public_keys.push(gpg::extract_public_key_info(&self.gpg, &fingerprint)?);
+                }
+            }
+        }
+
+        if !self.wkd_emails.is_empty() {
+            let mut client = wkd::Wkd::new().with_gpg_context(self.gpg.clone());
+            if let Some(base_url) = &self.wkd_base_url {
+                client = client.with_base_url(base_url);
+            }
+
+            for email in &self.wkd_emails {
+                let key = client.fetch_key(email)?;
+                public_keys.extend(self.import_public_key_data(&key.data)?);
+            }
+        }
+
+        if !self.keyserver_keys.is_empty() {
+            let keyserver = self.keyserver()?;
+            for fingerprint in &self.keyserver_keys {
+                let data = keyserver.fetch_key(fingerprint)?;
+                public_keys.extend(self.import_public_key_data(&data)?);
+            }
+        }
+
+        println!("\n> Imported public keys:");
+        for public_key in &public_keys {
+            println!("{public_key}");
+        }
+
+        Ok(public_keys)
+    }
+
+    fn import_public_key_data(&self, data: &[u8]) -> Result<Vec<gpg::GpgPublicKey>> {
+        if self.dry_run {
+            return gpg::preview_public_key_data(&self.gpg, data);
+        }
+
+        gpg::import_public_key_data(&self.gpg, data)?
+            .iter()
+            .map(|fingerprint| gpg::extract_public_key_info(&self.gpg, fingerprint))
+            .collect()
+    }
+
+    fn keyserver(&self) -> Result<hkp::Keyserver> {
+        let Some(url) = &self.keyserver else {
+            bail!("A keyserver is required to fetch or publish keys");
+        };
+        Ok(hkp::Keyserver::new(url)?.with_gpg_context(self.gpg.clone()))
+    }
+
+    fn publish_public_key(&self, private_key: &gpg::GpgPrivateKey) -> Result<()> {
+        if !self.publish {
+            return Ok(());
         }
 
+        let keyserver = self.keyserver()?;
+        let fingerprint = &private_key.secret_key.fingerprint;
+        if !self.dry_run {
+            let armored = gpg::export_public_key(&self.gpg, fingerprint, false)?;
+            keyserver.publish_key(fingerprint, &armored)?;
+        }
+
+        println!("\n> Published public key:");
+        println!("keyserver: {} [{}]", keyserver.base_url(), fingerprint);
+
         Ok(())
     }
 
+    /// The trust level for the imported key: either explicitly set, or
+    /// derived as ultimate (as the key is our own) when auto-trust is enabled
+    fn effective_trust_level(&self) -> Option<gpg::TrustLevel> {
+        self.trust_level
+            .or(self.auto_trust.then_some(gpg::TrustLevel::Ultimate))
+    }
+
     fn configure_gpg_trust_level(&self, private_key: &gpg::GpgPrivateKey) -> Result<()> {
-        let Some(trust_level) = self.trust_level else {
+        let own_trust = self
+            .effective_trust_level()
+            .map(|trust_level| gpg::KeyTrust {
+                fingerprint: private_key.secret_key.fingerprint.clone(),
+                trust_level,
+            });
+        let key_trust: Vec<&gpg::KeyTrust> = own_trust.iter().chain(&self.key_trust).collect();
+        if key_trust.is_empty() {
             return Ok(());
-        };
+        }
 
         if !self.dry_run {
-            gpg::assign_trust_level(&private_key.secret_key.key_id, trust_level)?;
+            for trust in &key_trust {
+                gpg::assign_trust_level(
+                    &self.gpg,
+                    &trust.fingerprint,
+                    trust.trust_level.trust_db_value(),
+                )?;
+            }
         }
 
         println!("\n> Setting Trust Level:");
-        println!(
-            "trust_level: {} [{}]",
-            trust_level, private_key.secret_key.key_id
-        );
+        for trust in &key_trust {
+            println!(
+                "trust_level: {} [{}]",
+                trust.trust_level.trust_db_value(),
+                trust.fingerprint
+            );
+        }
+
+        Ok(())
+    }
+
+    fn configure_ssh_support(
+        &self,
+        info: &gpg::GpgInfo,
+        private_key: &gpg::GpgPrivateKey,
+    ) -> Result<()> {
+        if !self.agent_config.enable_ssh_support {
+            return Ok(());
+        }
+
+        let subkey = ssh_auth_subkey(private_key)?;
+        println!("\n> SSH support enabled:");
+        println!("keygrip: {} [{}]", subkey.keygrip, subkey.key_id);
+        if self.dry_run {
+            return Ok(());
+        }
+
+        // SSH clients can't answer a pinentry prompt, so the subkey has to be
+        // usable without one
+        let usable = gpg::agent_key_info(&self.gpg)?.into_iter().any(|key| {
+            key.keygrip == subkey.keygrip
+                && (key.cached || key.protection == gpg::KeyProtection::Unprotected)
+        });
+        if !usable {
+            bail!(
+                "the passphrase of authentication subkey {} was not preset, so ssh clients \
+                 would be prompted for it",
+                subkey.key_id
+            );
+        }
+
+        gpg::add_ssh_control(&info.home_dir, &subkey.keygrip)?;
+        let ssh_auth_sock = gpg::ssh_auth_sock(&self.gpg)?;
+        append_github_env("SSH_AUTH_SOCK", ssh_auth_sock.as_os_str())?;
+        println!("SSH_AUTH_SOCK={}", ssh_auth_sock.display());
+        let exact_subkey = format!("{}!", subkey.fingerprint);
+        println!("{}", gpg::export_ssh_key(&self.gpg, &exact_subkey)?);
+
+        Ok(())
+    }
+
+    fn export_public_key(&self, private_key: &gpg::GpgPrivateKey) -> Result<()> {
+        if self.export_public.is_none() && self.export_public_output.is_none() {
+            return Ok(());
+        }
+
+        // Export just the selected subkey (alongside its primary key), or the
+        // whole key when signing with the primary key
+        let signing_key = self.resolve_signing_key(private_key)?;
+        let export_key = if signing_key.ends_with('!') {
+            signing_key
+        } else {
+            private_key.secret_key.fingerprint.clone()
+        };
+
+        let armored = if self.dry_run {
+            None
+        } else {
+            Some(gpg::export_public_key(
+                &self.gpg,
+                &export_key,
+                self.export_minimal,
+            )?)
+        };
+
+        println!("\n> Exported public key:");
+        if let Some(path) = &self.export_public {
+            if let Some(armored) = &armored {
+                fs::write(path, armored)?;
+            }
+            println!("path: {} [{}]", path.display(), export_key);
+        }
+        if let Some(name) = &self.export_public_output {
+            if let Some(armored) = &armored {
+                write_step_output(name, armored)?;
+            }
+            println!("output: {name} [{export_key}]");
+        }
+
+        Ok(())
+    }
+
+    fn write_allowed_signers(
+        &self,
+        private_key: &gpg::GpgPrivateKey,
+        public_keys: &[gpg::GpgPublicKey],
+    ) -> Result<()> {
+        let Some(path) = &self.allowed_signers else {
+            return Ok(());
+        };
+
+        // Map each uid onto the key it signs with: the selected signing key
+        // for our own key (or, with ssh support, the authentication subkey
+        // the agent serves to ssh-keygen), and the primary key of any
+        // public keys. Entries are marked with the primary fingerprint, so
+        // a key sharing an email with another never replaces its entry
+        let mut signers = Vec::new();
+        let signing_key = if self.agent_config.enable_ssh_support {
+            format!("{}!", ssh_auth_subkey(private_key)?.fingerprint)
+        } else {
+            self.resolve_signing_key(private_key)?
+        };
+        for uid in &private_key.uids {
+            signers.push((
+                uid.email.as_str(),
+                signing_key.as_str(),
+                private_key.secret_key.fingerprint.as_str(),
+            ));
+        }
+        for public_key in public_keys {
+            for uid in &public_key.uids {
+                signers.push((
+                    uid.email.as_str(),
+                    public_key.fingerprint.as_str(),
+                    public_key.fingerprint.as_str(),
+                ));
+            }
+        }
+
+        let mut allowed_signers = AllowedSigners::load(path)?;
+        println!("\n> Allowed signers: {}", path.display());
+        for (email, key, fingerprint) in signers {
+            if email.trim().is_empty() {
+                continue;
+            }
+            if !self.dry_run {
+                // Only some key algorithms have an SSH form (e.g. not DSA or
+                // ElGamal), which shouldn't hold up importing the others
+                let ssh_key = match gpg::export_ssh_key(&self.gpg, key) {
+                    Ok(ssh_key) => ssh_key,
+                    Err(e) => {
+                        eprintln!("warning: skipping {email} [{key}], no ssh key: {e}");
+                        continue;
+                    }
+                };
+                let signer = AllowedSigner::for_git(email, &ssh_key, fingerprint)
+                    .ok_or_else(|| anyhow::anyhow!("invalid ssh key exported for {key}"))?;
+                allowed_signers.add(signer);
+            }
+            println!("{email} [{key}]");
+        }
+
+        if !self.dry_run {
+            allowed_signers.save(path)?;
+        }
+        Ok(())
+    }
+
+    /// Points git at the allowed signers file within every repository (or
+    /// globally) that signs in SSH mode, as only SSH signatures are verified
+    /// against it
+    fn configure_allowed_signers_file(&self, repos: &[Repository]) -> Result<()> {
+        let Some(path) = &self.allowed_signers else {
+            return Ok(());
+        };
+        let path = std::path::absolute(path)?;
+
+        let targets: Vec<Option<&Repository>> = if self.git_global_config {
+            vec![None]
+        } else {
+            repos.iter().map(Some).collect()
+        };
+
+        for repo in targets {
+            if git::signing_format(repo)?.as_deref() != Some("ssh") {
+                continue;
+            }
+            if !self.dry_run {
+                git::configure_allowed_signers(repo, &path)?;
+            }
+            let location = match repo {
+                Some(repo) => repo
+                    .workdir()
+                    .unwrap_or_else(|| repo.path())
+                    .display()
+                    .to_string(),
+                None => "global".to_string(),
+            };
+            println!("\n> Git config set (ssh): {location}");
+            println!("gpg.ssh.allowedSignersFile: {}", path.display());
+        }
+
+        Ok(())
+    }
+
+    fn install_hooks(&self, repos: &[Repository]) -> Result<()> {
+        if !self.install_pre_push_hook {
+            return Ok(());
+        }
+
+        for repo in repos {
+            let hook_path = if self.dry_run {
+                git::hooks_dir(repo)?.join("pre-push")
+            } else {
+                git::install_pre_push_hook(repo)?
+            };
+            println!("\n> Installed pre-push hook: {}", hook_path.display());
+        }
+
+        Ok(())
+    }
+
+    fn sign_artifacts(&self, private_key: &gpg::GpgPrivateKey) -> Result<()> {
+        if self.detached_signatures.is_empty() && self.clearsigned.is_empty() {
+            return Ok(());
+        }
+
+        let local_user = self.resolve_signing_key(private_key)?;
+        let detached_kind = if self.binary_signatures {
+            gpg::SignatureKind::BinaryDetached
+        } else {
+            gpg::SignatureKind::ArmoredDetached
+        };
+
+        let artifacts = self
+            .detached_signatures
+            .iter()
+            .map(|path| (path, detached_kind))
+            .chain(
+                self.clearsigned
+                    .iter()
+                    .map(|path| (path, gpg::SignatureKind::Clearsigned)),
+            );
+
+        println!("\n> Signed artifacts:");
+        for (path, kind) in artifacts {
+            let signature_path = if self.dry_run {
+                gpg::signature_path(path, kind)
+            } else {
+                gpg::sign_file(&self.gpg, path, &local_user, kind)?
+            };
+            println!("{} [{}]", signature_path.display(), local_user);
+        }
 
         Ok(())
     }
@@ -166,8 +910,8 @@ impl GpgImport {
             return Ok(());
         }
 
-        let repo = git::is_repo();
-        if !self.git_global_config && repo.is_none() {
+        let repos = self.discover_repos()?;
+        if !self.git_global_config && repos.is_empty() {
             return Ok(());
         }
 
@@ -192,60 +936,279 @@ impl GpgImport {
             commit_sign: true,
             tag_sign: true,
             push_sign: true,
+            gpg_program: self.gpg.pinned_program().map(|program| {
+                gpg::locate_program(program)
+                    .unwrap_or_else(|| program.to_path_buf())
+                    .to_string_lossy()
+                    .into_owned()
+            }),
         };
 
-        self.apply_git_config(&git_cfg, repo.as_ref())?;
-        println!("{git_cfg}");
+        self.apply_git_config(private_key, &git_cfg, &repos)?;
+        self.configure_allowed_signers_file(&repos)?;
+        self.install_hooks(&repos)?;
+        if !self.dry_run {
+            self.warn_on_diverging_gpg_program(&repos)?;
+        }
 
         Ok(())
     }
 
-    fn resolve_signing_key(&self, private_key: &gpg::GpgPrivateKey) -> Result<String> {
-        match &self.fingerprint {
-            Some(fp) => {
-                let matches_subkey = private_key
-                    .subkeys
-                    .iter()
-                    .any(|subkey| fp == &subkey.fingerprint);
+    /// Warns if git would sign with a different GnuPG binary than the one
+    /// the key was imported with, as each installation may use its own
+    /// keyring and agent, leaving git unable to find the key
+    fn warn_on_diverging_gpg_program(&self, repos: &[Repository]) -> Result<()> {
+        let imported_with = self.gpg.program();
+        let imported_with_path = gpg::resolve_program(&imported_with);
 
-                if fp != &private_key.secret_key.fingerprint && !matches_subkey {
-                    bail!(gpg::GpgError::FingerprintNotFound(fp.clone()));
-                }
-                Ok(fp.clone())
+        let targets: Vec<Option<&Repository>> = if self.git_global_config {
+            vec![None]
+        } else {
+            repos.iter().map(Some).collect()
+        };
+
+        for repo in targets {
+            let git_program =
+                git::effective_gpg_program(repo)?.unwrap_or_else(|| "gpg".to_string());
+            let git_program_path = gpg::resolve_program(Path::new(&git_program));
+            if git_program_path != imported_with_path {
+                eprintln!(
+                    "warning: git will sign with gpg.program '{}' ({}), but the key was imported with '{}' ({})",
+                    git_program,
+                    describe_resolved(git_program_path.as_deref()),
+                    imported_with.display(),
+                    describe_resolved(imported_with_path.as_deref()),
+                );
             }
-            None => Ok(private_key.secret_key.key_id.clone()),
         }
+
+        Ok(())
+    }
+
+    /// Resolves the repositories to configure: those given explicitly, or
+    /// the one discovered from the current directory, followed by their
+    /// submodules if requested. A repository reached more than once (e.g.
+    /// through two paths within it) is only configured once.
+    fn discover_repos(&self) -> Result<Vec<Repository>> {
+        let repos = if self.repos.is_empty() {
+            git::is_repo().into_iter().collect()
+        } else {
+            self.repos
+                .iter()
+                .map(|path| git::discover_repo(path))
+                .collect::<Result<Vec<_>>>()?
+        };
+
+        let mut discovered = Vec::new();
+        for repo in repos {
+            let submodules = if self.recurse_submodules {
+                git::submodule_repos(&repo)?
+            } else {
+                Vec::new()
+            };
+            discovered.push(repo);
+            discovered.extend(submodules);
+        }
+
+        let mut seen = HashSet::new();
+        discovered.retain(|repo| {
+            seen.insert(
+                repo.path()
+                    .canonicalize()
+                    .unwrap_or_else(|_| repo.path().to_path_buf()),
+            )
+        });
+        Ok(discovered)
     }
 
-    fn apply_git_config(&self, cfg: &git::SigningConfig, repo: Option<&Repository>) -> Result<()> {
+    /// Resolves the key git (and gpg) should sign with. A `--fingerprint`
+    /// may be a fingerprint or a long or short key id, of the primary key or
+    /// any subkey. A selected subkey is suffixed with `!`, forcing gpg to use
+    /// that exact subkey rather than the most recent signing subkey
+    fn resolve_signing_key(&self, private_key: &gpg::GpgPrivateKey) -> Result<String> {
+        let Some(fingerprint) = &self.fingerprint else {
+            return Ok(private_key.secret_key.key_id.clone());
+        };
+
+        let selector = normalize_key_selector(fingerprint);
+        if matches_key(&private_key.secret_key, &selector) {
+            return Ok(private_key.secret_key.fingerprint.clone());
+        }
+
+        private_key
+            .subkeys
+            .iter()
+            .find(|subkey| matches_key(subkey, &selector))
+            .map(|subkey| format!("{}!", subkey.fingerprint))
+            .ok_or_else(|| gpg::GpgError::FingerprintNotFound(fingerprint.clone()).into())
+    }
+
+    fn apply_git_config(
+        &self,
+        private_key: &gpg::GpgPrivateKey,
+        cfg: &git::SigningConfig,
+        repos: &[Repository],
+    ) -> Result<()> {
         if self.git_global_config {
+            let cfg = self.signing_config_for(private_key, cfg, None)?;
             if !self.dry_run {
-                git::configure_signing_global(cfg)?;
+                git::configure_signing_global(&cfg)?;
             }
             println!("\n> Git config set (global):");
-        } else if let Some(repo) = repo {
+            println!("{cfg}");
+            return Ok(());
+        }
+
+        for repo in repos {
+            let cfg = self.signing_config_for(private_key, cfg, Some(repo))?;
             if !self.dry_run {
-                git::configure_signing(repo, cfg)?;
+                git::configure_signing(repo, &cfg)?;
             }
-            println!("\n> Git config set (local):");
+            let location = repo.workdir().unwrap_or_else(|| repo.path());
+            println!("\n> Git config set (local): {}", location.display());
+            println!("{cfg}");
         }
 
         Ok(())
     }
+
+    /// Adapts the signing config to the gpg.format in effect. In SSH mode,
+    /// git signs with `ssh-keygen` through the gpg-agent's ssh support, so
+    /// user.signingKey has to be the SSH public key (`key::ssh-...`) of the
+    /// authentication subkey the agent serves, rather than a gpg key id
+    fn signing_config_for(
+        &self,
+        private_key: &gpg::GpgPrivateKey,
+        cfg: &git::SigningConfig,
+        repo: Option<&Repository>,
+    ) -> Result<git::SigningConfig> {
+        let mut cfg = cfg.clone();
+        if git::signing_format(repo)?.as_deref() != Some("ssh") {
+            return Ok(cfg);
+        }
+        if !self.agent_config.enable_ssh_support {
+            bail!(
+                "git signs in SSH mode (gpg.format=ssh), which requires ssh support to be \
+                 enabled so the gpg-agent serves the signing key"
+            );
+        }
+        let subkey = ssh_auth_subkey(private_key)?;
+        if !self.dry_run {
+            let ssh_key = gpg::export_ssh_key(&self.gpg, &format!("{}!", subkey.fingerprint))?;
+            cfg.key_id = format!("key::{ssh_key}");
+        }
+        Ok(cfg)
+    }
+}
+
+/// Finds the authentication subkey the gpg-agent serves to SSH clients,
+/// which is also the key git signs with in SSH mode
+fn ssh_auth_subkey(private_key: &gpg::GpgPrivateKey) -> Result<&gpg::GpgKeyDetails> {
+    private_key
+        .subkeys
+        .iter()
+        .find(|subkey| subkey.capabilities.authenticate)
+        .ok_or_else(|| {
+            anyhow::anyhow!(
+                "ssh support requires an authentication capable subkey, but the key has none"
+            )
+        })
+}
+
+/// Appends a (multiline) value to the GitHub Actions step outputs file,
+/// using a random heredoc-style delimiter so the value can't end it early
+/// and inject further outputs
+fn write_step_output(name: &str, value: &str) -> Result<()> {
+    let Some(path) = env::var_os("GITHUB_OUTPUT") else {
+        bail!("GITHUB_OUTPUT is not set; step outputs are only available within GitHub Actions");
+    };
+
+    let mut nonce = [0u8; 16];
+    getrandom::getrandom(&mut nonce)?;
+    let delimiter = format!("ghadelimiter_{}", hex::encode(nonce));
+    if value.lines().any(|line| line == delimiter) {
+        bail!("Step output {name} contains its delimiter");
+    }
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    writeln!(file, "{name}<<{delimiter}")?;
+    write!(file, "{value}")?;
+    if !value.ends_with('\n') {
+        writeln!(file)?;
+    }
+    writeln!(file, "{delimiter}")?;
+    Ok(())
+}
+
+/// Appends a variable to the GitHub Actions environment file, exporting it
+/// to later steps. Does nothing outside of GitHub Actions
+fn append_github_env(name: &str, value: &OsStr) -> Result<()> {
+    let Some(path) = env::var_os("GITHUB_ENV") else {
+        return Ok(());
+    };
+    // A line break would end the value early, and inject further variables
+    if value
+        .as_encoded_bytes()
+        .iter()
+        .any(|byte| matches!(byte, b'\n' | b'\r'))
+    {
+        bail!("{name} can't be exported to GITHUB_ENV, as its value contains a line break");
+    }
+
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    write!(file, "{name}=")?;
+    // Paths aren't necessarily valid UTF-8, so write their raw bytes
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        file.write_all(value.as_bytes())?;
+    }
+    #[cfg(not(unix))]
+    file.write_all(value.to_string_lossy().as_bytes())?;
+    writeln!(file)?;
+    Ok(())
+}
+
+/// Normalises a fingerprint or key id for comparison: whitespace, a `0x`
+/// prefix and a `!` suffix are removed, and hex digits are uppercased
+fn normalize_key_selector(selector: &str) -> String {
+    let selector: String = selector.chars().filter(|c| !c.is_whitespace()).collect();
+    let selector = selector.strip_prefix("0x").unwrap_or(&selector);
+    selector.trim_end_matches('!').to_uppercase()
+}
+
+/// Determines if a normalised selector names the given key, by its
+/// fingerprint, long (16 digit) key id or short (8 digit) key id
+fn matches_key(key: &gpg::GpgKeyDetails, selector: &str) -> bool {
+    let key_id = key.key_id.to_uppercase();
+    key.fingerprint.to_uppercase() == selector
+        || key_id == selector
+        || (selector.len() == 8 && key_id.ends_with(selector))
+}
+
+fn describe_resolved(path: Option<&Path>) -> String {
+    path.map(|path| path.display().to_string())
+        .unwrap_or_else(|| "not found".to_string())
 }
 
 /// Validates that the key actually selected for signing (as resolved by
 /// `resolve_signing_key`) isn't expired. `signing_key` is either a subkey's
-/// fingerprint (explicit `--fingerprint` selecting a subkey), the primary
-/// key's fingerprint, or the primary key's key id (default, no
-/// `--fingerprint`) -- only the first case names a subkey, so this only has
-/// something to check when `signing_key` matches one. The primary key's own
-/// expiry is already checked unconditionally by `gpg::extract_key_info`.
+/// fingerprint suffixed with `!` (explicit `--fingerprint` selecting a
+/// subkey), the primary key's fingerprint, or the primary key's key id
+/// (default, no `--fingerprint`) -- only the first case names a subkey, so
+/// this only has something to check when `signing_key` matches one. The
+/// primary key's own expiry is already checked unconditionally by
+/// `gpg::extract_key_info`.
 fn validate_signing_key_expiry(private_key: &gpg::GpgPrivateKey, signing_key: &str) -> Result<()> {
     let Some(subkey) = private_key
         .subkeys
         .iter()
-        .find(|subkey| subkey.fingerprint == signing_key)
+        .find(|subkey| subkey.fingerprint == signing_key.trim_end_matches('!'))
     else {
         return Ok(());
     };
@@ -265,16 +1228,16 @@ fn validate_signing_key_expiry(private_key: &gpg::GpgPrivateKey, signing_key: &s
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::passphrase::PassphraseSource;
     use gpg::{GpgCapabilities, GpgKeyDetails, GpgPrivateKey, GpgUid};
     use serial_test::serial;
-    use std::{env, path::Path};
+    use std::env;
     use tempfile::TempDir;
 
     /// Temporarily changes the process's current directory, restoring it
-    /// when dropped. `git::is_repo` resolves the repo via
-    /// `Repository::open(".")`, so tests exercising git configuration need
-    /// to point the process at a throwaway repo instead of this project's
-    /// own checkout.
+    /// when dropped. `git::is_repo` discovers the repo from the current
+    /// directory, so tests exercising git configuration need to point the
+    /// process at a throwaway repo instead of this project's own checkout.
     struct CwdGuard {
         original: std::path::PathBuf,
     }
@@ -357,6 +1320,108 @@ mod tests {
         assert_eq!(config.get_string("user.email").unwrap(), "batman@dc.com");
     }
 
+    #[test]
+    fn configure_git_signing_discovers_repo_from_explicit_path() {
+        let repo_dir = TempDir::new().unwrap();
+        Repository::init(repo_dir.path()).expect("Failed to init throwaway git repo");
+        let nested = repo_dir.path().join("nested");
+        std::fs::create_dir(&nested).expect("Failed to create nested directory");
+
+        let key = key_with_uid_email("batman@dc.com");
+        let import = GpgImport::new("irrelevant".to_string()).with_repos(vec![nested]);
+
+        let result = import.configure_git_signing(&key);
+        assert!(
+            result.is_ok(),
+            "Should configure the repository enclosing --repo: {:?}",
+            result.err()
+        );
+
+        let repo = Repository::open(repo_dir.path()).expect("Failed to reopen throwaway repo");
+        let config = repo.config().expect("Failed to read throwaway repo config");
+        assert_eq!(config.get_string("user.email").unwrap(), "batman@dc.com");
+    }
+
+    #[test]
+    fn configure_git_signing_rejects_explicit_path_outside_repo() {
+        let not_a_repo = TempDir::new().unwrap();
+
+        let key = key_with_uid_email("batman@dc.com");
+        let import = GpgImport::new("irrelevant".to_string())
+            .with_repos(vec![not_a_repo.path().to_path_buf()]);
+
+        let result = import.configure_git_signing(&key);
+        assert!(
+            result.is_err(),
+            "An explicit --repo that isn't a repository should be an error"
+        );
+    }
+
+    #[test]
+    fn configure_git_signing_applies_to_multiple_repos_and_submodules() {
+        let superproject_dir = TempDir::new().unwrap();
+        let superproject =
+            Repository::init(superproject_dir.path()).expect("Failed to init superproject");
+        let submodule = superproject
+            .submodule(
+                "https://example.com/batman/utility-belt.git",
+                Path::new("utility-belt"),
+                true,
+            )
+            .expect("Failed to add submodule")
+            .open()
+            .expect("Failed to open submodule");
+        let other_dir = TempDir::new().unwrap();
+        let other = Repository::init(other_dir.path()).expect("Failed to init second repo");
+
+        let key = key_with_uid_email("batman@dc.com");
+        let import = GpgImport::new("irrelevant".to_string())
+            .with_repos(vec![
+                superproject_dir.path().to_path_buf(),
+                other_dir.path().to_path_buf(),
+            ])
+            .recurse_submodules(true);
+
+        let result = import.configure_git_signing(&key);
+        assert!(
+            result.is_ok(),
+            "Should configure every repository and submodule: {:?}",
+            result.err()
+        );
+
+        for repo in [&superproject, &submodule, &other] {
+            let config = repo.config().expect("Failed to read repo config");
+            assert_eq!(config.get_string("user.email").unwrap(), "batman@dc.com");
+            assert!(config.get_bool("commit.gpgsign").unwrap());
+        }
+    }
+
+    #[test]
+    fn configure_git_signing_pins_gpg_program() {
+        let repo_dir = TempDir::new().unwrap();
+        let repo = Repository::init(repo_dir.path()).expect("Failed to init throwaway git repo");
+        let gpg_program = repo_dir.path().join("gpg2");
+        std::fs::write(&gpg_program, "").expect("Failed to create stand-in gpg binary");
+
+        let key = key_with_uid_email("batman@dc.com");
+        let import = GpgImport::new("irrelevant".to_string())
+            .with_repos(vec![repo_dir.path().to_path_buf()])
+            .with_gpg_program(Some(gpg_program.clone()));
+
+        let result = import.configure_git_signing(&key);
+        assert!(
+            result.is_ok(),
+            "Should configure gpg.program: {:?}",
+            result.err()
+        );
+
+        let config = repo.config().expect("Failed to read throwaway repo config");
+        assert_eq!(
+            config.get_string("gpg.program").unwrap(),
+            gpg_program.to_string_lossy()
+        );
+    }
+
     fn key_with_expiring_subkeys(subkeys: &[(&str, Option<i64>)]) -> GpgPrivateKey {
         GpgPrivateKey {
             uids: vec![GpgUid {
@@ -477,6 +1542,80 @@ mod tests {
         }
     }
 
+    #[test]
+    fn effective_trust_level_defaults_to_ultimate_with_auto_trust() {
+        let import = GpgImport::new("irrelevant".to_string());
+        assert_eq!(import.effective_trust_level(), None);
+
+        let import = GpgImport::new("irrelevant".to_string()).auto_trust(true);
+        assert_eq!(
+            import.effective_trust_level(),
+            Some(gpg::TrustLevel::Ultimate)
+        );
+
+        let import = GpgImport::new("irrelevant".to_string())
+            .auto_trust(true)
+            .with_trust_level(Some(gpg::TrustLevel::Marginal));
+        assert_eq!(
+            import.effective_trust_level(),
+            Some(gpg::TrustLevel::Marginal),
+            "An explicit trust level takes precedence"
+        );
+    }
+
+    fn key_passphrase(key: &str, passphrase: &str) -> KeyPassphrase {
+        KeyPassphrase {
+            key: key.to_string(),
+            source: PassphraseSource::Value(passphrase.to_string()),
+        }
+    }
+
+    #[test]
+    fn resolve_key_passphrases_prefers_key_specific_passphrase() {
+        let key = key_with_subkeys(&["FIRSTSUBKEYFPR", "SECONDSUBKEYFPR"]);
+        let import = GpgImport::new("irrelevant".to_string())
+            .with_passphrase(Some("gotham".to_string()))
+            .with_key_passphrases(vec![
+                key_passphrase("subkeygrip1", "robin"),
+                key_passphrase("0xFIRSTSUBKEYFPR", "alfred"),
+            ]);
+
+        let passphrases: Vec<(&str, String)> = import
+            .resolve_key_passphrases(&key)
+            .unwrap()
+            .into_iter()
+            .map(|(key, passphrase)| (key.keygrip.as_str(), passphrase))
+            .collect();
+        assert_eq!(
+            passphrases,
+            vec![
+                ("PRIMARYGRIP", "gotham".to_string()),
+                ("SUBKEYGRIP0", "alfred".to_string()),
+                ("SUBKEYGRIP1", "robin".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn resolve_key_passphrases_omits_keys_without_passphrase() {
+        let key = key_with_subkeys(&["FIRSTSUBKEYFPR", "SECONDSUBKEYFPR"]);
+        let import = GpgImport::new("irrelevant".to_string())
+            .with_key_passphrases(vec![key_passphrase("SUBKEYID1", "robin")]);
+
+        let passphrases = import.resolve_key_passphrases(&key).unwrap();
+        assert_eq!(passphrases.len(), 1);
+        assert_eq!(passphrases[0].0.keygrip, "SUBKEYGRIP1");
+    }
+
+    #[test]
+    fn resolve_key_passphrases_rejects_unknown_key() {
+        let key = key_with_subkeys(&["FIRSTSUBKEYFPR"]);
+        let import = GpgImport::new("irrelevant".to_string())
+            .with_key_passphrases(vec![key_passphrase("DEADBEEF", "robin")]);
+
+        assert!(import.resolve_key_passphrases(&key).is_err());
+    }
+
     #[test]
     fn resolve_signing_key_accepts_non_first_subkey_fingerprint() {
         let key = key_with_subkeys(&["FIRSTSUBKEYFPR", "SECONDSUBKEYFPR", "THIRDSUBKEYFPR"]);
@@ -489,7 +1628,47 @@ mod tests {
             "A --fingerprint matching a non-first subkey should resolve, not error: {:?}",
             result.err()
         );
-        assert_eq!(result.unwrap(), "THIRDSUBKEYFPR");
+        assert_eq!(result.unwrap(), "THIRDSUBKEYFPR!");
+    }
+
+    #[test]
+    fn resolve_signing_key_normalises_case_spacing_and_suffix() {
+        let key = key_with_subkeys(&["FIRSTSUBKEYFPR", "SECONDSUBKEYFPR"]);
+        for fingerprint in ["secondsubkeyfpr", "SECOND SUBKEY FPR", "0xSECONDSUBKEYFPR!"] {
+            let import = GpgImport::new("irrelevant".to_string())
+                .with_fingerprint(Some(fingerprint.to_string()));
+
+            let result = import.resolve_signing_key(&key);
+            assert_eq!(
+                result.unwrap(),
+                "SECONDSUBKEYFPR!",
+                "{fingerprint} should select the second subkey"
+            );
+        }
+    }
+
+    #[test]
+    fn resolve_signing_key_accepts_long_and_short_key_ids() {
+        let mut key = key_with_subkeys(&["FIRSTSUBKEYFPR", "SECONDSUBKEYFPR"]);
+        key.subkeys[1].key_id = "9C3A60B8527C5AED".to_string();
+
+        for key_id in ["9C3A60B8527C5AED", "527c5aed"] {
+            let import =
+                GpgImport::new("irrelevant".to_string()).with_fingerprint(Some(key_id.to_string()));
+
+            let result = import.resolve_signing_key(&key);
+            assert_eq!(result.unwrap(), "SECONDSUBKEYFPR!");
+        }
+    }
+
+    #[test]
+    fn resolve_signing_key_does_not_force_primary_key() {
+        let key = key_with_subkeys(&["FIRSTSUBKEYFPR"]);
+        let import = GpgImport::new("irrelevant".to_string())
+            .with_fingerprint(Some("primaryfpr".to_string()));
+
+        let result = import.resolve_signing_key(&key);
+        assert_eq!(result.unwrap(), "PRIMARYFPR");
     }
 
     #[test]
@@ -510,4 +1689,32 @@ mod tests {
         let result = import.resolve_signing_key(&key);
         assert_eq!(result.unwrap(), "PRIMARYKEYID");
     }
+
+    #[test]
+    #[serial]
+    fn append_github_env_rejects_line_breaks() {
+        let env_dir = TempDir::new().unwrap();
+        let env_path = env_dir.path().join("github_env");
+        let original_env = env::var_os("GITHUB_ENV");
+        env::set_var("GITHUB_ENV", &env_path);
+
+        let injected = append_github_env(
+            "GNUPGHOME",
+            OsStr::new("/tmp/gnupg\nBASH_ENV=/tmp/joker.sh"),
+        );
+        let carriage_return = append_github_env("GNUPGHOME", OsStr::new("/tmp/gnupg\r"));
+        let exported = append_github_env("GNUPGHOME", OsStr::new("/tmp/gotham city"));
+
+        match original_env {
+            Some(original) => env::set_var("GITHUB_ENV", original),
+            None => env::remove_var("GITHUB_ENV"),
+        }
+        assert!(injected.is_err());
+        assert!(carriage_return.is_err());
+        assert!(exported.is_ok());
+        assert_eq!(
+            fs::read_to_string(&env_path).unwrap(),
+            "GNUPGHOME=/tmp/gotham city\n"
+        );
+    }
 }
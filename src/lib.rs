@@ -1,6 +1,20 @@
+/// Module containing allowed signers based utilities
+pub mod allowed_signers;
+/// Module containing signing setup diagnostic utilities
+pub mod doctor;
 /// Module containing git based utilities
 pub mod git;
 /// Module containing gpg based utilities
 pub mod gpg;
+/// Module containing keyserver (HKP) based utilities
+pub mod hkp;
 /// Module containing import based utilities
 pub mod import;
+/// Module containing passphrase based utilities
+pub mod passphrase;
+/// Module containing signing status utilities
+pub mod status;
+/// Module containing signature verification utilities
+pub mod verify;
+/// Module containing Web Key Directory based utilities
+pub mod wkd;
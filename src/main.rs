@@ -1,7 +1,16 @@
 use anyhow::Result;
-use clap::{command, Parser, Subcommand, ValueEnum};
-use gpg_import::import::GpgImport;
+use clap::{Parser, Subcommand, ValueEnum};
+use gpg_import::{
+    doctor::{self, Doctor},
+    git,
+    gpg::{self, KeyTrust, TrustLevel},
+    import::GpgImport,
+    passphrase::{KeyPassphrase, PassphraseChange, PassphraseSource},
+    status::Status,
+    verify::{self, Verifier},
+};
 use std::io::Read;
+use std::path::{Path, PathBuf};
 use std::println;
 
 pub mod built_info {
@@ -19,17 +28,115 @@ struct Args {
     #[arg(short, long, env = "GPG_PRIVATE_KEY", value_name = "GPG_KEY")]
     key: Option<String>,
 
+    /// An ASCII armored GPG public key (optionally base64 encoded) to import
+    /// alongside the private key. Use - for stdin, @path to read from a file,
+    /// or @dir to read every .asc file within a directory. Can be repeated
+    #[arg(long, env = "GPG_PUBLIC_KEY", value_name = "GPG_KEY")]
+    public_key: Vec<String>,
+
+    /// An email address whose public key is retrieved via Web Key Directory
+    /// (WKD) and imported alongside the private key. Can be repeated
+    #[arg(long, env = "GPG_WKD", value_name = "EMAIL", value_delimiter = ',')]
+    wkd: Vec<String>,
+
+    /// Retrieve WKD keys from this base URL (e.g. a local mirror), rather than
+    /// the domain of each email address
+    #[arg(long, env = "GPG_WKD_BASE_URL", value_name = "URL")]
+    wkd_base_url: Option<String>,
+
+    /// The keyserver (hkp://, hkps://, http:// or https://) to retrieve public
+    /// keys from and publish the imported key to
+    #[arg(long, env = "GPG_KEYSERVER", value_name = "URL")]
+    keyserver: Option<String>,
+
+    /// The fingerprint of a public key to retrieve from the keyserver and
+    /// import alongside the private key. Can be repeated
+    #[arg(
+        long,
+        env = "GPG_KEYSERVER_KEY",
+        value_name = "FINGERPRINT",
+        value_delimiter = ',',
+        requires = "keyserver"
+    )]
+    keyserver_key: Vec<String>,
+
+    /// Publish the public part of the imported GPG private key to the keyserver
+    #[arg(long, env = "GPG_PUBLISH", requires = "keyserver")]
+    publish: bool,
+
     /// The passphrase of the GPG private key if set
     #[arg(short, long, env = "GPG_PASSPHRASE")]
     passphrase: Option<String>,
 
-    /// The fingerprint of a specific key or subkey to use for signing
+    /// Read the passphrase from the first line of a file. Use - for stdin
+    #[arg(
+        long,
+        env = "GPG_PASSPHRASE_FILE",
+        value_name = "PATH",
+        conflicts_with_all = ["passphrase", "passphrase_fd", "passphrase_command"]
+    )]
+    passphrase_file: Option<PathBuf>,
+
+    /// Read the passphrase from the first line of an open file descriptor
+    #[arg(
+        long,
+        env = "GPG_PASSPHRASE_FD",
+        value_name = "FD",
+        conflicts_with_all = ["passphrase", "passphrase_command"]
+    )]
+    passphrase_fd: Option<u32>,
+
+    /// Run a shell command and read the passphrase from the first line of its
+    /// stdout, e.g. a secrets manager CLI
+    #[arg(
+        long,
+        env = "GPG_PASSPHRASE_COMMAND",
+        value_name = "COMMAND",
+        conflicts_with = "passphrase"
+    )]
+    passphrase_command: Option<String>,
+
+    /// A passphrase for a specific key or subkey, identified by its keygrip,
+    /// fingerprint or key id, taking precedence over --passphrase. Use
+    /// KEY=@path to read it from a file. Can be repeated
+    #[arg(long, env = "GPG_KEY_PASSPHRASE", value_name = "KEY=PASSPHRASE")]
+    key_passphrase: Vec<KeyPassphrase>,
+
+    /// Once the passphrase is preset, strip the key's passphrase protection or
+    /// rotate it to a random passphrase preset within the agent, so signing
+    /// survives the agent cache being flushed. Intended for throwaway keyrings
+    #[arg(long, env = "GPG_PASSPHRASE_CHANGE", value_enum)]
+    passphrase_change: Option<PassphraseChangeArg>,
+
+    /// Write the rotated passphrase to this file (only readable by the current
+    /// user), as the key can't otherwise be unlocked once the agent cache is
+    /// flushed
+    #[arg(long, env = "GPG_ROTATED_PASSPHRASE_FILE", value_name = "PATH")]
+    rotated_passphrase_file: Option<PathBuf>,
+
+    /// The fingerprint, or long or short key id, of a specific key or subkey to
+    /// use for signing. A subkey is forced with the ! suffix
     #[arg(short, long, env = "GPG_FINGERPRINT", value_name = "FINGERPRINT")]
     fingerprint: Option<String>,
 
     /// A level of trust to associate with the GPG private key
     #[arg(short, long, env = "GPG_TRUST_LEVEL", value_enum)]
-    trust_level: Option<TrustLevel>,
+    trust_level: Option<TrustLevelArg>,
+
+    /// Ultimately trust the imported GPG private key, unless a trust level is
+    /// explicitly provided
+    #[arg(long, env = "GPG_AUTO_TRUST")]
+    auto_trust: bool,
+
+    /// A level of trust to associate with another key within the keyring, such
+    /// as a public key imported alongside. Can be repeated
+    #[arg(
+        long,
+        env = "GPG_TRUST_KEY",
+        value_name = "FINGERPRINT=LEVEL",
+        value_delimiter = ','
+    )]
+    trust_key: Vec<KeyTrust>,
 
     /// Skip all GPG configuration for the detected git repository
     #[arg(short, long, env = "GPG_SKIP_GIT")]
@@ -39,6 +146,18 @@ struct Args {
     #[arg(long, env = "GPG_GIT_GLOBAL_CONFIG")]
     git_global_config: bool,
 
+    /// A path within a git repository to configure, rather than discovering
+    /// it from the current directory (GIT_DIR and GIT_WORK_TREE are honoured
+    /// when omitted). Can be repeated to configure multiple repositories.
+    /// Paths within GPG_GIT_REPO are separated in the same way as PATH, so
+    /// paths containing commas are kept intact
+    #[arg(long, value_name = "PATH")]
+    repo: Vec<PathBuf>,
+
+    /// Also configure every submodule (recursively) of each git repository
+    #[arg(long, env = "GPG_GIT_RECURSE_SUBMODULES")]
+    recurse_submodules: bool,
+
     /// Override the committer name instead of using the value from the GPG key
     #[arg(long, env = "GPG_GIT_COMMITTER_NAME", value_name = "NAME")]
     git_committer_name: Option<String>,
@@ -47,13 +166,181 @@ struct Args {
     #[arg(long, env = "GPG_GIT_COMMITTER_EMAIL", value_name = "EMAIL")]
     git_committer_email: Option<String>,
 
+    /// The GnuPG binary to use for the import and for signing with git (written
+    /// to gpg.program), rather than resolving gpg from PATH
+    #[arg(long, env = "GPG_PROGRAM", value_name = "PATH")]
+    gpg_program: Option<PathBuf>,
+
+    /// The gpg home directory to import into and configure, passed as
+    /// --homedir to every gpg invocation, rather than GNUPGHOME or ~/.gnupg
+    #[arg(long, env = "GPG_HOMEDIR", value_name = "PATH")]
+    homedir: Option<PathBuf>,
+
+    /// Seconds a cached passphrase remains valid since it was last used
+    #[arg(long, env = "GPG_DEFAULT_CACHE_TTL", value_name = "SECONDS", default_value_t = gpg::DEFAULT_CACHE_TTL)]
+    default_cache_ttl: u64,
+
+    /// Seconds a passphrase remains cached by the agent, regardless of use
+    #[arg(long, env = "GPG_MAX_CACHE_TTL", value_name = "SECONDS", default_value_t = gpg::MAX_CACHE_TTL)]
+    max_cache_ttl: u64,
+
+    /// Prevent the agent from caching passphrases within an external password
+    /// manager
+    #[arg(long, env = "GPG_NO_ALLOW_EXTERNAL_CACHE")]
+    no_allow_external_cache: bool,
+
+    /// An absolute path to the pinentry program used by the agent whenever gpg
+    /// isn't in loopback mode
+    #[arg(long, env = "GPG_PINENTRY_PROGRAM", value_name = "PATH")]
+    pinentry_program: Option<PathBuf>,
+
+    /// Let the agent act as an OpenSSH agent for the key's authentication
+    /// subkey, exposing SSH_AUTH_SOCK (and writing it to GITHUB_ENV when set)
+    /// and printing the OpenSSH public key, e.g. for use as a deploy key
+    #[arg(long, env = "GPG_ENABLE_SSH_SUPPORT")]
+    enable_ssh_support: bool,
+
+    /// Write the ASCII armored public key of the imported GPG private key (or
+    /// selected subkey) to a file, e.g. for registering with GitHub or GitLab
+    #[arg(long, env = "GPG_EXPORT_PUBLIC", value_name = "PATH")]
+    export_public: Option<PathBuf>,
+
+    /// Write the ASCII armored public key to a GitHub Actions step output with
+    /// the given name
+    #[arg(long, env = "GPG_EXPORT_PUBLIC_OUTPUT", value_name = "NAME")]
+    export_public_output: Option<String>,
+
+    /// Strip all but the most recent self-signatures from the exported public
+    /// key
+    #[arg(long, env = "GPG_EXPORT_MINIMAL")]
+    export_minimal: bool,
+
+    /// Maintain an allowed signers file, mapping the email of every uid of the
+    /// imported keys onto their SSH public key. Written to
+    /// gpg.ssh.allowedSignersFile when git signs in SSH mode (gpg.format=ssh)
+    #[arg(long, env = "GPG_ALLOWED_SIGNERS", value_name = "PATH")]
+    allowed_signers: Option<PathBuf>,
+
+    /// Install a pre-push hook into each git repository (respecting
+    /// core.hooksPath) that refuses to push commits not signed by the
+    /// configured user.signingKey
+    #[arg(long, env = "GPG_INSTALL_PRE_PUSH_HOOK")]
+    install_pre_push_hook: bool,
+
     /// Simulate the import without making changes
     #[arg(long, env = "GPG_DRY_RUN")]
     dry_run: bool,
 }
 
+/// The trust levels accepted by `--trust-level`, as presented by
+/// `gpg --edit-key`
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum TrustLevelArg {
+    #[value(help = "I don't know or won't say", name = "1", alias = "undefined")]
+    Undefined,
+    #[value(help = "I do NOT trust", name = "2", alias = "never")]
+    Never,
+    #[value(help = "I trust marginally", name = "3", alias = "marginal")]
+    Marginal,
+    #[value(help = "I trust fully", name = "4", alias = "full")]
+    Full,
+    #[value(help = "I trust ultimately", name = "5", alias = "ultimate")]
+    Ultimate,
+}
+
+impl From<TrustLevelArg> for TrustLevel {
+    fn from(level: TrustLevelArg) -> Self {
+        match level {
+            TrustLevelArg::Undefined => TrustLevel::Undefined,
+            TrustLevelArg::Never => TrustLevel::Never,
+            TrustLevelArg::Marginal => TrustLevel::Marginal,
+            TrustLevelArg::Full => TrustLevel::Full,
+            TrustLevelArg::Ultimate => TrustLevel::Ultimate,
+        }
+    }
+}
+
+/// The changes accepted by `--passphrase-change`
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum PassphraseChangeArg {
+    /// Remove the passphrase protection entirely
+    Strip,
+    /// Re-protect with a fresh random passphrase, preset within the agent
+    Rotate,
+}
+
+impl From<PassphraseChangeArg> for PassphraseChange {
+    fn from(change: PassphraseChangeArg) -> Self {
+        match change {
+            PassphraseChangeArg::Strip => PassphraseChange::Strip,
+            PassphraseChangeArg::Rotate => PassphraseChange::Rotate,
+        }
+    }
+}
+
 #[derive(Subcommand, Debug)]
 enum Commands {
+    /// Import the GPG private key, then sign each file with it (or the
+    /// selected subkey), writing detached signatures alongside them
+    Sign {
+        /// The files to produce detached signatures for, e.g. release tarballs
+        #[arg(required_unless_present = "clearsign")]
+        files: Vec<PathBuf>,
+
+        /// Produce binary (.sig) rather than ASCII armored (.asc) detached
+        /// signatures
+        #[arg(long)]
+        binary: bool,
+
+        /// A file to clearsign (written to <FILE>.asc), e.g. a checksums file.
+        /// Can be repeated
+        #[arg(long, value_name = "FILE")]
+        clearsign: Vec<PathBuf>,
+    },
+    /// Verify the signatures of every commit within a revision range against
+    /// the keyring, failing if any commit is unsigned or not signed by an
+    /// allowed key
+    Verify {
+        /// A revision range (e.g. origin/main..HEAD or origin/main...HEAD), or
+        /// a single revision to verify every commit reachable from it
+        #[arg(default_value = "HEAD")]
+        range: String,
+
+        /// A path within the git repository to verify, rather than discovering
+        /// it from the current directory
+        #[arg(long, value_name = "PATH")]
+        repo: Option<PathBuf>,
+
+        /// Also verify annotated tags pointing at a commit within the range
+        #[arg(long)]
+        tags: bool,
+
+        /// The fingerprint of a key (or subkey) allowed to sign. Any key within
+        /// the keyring is allowed when omitted. Can be repeated
+        #[arg(
+            long,
+            env = "GPG_VERIFY_ALLOW",
+            value_name = "FINGERPRINT",
+            value_delimiter = ','
+        )]
+        allow: Vec<String>,
+    },
+    /// Diagnose why signing may fail, checking the gpg installation, its
+    /// configuration and agent, and the key git is configured to sign with
+    Doctor {
+        /// A path within the git repository whose config is checked, rather
+        /// than discovering it from the current directory
+        #[arg(long, value_name = "PATH")]
+        repo: Option<PathBuf>,
+    },
+    /// Show the effective git signing config, where each value was set,
+    /// and the uid, expiry and passphrase state of the signing key
+    Status {
+        /// A path within the git repository whose config is shown, rather
+        /// than discovering it from the current directory
+        #[arg(long, value_name = "PATH")]
+        repo: Option<PathBuf>,
+    },
     /// Print build time version information
     Version {
         /// Only print the version number
@@ -62,30 +349,15 @@ enum Commands {
     },
 }
 
-#[derive(Clone, Debug, ValueEnum)]
-enum TrustLevel {
-    #[value(help = "I don't know or won't say", name = "1")]
-    Undefined,
-    #[value(help = "I do NOT trust", name = "2")]
-    Never,
-    #[value(help = "I trust marginally", name = "3")]
-    Marginal,
-    #[value(help = "I trust fully", name = "4")]
-    Full,
-    #[value(help = "I trust ultimately", name = "5")]
-    Ultimate,
-}
-
-impl TrustLevel {
-    fn trust_db_value(&self) -> u8 {
-        match self {
-            TrustLevel::Undefined => 1,
-            TrustLevel::Never => 2,
-            TrustLevel::Marginal => 3,
-            TrustLevel::Full => 4,
-            TrustLevel::Ultimate => 5,
-        }
+/// Resolves the repositories to configure, falling back to the paths
+/// within GPG_GIT_REPO when none were given on the command line
+fn resolve_repos(repos: Vec<PathBuf>) -> Vec<PathBuf> {
+    if !repos.is_empty() {
+        return repos;
     }
+    std::env::var_os("GPG_GIT_REPO")
+        .map(|paths| std::env::split_paths(&paths).collect())
+        .unwrap_or_default()
 }
 
 /// Resolves the key input from stdin, a file, or a direct value.
@@ -102,10 +374,41 @@ fn resolve_key_input(key: &str) -> Result<String> {
     }
 }
 
+/// Resolves each public key input in the same way as [`resolve_key_input`],
+/// additionally expanding @dir into every .asc file within that directory
+fn resolve_public_key_inputs(inputs: &[String]) -> Result<Vec<String>> {
+    let mut public_keys = Vec::new();
+    for input in inputs {
+        match input.strip_prefix('@').map(Path::new) {
+            Some(dir) if dir.is_dir() => {
+                let mut paths = std::fs::read_dir(dir)
+                    .map_err(|e| {
+                        anyhow::anyhow!("Failed to read key directory '{}': {}", dir.display(), e)
+                    })?
+                    .map(|entry| entry.map(|entry| entry.path()))
+                    .collect::<std::io::Result<Vec<_>>>()?;
+                paths.retain(|path| path.extension().is_some_and(|ext| ext == "asc"));
+                paths.sort();
+
+                if paths.is_empty() {
+                    anyhow::bail!("No .asc files found in key directory '{}'", dir.display());
+                }
+                for path in paths {
+                    public_keys.push(std::fs::read_to_string(&path).map_err(|e| {
+                        anyhow::anyhow!("Failed to read key file '{}': {}", path.display(), e)
+                    })?);
+                }
+            }
+            _ => public_keys.push(resolve_key_input(input)?),
+        }
+    }
+    Ok(public_keys)
+}
+
 fn main() -> Result<()> {
     let args = Args::parse();
 
-    match args.command {
+    let (detached_signatures, binary_signatures, clearsigned) = match args.command {
         Some(Commands::Version { short }) => {
             if short {
                 print_version_short();
@@ -114,22 +417,135 @@ fn main() -> Result<()> {
             }
             return Ok(());
         }
+        Some(Commands::Verify {
+            range,
+            repo,
+            tags,
+            allow,
+        }) => {
+            let gpg_context = gpg::GpgContext::default()
+                .with_program(args.gpg_program)
+                .with_home_dir(args.homedir);
+            let repo = match repo {
+                Some(path) => git::discover_repo(&path)?,
+                None => {
+                    git::is_repo().ok_or_else(|| anyhow::anyhow!("Not within a git repository"))?
+                }
+            };
+
+            let verifications = Verifier::new(&repo)
+                .with_gpg_context(gpg_context)
+                .with_allowed_signers(allow)
+                .verify_tags(tags)
+                .verify_range(&range)?;
+            return verify::report(&verifications);
+        }
+        Some(Commands::Doctor { repo }) => {
+            let gpg_context = gpg::GpgContext::default()
+                .with_program(args.gpg_program)
+                .with_home_dir(args.homedir);
+            let repo = match repo {
+                Some(path) => Some(git::discover_repo(&path)?),
+                None => git::is_repo(),
+            };
+            return doctor::report(
+                &Doctor::new(repo.as_ref())
+                    .with_gpg_context(gpg_context)
+                    .run(),
+            );
+        }
+        Some(Commands::Status { repo }) => {
+            let gpg_context = gpg::GpgContext::default()
+                .with_program(args.gpg_program)
+                .with_home_dir(args.homedir);
+            let repo = match repo {
+                Some(path) => Some(git::discover_repo(&path)?),
+                None => git::is_repo(),
+            };
+            print!("{}", Status::read(&gpg_context, repo.as_ref())?);
+            return Ok(());
+        }
+        Some(Commands::Sign {
+            files,
+            binary,
+            clearsign,
+        }) => (files, binary, clearsign),
         None => {
             // Continue with normal GPG import flow
+            (Vec::new(), false, Vec::new())
         }
-    }
+    };
 
     let key_input = args.key.ok_or_else(|| anyhow::anyhow!("Key is required for GPG import. Use --key or set GPG_PRIVATE_KEY environment variable."))?;
+    let passphrase_source = if let Some(path) = args.passphrase_file {
+        Some(PassphraseSource::File(path))
+    } else if let Some(fd) = args.passphrase_fd {
+        Some(PassphraseSource::Fd(fd))
+    } else if let Some(command) = args.passphrase_command {
+        Some(PassphraseSource::Command(command))
+    } else {
+        args.passphrase.map(PassphraseSource::Value)
+    };
+
+    let stdin_inputs = [key_input == "-"]
+        .into_iter()
+        .chain(args.public_key.iter().map(|input| input == "-"))
+        .chain(passphrase_source.iter().map(PassphraseSource::reads_stdin))
+        .chain(
+            args.key_passphrase
+                .iter()
+                .map(|key_passphrase| key_passphrase.source.reads_stdin()),
+        )
+        .filter(|stdin| *stdin)
+        .count();
+    if stdin_inputs > 1 {
+        anyhow::bail!(
+            "Only one of --key, --public-key, --passphrase-file, --passphrase-fd or \
+             --key-passphrase can be read from stdin"
+        );
+    }
     let key = resolve_key_input(&key_input)?;
+    let public_keys = resolve_public_key_inputs(&args.public_key)?;
+    let passphrase = passphrase_source.map(|source| source.read()).transpose()?;
 
     GpgImport::new(key)
-        .with_passphrase(args.passphrase)
+        .with_public_keys(public_keys)
+        .with_wkd_emails(args.wkd)
+        .with_wkd_base_url(args.wkd_base_url)
+        .with_keyserver(args.keyserver)
+        .with_keyserver_keys(args.keyserver_key)
+        .publish(args.publish)
+        .with_passphrase(passphrase)
+        .with_key_passphrases(args.key_passphrase)
+        .with_passphrase_change(args.passphrase_change.map(PassphraseChange::from))
+        .with_rotated_passphrase_file(args.rotated_passphrase_file)
         .with_fingerprint(args.fingerprint)
-        .with_trust_level(args.trust_level.map(|t| t.trust_db_value()))
+        .with_trust_level(args.trust_level.map(TrustLevel::from))
+        .auto_trust(args.auto_trust)
+        .with_key_trust(args.trust_key)
         .skip_git(args.skip_git)
         .git_global_config(args.git_global_config)
+        .with_repos(resolve_repos(args.repo))
+        .recurse_submodules(args.recurse_submodules)
         .with_git_committer_name(args.git_committer_name)
         .with_git_committer_email(args.git_committer_email)
+        .with_gpg_program(args.gpg_program)
+        .with_home_dir(args.homedir)
+        .with_agent_config(gpg::AgentConfig {
+            default_cache_ttl: args.default_cache_ttl,
+            max_cache_ttl: args.max_cache_ttl,
+            allow_external_cache: !args.no_allow_external_cache,
+            pinentry_program: args.pinentry_program,
+            enable_ssh_support: args.enable_ssh_support,
+        })
+        .with_export_public(args.export_public)
+        .with_export_public_output(args.export_public_output)
+        .export_minimal(args.export_minimal)
+        .with_allowed_signers(args.allowed_signers)
+        .install_pre_push_hook(args.install_pre_push_hook)
+        .with_detached_signatures(detached_signatures)
+        .binary_signatures(binary_signatures)
+        .with_clearsigned(clearsigned)
         .dry_run(args.dry_run)
         .import()
 }
@@ -0,0 +1,248 @@
+use anyhow::{anyhow, bail, Result};
+use base64::{engine::general_purpose, Engine as _};
+use std::{
+    fs,
+    io::Read,
+    path::{Path, PathBuf},
+    process::Command,
+    str::FromStr,
+};
+use thiserror::Error;
+
+/// Errors that can occur when parsing passphrase options
+#[derive(Clone, Debug, Eq, Error, PartialEq)]
+pub enum PassphraseError {
+    /// A key passphrase wasn't of the form `KEY=PASSPHRASE`
+    #[error("invalid key passphrase '{0}'; expected KEY=PASSPHRASE or KEY=@path")]
+    InvalidKeyPassphrase(String),
+}
+
+/// Where the passphrase of a key is read from. Every source other than an
+/// explicit value keeps the passphrase out of `ps` output and the
+/// environment inherited by child processes
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PassphraseSource {
+    /// The passphrase itself
+    Value(String),
+    /// A file containing the passphrase, or `-` for stdin
+    File(PathBuf),
+    /// An open file descriptor inherited from the parent process
+    Fd(u32),
+    /// A shell command that prints the passphrase to stdout
+    Command(String),
+}
+
+impl PassphraseSource {
+    /// Reads the passphrase. As with gpg's own `--passphrase-file` and
+    /// `--passphrase-fd`, only the first line is used, so trailing newlines
+    /// written by editors or `echo` aren't treated as part of the passphrase
+    pub fn read(&self) -> Result<String> {
+        let contents = match self {
+            PassphraseSource::Value(value) => return Ok(value.clone()),
+            PassphraseSource::File(path) if path == Path::new("-") => {
+                let mut buffer = String::new();
+                std::io::stdin().read_to_string(&mut buffer)?;
+                buffer
+            }
+            PassphraseSource::File(path) => fs::read_to_string(path).map_err(|e| {
+                anyhow!("Failed to read passphrase file '{}': {}", path.display(), e)
+            })?,
+            PassphraseSource::Fd(fd) => {
+                let path = format!("/dev/fd/{fd}");
+                fs::read_to_string(&path)
+                    .map_err(|e| anyhow!("Failed to read passphrase from fd {}: {}", fd, e))?
+            }
+            PassphraseSource::Command(command) => run_passphrase_command(command)?,
+        };
+
+        Ok(first_line(&contents).to_string())
+    }
+
+    /// Determines if the passphrase is read from stdin, either as the `-`
+    /// file or through file descriptor 0
+    pub fn reads_stdin(&self) -> bool {
+        match self {
+            PassphraseSource::File(path) => path == Path::new("-"),
+            PassphraseSource::Fd(fd) => *fd == 0,
+            PassphraseSource::Value(_) | PassphraseSource::Command(_) => false,
+        }
+    }
+}
+
+/// A change to the protection of the imported secret key, made once its
+/// passphrase has been preset. Intended for throwaway keyrings, so signing
+/// keeps working after the agent cache is flushed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PassphraseChange {
+    /// Remove the passphrase protection entirely
+    Strip,
+    /// Re-protect with a fresh random passphrase, preset within the agent
+    Rotate,
+}
+
+/// A passphrase for a single key or subkey, identified by its keygrip,
+/// fingerprint or key id. Parsed from `KEY=PASSPHRASE`, or `KEY=@path` to
+/// read the passphrase from a file
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeyPassphrase {
+    /// The keygrip, fingerprint or key id of the key
+    pub key: String,
+    /// Where the passphrase of the key is read from
+    pub source: PassphraseSource,
+}
+
+impl FromStr for KeyPassphrase {
+    type Err = PassphraseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || PassphraseError::InvalidKeyPassphrase(s.to_string());
+        let (key, passphrase) = s.split_once('=').ok_or_else(invalid)?;
+
+        let key: String = key.chars().filter(|c| !c.is_whitespace()).collect();
+        let hex = key.strip_prefix("0x").unwrap_or(&key).trim_end_matches('!');
+        if hex.is_empty() || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(invalid());
+        }
+
+        let source = match passphrase.strip_prefix('@') {
+            Some(path) if !path.is_empty() => PassphraseSource::File(PathBuf::from(path)),
+            _ => PassphraseSource::Value(passphrase.to_string()),
+        };
+        Ok(KeyPassphrase { key, source })
+    }
+}
+
+/// Generates a random passphrase from 24 bytes of the OS's strong
+/// randomness, base64 encoded
+pub fn generate_passphrase() -> Result<String> {
+    let mut bytes = [0u8; 24];
+    getrandom::getrandom(&mut bytes)?;
+    Ok(general_purpose::STANDARD.encode(bytes))
+}
+
+fn run_passphrase_command(command: &str) -> Result<String> {
+    let output = Command::new("sh").args(["-c", command]).output()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        bail!(
+            "Passphrase command failed with {}: {}",
+            output.status,
+            stderr.trim()
+        );
+    }
+    Ok(String::from_utf8(output.stdout)?)
+}
+
+fn first_line(contents: &str) -> &str {
+    contents.lines().next().unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn read_value_is_used_verbatim() {
+        let source = PassphraseSource::Value(" gotham \n".to_string());
+        assert_eq!(source.read().unwrap(), " gotham \n");
+    }
+
+    #[test]
+    fn read_file_uses_first_line() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("passphrase");
+        fs::write(&path, "gotham city\r\nignored\n").unwrap();
+
+        let source = PassphraseSource::File(path);
+        assert_eq!(source.read().unwrap(), "gotham city");
+    }
+
+    #[test]
+    fn reads_stdin_through_dash_file_or_fd_zero() {
+        assert!(PassphraseSource::File(PathBuf::from("-")).reads_stdin());
+        assert!(PassphraseSource::Fd(0).reads_stdin());
+        assert!(!PassphraseSource::Fd(3).reads_stdin());
+        assert!(!PassphraseSource::File(PathBuf::from("./-")).reads_stdin());
+        assert!(!PassphraseSource::Value("-".to_string()).reads_stdin());
+        assert!(!PassphraseSource::Command("-".to_string()).reads_stdin());
+    }
+
+    #[test]
+    fn read_missing_file_fails() {
+        let source = PassphraseSource::File(PathBuf::from("/does/not/exist"));
+        assert!(source.read().is_err());
+    }
+
+    #[test]
+    fn read_fd_inherited_from_parent() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("passphrase");
+        fs::write(&path, "gotham\n").unwrap();
+
+        // Any open descriptor will do, so open the file and read it back
+        // through its descriptor rather than its path
+        let file = fs::File::open(&path).unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::io::AsRawFd;
+            let source = PassphraseSource::Fd(file.as_raw_fd() as u32);
+            assert_eq!(source.read().unwrap(), "gotham");
+        }
+    }
+
+    #[test]
+    fn read_command_stdout() {
+        let source = PassphraseSource::Command("printf 'gotham\\n'".to_string());
+        assert_eq!(source.read().unwrap(), "gotham");
+    }
+
+    #[test]
+    fn parse_key_passphrase() {
+        assert_eq!(
+            "4AC8 E7E7 FD8B 405D F276 1726 D296 F98C 9B77 8875=gotham=city"
+                .parse::<KeyPassphrase>()
+                .unwrap(),
+            KeyPassphrase {
+                key: "4AC8E7E7FD8B405DF2761726D296F98C9B778875".to_string(),
+                source: PassphraseSource::Value("gotham=city".to_string()),
+            }
+        );
+        assert_eq!(
+            "0xB8527C5AED483BE3=@/run/secrets/robin"
+                .parse::<KeyPassphrase>()
+                .unwrap(),
+            KeyPassphrase {
+                key: "0xB8527C5AED483BE3".to_string(),
+                source: PassphraseSource::File(PathBuf::from("/run/secrets/robin")),
+            }
+        );
+    }
+
+    #[test]
+    fn parse_key_passphrase_rejects_invalid_key() {
+        for value in ["gotham", "=gotham", "batman@dc.com=gotham"] {
+            assert_eq!(
+                value.parse::<KeyPassphrase>(),
+                Err(PassphraseError::InvalidKeyPassphrase(value.to_string()))
+            );
+        }
+    }
+
+    #[test]
+    fn read_failing_command() {
+        let source = PassphraseSource::Command("echo nope >&2; exit 3".to_string());
+        let err = source.read().unwrap_err().to_string();
+        assert!(err.contains("nope"), "Unexpected error: {err}");
+    }
+
+    #[test]
+    fn generate_passphrase_is_random_base64() {
+        let first = generate_passphrase().unwrap();
+        let second = generate_passphrase().unwrap();
+
+        assert_eq!(first.len(), 32);
+        assert_eq!(general_purpose::STANDARD.decode(&first).unwrap().len(), 24);
+        assert_ne!(first, second);
+    }
+}
@@ -0,0 +1,349 @@
+use crate::{
+    doctor::{self, SshKeyStatus},
+    git::{self, ConfigValue},
+    gpg::{self, AgentKeyInfo, GpgContext, GpgKeyDetails, GpgUid, KeyProtection},
+};
+use anyhow::Result;
+use git2::Repository;
+use std::fmt::{self, Display};
+
+/// Whether the gpg-agent can use a key without prompting for its passphrase
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PassphraseStatus {
+    /// The passphrase is cached by the agent
+    Cached,
+    /// The key isn't protected by a passphrase
+    Unprotected,
+    /// The passphrase isn't cached, so signing will prompt for it
+    NotCached,
+    /// The agent couldn't be queried
+    Unknown,
+}
+
+impl Display for PassphraseStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PassphraseStatus::Cached => write!(f, "cached"),
+            PassphraseStatus::Unprotected => write!(f, "unprotected"),
+            PassphraseStatus::NotCached => write!(f, "not cached"),
+            PassphraseStatus::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+/// A key or subkey git may sign with
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SigningKeyStatus {
+    /// A fingerprint used for verification of the key
+    pub fingerprint: String,
+    /// A 20-byte hash identifier for the key
+    pub keygrip: String,
+    /// The date for when the key will expire
+    pub expiration_date: Option<i64>,
+    /// Whether the agent holds the passphrase of the key
+    pub passphrase: PassphraseStatus,
+}
+
+/// The outcome of resolving `user.signingKey` against the secret keyring
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SigningKey {
+    /// user.signingKey isn't set
+    Unset,
+    /// user.signingKey doesn't resolve to a signing capable secret key
+    Unresolved {
+        /// The configured user.signingKey
+        signing_key: String,
+        /// Why the key couldn't be resolved
+        reason: String,
+    },
+    /// user.signingKey is an SSH key, as git signs in SSH mode
+    /// (gpg.format=ssh)
+    Ssh {
+        /// The configured user.signingKey
+        signing_key: String,
+        /// Whether git can sign with the key
+        status: SshKeyStatus,
+    },
+    /// user.signingKey resolved to one or more signing capable keys
+    Resolved {
+        /// The configured user.signingKey
+        signing_key: String,
+        /// The primary user identity of the secret key
+        uid: GpgUid,
+        /// The keys gpg may sign with
+        keys: Vec<SigningKeyStatus>,
+    },
+}
+
+/// The effective git signing configuration, and the state of the key it
+/// signs with
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Status {
+    /// The signing related git config values, with their origin
+    pub config: Vec<ConfigValue>,
+    /// The key git signs with
+    pub signing_key: SigningKey,
+}
+
+impl Status {
+    /// Reads the signing configuration as seen from the given repository,
+    /// or from the global and system config when outside of a repository,
+    /// resolving the signing key with the given GnuPG installation
+    pub fn read(ctx: &GpgContext, repo: Option<&Repository>) -> Result<Self> {
+        let config = git::signing_config(repo)?;
+        let ssh = git::signing_format(repo)?.as_deref() == Some("ssh");
+        let signing_key = match git::signing_key(repo)? {
+            Some(signing_key) if ssh => resolve_ssh_signing_key(ctx, signing_key),
+            Some(signing_key) => resolve_signing_key(ctx, signing_key),
+            None => SigningKey::Unset,
+        };
+        Ok(Self {
+            config,
+            signing_key,
+        })
+    }
+}
+
+impl Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "> Git signing config:")?;
+        if self.config.is_empty() {
+            writeln!(f, "(none)")?;
+        }
+        for value in &self.config {
+            writeln!(f, "{value}")?;
+        }
+
+        writeln!(f, "\n> Signing key:")?;
+        match &self.signing_key {
+            SigningKey::Unset => writeln!(f, "user.signingKey is not set")?,
+            SigningKey::Unresolved {
+                signing_key,
+                reason,
+            } => writeln!(f, "{signing_key} could not be resolved: {reason}")?,
+            SigningKey::Ssh {
+                signing_key,
+                status,
+            } => {
+                writeln!(f, "signing_key:    {signing_key}")?;
+                writeln!(f, "ssh:            {status}")?;
+            }
+            SigningKey::Resolved {
+                signing_key,
+                uid,
+                keys,
+            } => {
+                writeln!(f, "signing_key:    {signing_key}")?;
+                writeln!(f, "user:           {uid}")?;
+                for key in keys {
+                    writeln!(f, "fingerprint:    {}", key.fingerprint)?;
+                    writeln!(f, "keygrip:        {}", key.keygrip)?;
+                    match key.expiration_date {
+                        Some(expiration_date) => writeln!(
+                            f,
+                            "expires_on:     {}",
+                            gpg::format_expiration_in_days(expiration_date)
+                        )?,
+                        None => writeln!(f, "expires_on:     never")?,
+                    }
+                    writeln!(f, "passphrase:     {}", key.passphrase)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn resolve_ssh_signing_key(ctx: &GpgContext, signing_key: String) -> SigningKey {
+    match doctor::ssh_key_status(ctx, &signing_key) {
+        Ok(status) => SigningKey::Ssh {
+            signing_key,
+            status,
+        },
+        Err(e) => SigningKey::Unresolved {
+            signing_key,
+            reason: e.to_string(),
+        },
+    }
+}
+
+fn resolve_signing_key(ctx: &GpgContext, signing_key: String) -> SigningKey {
+    let private_key = match gpg::extract_key_info(ctx, signing_key.trim_end_matches('!')) {
+        Ok(private_key) => private_key,
+        Err(e) => {
+            return SigningKey::Unresolved {
+                signing_key,
+                reason: e.to_string(),
+            }
+        }
+    };
+
+    let keys = doctor::signing_keys(&private_key, &signing_key);
+    if keys.is_empty() {
+        return SigningKey::Unresolved {
+            signing_key,
+            reason: "no signing capable key or subkey".to_string(),
+        };
+    }
+
+    let agent_keys = gpg::agent_key_info(ctx).ok();
+    let keys = keys
+        .into_iter()
+        .map(|key| SigningKeyStatus {
+            fingerprint: key.fingerprint.clone(),
+            keygrip: key.keygrip.clone(),
+            expiration_date: key.expiration_date,
+            passphrase: passphrase_status(key, agent_keys.as_deref()),
+        })
+        .collect();
+
+    SigningKey::Resolved {
+        signing_key,
+        uid: private_key.primary_uid().clone(),
+        keys,
+    }
+}
+
+fn passphrase_status(key: &GpgKeyDetails, agent_keys: Option<&[AgentKeyInfo]>) -> PassphraseStatus {
+    let Some(agent_keys) = agent_keys else {
+        return PassphraseStatus::Unknown;
+    };
+
+    match agent_keys.iter().find(|info| info.keygrip == key.keygrip) {
+        Some(info) if info.protection == KeyProtection::Unprotected => {
+            PassphraseStatus::Unprotected
+        }
+        Some(info) if info.cached => PassphraseStatus::Cached,
+        _ => PassphraseStatus::NotCached,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use git2::ConfigLevel;
+    use gpg::GpgCapabilities;
+
+    fn key(keygrip: &str) -> GpgKeyDetails {
+        GpgKeyDetails {
+            creation_date: 0,
+            expiration_date: None,
+            fingerprint: format!("{keygrip}FPR"),
+            key_id: format!("{keygrip}ID"),
+            keygrip: keygrip.to_string(),
+            capabilities: GpgCapabilities {
+                sign: true,
+                ..Default::default()
+            },
+        }
+    }
+
+    fn agent_key(keygrip: &str, cached: bool, protection: KeyProtection) -> AgentKeyInfo {
+        AgentKeyInfo {
+            keygrip: keygrip.to_string(),
+            cached,
+            protection,
+        }
+    }
+
+    #[test]
+    fn passphrase_status_from_agent_keys() {
+        let agent_keys = vec![
+            agent_key("CACHED", true, KeyProtection::Protected),
+            agent_key("PLAIN", false, KeyProtection::Unprotected),
+            agent_key("LOCKED", false, KeyProtection::Protected),
+        ];
+
+        for (keygrip, expected) in [
+            ("CACHED", PassphraseStatus::Cached),
+            ("PLAIN", PassphraseStatus::Unprotected),
+            ("LOCKED", PassphraseStatus::NotCached),
+            ("MISSING", PassphraseStatus::NotCached),
+        ] {
+            assert_eq!(
+                passphrase_status(&key(keygrip), Some(&agent_keys)),
+                expected,
+                "{keygrip}"
+            );
+        }
+        assert_eq!(
+            passphrase_status(&key("CACHED"), None),
+            PassphraseStatus::Unknown
+        );
+    }
+
+    #[test]
+    fn display_status() {
+        let status = Status {
+            config: vec![
+                ConfigValue {
+                    key: "user.signingKey".to_string(),
+                    value: "SIGNFPR!".to_string(),
+                    level: ConfigLevel::Local,
+                },
+                ConfigValue {
+                    key: "commit.gpgSign".to_string(),
+                    value: "true".to_string(),
+                    level: ConfigLevel::Global,
+                },
+            ],
+            signing_key: SigningKey::Resolved {
+                signing_key: "SIGNFPR!".to_string(),
+                uid: GpgUid {
+                    name: "batman".to_string(),
+                    email: "batman@dc.com".to_string(),
+                },
+                keys: vec![SigningKeyStatus {
+                    fingerprint: "SIGNFPR".to_string(),
+                    keygrip: "SIGNGRIP".to_string(),
+                    expiration_date: None,
+                    passphrase: PassphraseStatus::Cached,
+                }],
+            },
+        };
+
+        assert_eq!(
+            status.to_string(),
+            "> Git signing config:
+user.signingKey:            SIGNFPR! (local)
+commit.gpgSign:             true (global)
+
+> Signing key:
+signing_key:    SIGNFPR!
+user:           batman <batman@dc.com>
+fingerprint:    SIGNFPR
+keygrip:        SIGNGRIP
+expires_on:     never
+passphrase:     cached
+"
+        );
+    }
+
+    #[test]
+    fn display_status_ssh_signing_key() {
+        let status = Status {
+            config: Vec::new(),
+            signing_key: SigningKey::Ssh {
+                signing_key: "key::ssh-ed25519 AAAAC3Nza".to_string(),
+                status: SshKeyStatus::Served,
+            },
+        };
+        assert_eq!(
+            status.to_string(),
+            "> Git signing config:\n(none)\n\n> Signing key:\n\
+signing_key:    key::ssh-ed25519 AAAAC3Nza\nssh:            served by the ssh agent\n"
+        );
+    }
+
+    #[test]
+    fn display_status_unset_signing_key() {
+        let status = Status {
+            config: Vec::new(),
+            signing_key: SigningKey::Unset,
+        };
+        assert_eq!(
+            status.to_string(),
+            "> Git signing config:\n(none)\n\n> Signing key:\nuser.signingKey is not set\n"
+        );
+    }
+}
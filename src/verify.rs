@@ -0,0 +1,328 @@
+use crate::gpg::{self, GpgSignature, SignatureStatus};
+use anyhow::{bail, Result};
+use git2::{ErrorCode, Oid, Repository, RevparseMode, Revwalk, Sort};
+use std::{
+    collections::HashSet,
+    fmt::{self, Display},
+};
+
+/// Marks the start of a signature appended to an annotated tag
+const TAG_SIGNATURE_HEADER: &[u8] = b"-----BEGIN PGP SIGNATURE-----";
+
+/// The kind of git object whose signature was verified
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ObjectKind {
+    /// A commit, signed within its `gpgsig` header
+    Commit,
+    /// An annotated tag, with a signature appended to its message
+    Tag,
+}
+
+impl Display for ObjectKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ObjectKind::Commit => write!(f, "commit"),
+            ObjectKind::Tag => write!(f, "tag"),
+        }
+    }
+}
+
+/// The verification of a single commit or tag
+#[derive(Debug)]
+pub struct Verification {
+    /// The kind of object verified
+    pub kind: ObjectKind,
+    /// The id of the object verified
+    pub oid: Oid,
+    /// The commit summary or tag name
+    pub summary: String,
+    /// The signature, or `None` if the object is unsigned
+    pub signature: Option<GpgSignature>,
+    /// Whether the signer is permitted by the allowlist
+    pub allowed: bool,
+}
+
+impl Verification {
+    /// Determines if the object has a good signature from an allowed signer
+    pub fn passed(&self) -> bool {
+        self.allowed
+            && self
+                .signature
+                .as_ref()
+                .is_some_and(|signature| signature.status == SignatureStatus::Good)
+    }
+}
+
+impl Display for Verification {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let oid = self.oid.to_string();
+        write!(f, "{} {} {}", self.kind, &oid[..10], self.summary)?;
+
+        let Some(signature) = &self.signature else {
+            return write!(f, "\n  status: unsigned");
+        };
+
+        write!(f, "\n  status: {}", signature.status)?;
+        if !self.allowed {
+            write!(f, " (signer not allowed)")?;
+        }
+        if let Some(fingerprint) = signature
+            .primary_fingerprint
+            .as_ref()
+            .or(signature.fingerprint.as_ref())
+            .or(signature.key_id.as_ref())
+        {
+            write!(f, "\n  signer: {fingerprint}")?;
+        }
+        if let Some(uid) = &signature.uid {
+            write!(f, "\n  uid:    {uid}")?;
+        }
+        if let Some(validity) = &signature.validity {
+            write!(f, "\n  trust:  {validity}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Verifies the signatures of commits (and optionally annotated tags)
+/// within a repository against the current keyring
+pub struct Verifier<'repo> {
+    repo: &'repo Repository,
+    allowed_signers: Vec<String>,
+    tags: bool,
+    gpg: gpg::GpgContext,
+}
+
+impl<'repo> Verifier<'repo> {
+    /// Create a verifier for the given repository
+    pub fn new(repo: &'repo Repository) -> Self {
+        Self {
+            repo,
+            allowed_signers: Vec::new(),
+            tags: false,
+            gpg: gpg::GpgContext::default(),
+        }
+    }
+
+    /// Only accept signatures made by one of these fingerprints, either of a
+    /// primary key or of a specific subkey. Any key within the keyring is
+    /// accepted when empty
+    pub fn with_allowed_signers(mut self, fingerprints: Vec<String>) -> Self {
+        self.allowed_signers = fingerprints;
+        self
+    }
+
+    /// Also verify annotated tags that point at a commit within the range
+    pub fn verify_tags(mut self, enabled: bool) -> Self {
+        self.tags = enabled;
+        self
+    }
+
+    /// Verify signatures with the given GnuPG installation and keyring,
+    /// rather than `gpg` from `PATH` and its default homedir
+    pub fn with_gpg_context(mut self, gpg: gpg::GpgContext) -> Self {
+        self.gpg = gpg;
+        self
+    }
+
+    /// Verifies every commit within a revision range, such as
+    /// `origin/main..HEAD` or the symmetric `origin/main...HEAD`, or every
+    /// commit reachable from a single revision
+    pub fn verify_range(&self, range: &str) -> Result<Vec<Verification>> {
+        let mut revwalk = self.repo.revwalk()?;
+        revwalk.set_sorting(Sort::TOPOLOGICAL | Sort::REVERSE)?;
+        if range.contains("...") {
+            self.push_symmetric_difference(&mut revwalk, range)?;
+        } else if range.contains("..") {
+            revwalk.push_range(range)?;
+        } else {
+            let commit = self.repo.revparse_single(range)?.peel_to_commit()?;
+            revwalk.push(commit.id())?;
+        }
+
+        let mut commits = Vec::new();
+        let mut verifications = Vec::new();
+        for oid in revwalk {
+            let oid = oid?;
+            commits.push(oid);
+            verifications.push(self.verify_commit(oid)?);
+        }
+
+        if self.tags {
+            verifications.extend(self.verify_tags_pointing_at(&commits)?);
+        }
+
+        Ok(verifications)
+    }
+
+    /// Pushes the commits reachable from either side of an `A...B` range but
+    /// not from both, which libgit2's `push_range` doesn't support
+    fn push_symmetric_difference(&self, revwalk: &mut Revwalk, range: &str) -> Result<()> {
+        let revspec = self.repo.revparse(range)?;
+        let (Some(from), Some(to)) = (revspec.from(), revspec.to()) else {
+            bail!("invalid symmetric range: {range}");
+        };
+        if !revspec.mode().contains(RevparseMode::MERGE_BASE) {
+            bail!("invalid symmetric range: {range}");
+        }
+
+        let from = from.peel_to_commit()?.id();
+        let to = to.peel_to_commit()?.id();
+        revwalk.push(from)?;
+        revwalk.push(to)?;
+        match self.repo.merge_bases(from, to) {
+            Ok(bases) => {
+                for base in bases.iter() {
+                    revwalk.hide(*base)?;
+                }
+            }
+            // Unrelated histories share no commits to hide
+            Err(e) if e.code() == ErrorCode::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+        Ok(())
+    }
+
+    fn verify_commit(&self, oid: Oid) -> Result<Verification> {
+        let commit = self.repo.find_commit(oid)?;
+        let summary =
+            String::from_utf8_lossy(commit.summary_bytes().unwrap_or_default()).into_owned();
+
+        let signature = match commit.header_field_bytes("gpgsig") {
+            Ok(_) => {
+                let (signature, signed_data) = self.repo.extract_signature(&oid, None)?;
+                Some(gpg::verify_detached_signature(
+                    &self.gpg,
+                    &signature,
+                    &signed_data,
+                )?)
+            }
+            Err(e) if e.code() == ErrorCode::NotFound => None,
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(self.verification(ObjectKind::Commit, oid, summary, signature))
+    }
+
+    fn verify_tags_pointing_at(&self, commits: &[Oid]) -> Result<Vec<Verification>> {
+        let commits: HashSet<&Oid> = commits.iter().collect();
+        let mut verifications = Vec::new();
+
+        let odb = self.repo.odb()?;
+        let tag_names = self.repo.tag_names(None)?;
+        for name in tag_names.iter() {
+            let Some(name) = name? else {
+                continue;
+            };
+            let reference = self.repo.find_reference(&format!("refs/tags/{name}"))?;
+            let Ok(tag) = reference.peel_to_tag() else {
+                // Lightweight tags have no object to sign
+                continue;
+            };
+            let Ok(target) = reference.peel_to_commit() else {
+                // Tags of a tree or blob can't point at a commit in the range
+                continue;
+            };
+            if !commits.contains(&target.id()) {
+                continue;
+            }
+
+            let raw = odb.read(tag.id())?;
+            let signature = match split_tag_signature(raw.data()) {
+                Some((signed_data, signature)) => Some(gpg::verify_detached_signature(
+                    &self.gpg,
+                    signature,
+                    signed_data,
+                )?),
+                None => None,
+            };
+
+            verifications.push(self.verification(
+                ObjectKind::Tag,
+                tag.id(),
+                name.to_string(),
+                signature,
+            ));
+        }
+
+        Ok(verifications)
+    }
+
+    fn verification(
+        &self,
+        kind: ObjectKind,
+        oid: Oid,
+        summary: String,
+        signature: Option<GpgSignature>,
+    ) -> Verification {
+        let allowed = match &signature {
+            Some(signature) => {
+                self.allowed_signers.is_empty()
+                    || self
+                        .allowed_signers
+                        .iter()
+                        .any(|fingerprint| signature.signed_by(fingerprint))
+            }
+            None => false,
+        };
+
+        Verification {
+            kind,
+            oid,
+            summary,
+            signature,
+            allowed,
+        }
+    }
+}
+
+/// Splits a raw annotated tag into its signed data and the signature
+/// appended to its message
+fn split_tag_signature(raw: &[u8]) -> Option<(&[u8], &[u8])> {
+    let start = raw
+        .windows(TAG_SIGNATURE_HEADER.len())
+        .rposition(|window| window == TAG_SIGNATURE_HEADER)?;
+    Some(raw.split_at(start))
+}
+
+/// Prints every verification, failing if any object within the range is
+/// unsigned, has a signature that isn't good, or was signed by a key that
+/// isn't allowed
+pub fn report(verifications: &[Verification]) -> Result<()> {
+    println!("> Verified signatures:");
+    for verification in verifications {
+        println!("{verification}");
+    }
+
+    let failed = verifications.iter().filter(|v| !v.passed()).count();
+    if failed > 0 {
+        bail!(
+            "{failed} of {} signatures failed verification",
+            verifications.len()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_tag_signature_at_last_signature_header() {
+        let raw = b"object 1234\ntype commit\ntag v1.0.0\n\nRelease -----BEGIN PGP SIGNATURE----- notes\n-----BEGIN PGP SIGNATURE-----\n\nabc\n-----END PGP SIGNATURE-----\n";
+        let (signed_data, signature) = split_tag_signature(raw).unwrap();
+
+        assert!(signed_data.ends_with(b"notes\n"));
+        assert!(signature.starts_with(TAG_SIGNATURE_HEADER));
+        assert!(signature.ends_with(b"-----END PGP SIGNATURE-----\n"));
+    }
+
+    #[test]
+    fn split_tag_signature_unsigned() {
+        assert_eq!(
+            split_tag_signature(b"object 1234\ntag v1.0.0\n\nRelease\n"),
+            None
+        );
+    }
+}
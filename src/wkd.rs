@@ -0,0 +1,334 @@
+use crate::gpg;
+use anyhow::{bail, Result};
+use sha1_smol::Sha1;
+use std::{fmt, io::Read, time::Duration};
+use thiserror::Error;
+
+/// The z-base-32 alphabet, used to encode the hashed local part of an email
+const ZBASE32_ALPHABET: &[u8; 32] = b"ybndrfg8ejkmcpqxot1uwisza345h769";
+
+/// The largest key accepted from a Web Key Directory
+const MAX_KEY_SIZE: u64 = 1024 * 1024;
+
+/// The method used to locate a key within a Web Key Directory. The advanced
+/// method uses a dedicated `openpgpkey` subdomain, falling back to the
+/// direct method on the domain itself
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WkdMethod {
+    /// `https://openpgpkey.<domain>/.well-known/openpgpkey/<domain>/hu/...`
+    Advanced,
+    /// `https://<domain>/.well-known/openpgpkey/hu/...`
+    Direct,
+}
+
+impl fmt::Display for WkdMethod {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WkdMethod::Advanced => write!(f, "advanced"),
+            WkdMethod::Direct => write!(f, "direct"),
+        }
+    }
+}
+
+/// Errors that can occur when retrieving keys from a Web Key Directory
+#[derive(Clone, Debug, Eq, Error, PartialEq)]
+pub enum WkdError {
+    /// The email address isn't of the form `local@domain`
+    #[error("invalid email address for wkd lookup: {0}")]
+    InvalidEmail(String),
+
+    /// Neither the advanced nor the direct method returned a key
+    #[error("no key found for {0} via wkd: {1}")]
+    KeyNotFound(String, String),
+
+    /// The returned key doesn't contain a uid for the requested email
+    #[error("key returned via wkd for {0} does not contain a matching uid")]
+    UidMismatch(String),
+
+    /// The response was larger than any key that would be accepted
+    #[error("wkd response exceeds {0} bytes")]
+    ResponseTooLarge(u64),
+}
+
+/// A public key retrieved from a Web Key Directory
+#[derive(Debug)]
+pub struct WkdKey {
+    /// The method that located the key
+    pub method: WkdMethod,
+    /// The raw (binary) key data, narrowed down to the keys with a uid for
+    /// the requested email
+    pub data: Vec<u8>,
+}
+
+/// A client for retrieving public keys from a Web Key Directory
+pub struct Wkd {
+    agent: ureq::Agent,
+    base_url: Option<String>,
+    advanced_base_url: Option<String>,
+    gpg: gpg::GpgContext,
+}
+
+impl Default for Wkd {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Wkd {
+    /// Create a client that retrieves keys over https from the domain of
+    /// each email address
+    pub fn new() -> Self {
+        Self {
+            agent: ureq::AgentBuilder::new()
+                .timeout(Duration::from_secs(10))
+                .build(),
+            base_url: None,
+            advanced_base_url: None,
+            gpg: gpg::GpgContext::default(),
+        }
+    }
+
+    /// Route every request to the given base URL (e.g. `http://127.0.0.1:8080`)
+    /// instead of the host derived from the email address, keeping the path.
+    /// Intended for testing against a local stand-in server
+    pub fn with_base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = Some(base_url.into().trim_end_matches('/').to_string());
+        self
+    }
+
+    /// Route requests made by the advanced method to the given base URL,
+    /// overriding [`Wkd::with_base_url`]. Intended for testing, e.g. to
+    /// stand in for an `openpgpkey` subdomain that doesn't exist
+    pub fn with_advanced_base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.advanced_base_url = Some(base_url.into().trim_end_matches('/').to_string());
+        self
+    }
+
+    /// Run the gpg that narrows down the retrieved keys from the given
+    /// installation, rather than `gpg` from `PATH`
+    pub fn with_gpg_context(mut self, gpg: gpg::GpgContext) -> Self {
+        self.gpg = gpg;
+        self
+    }
+
+    /// Retrieves the public key for an email address, trying the advanced
+    /// method before the direct method. As per the specification, the direct
+    /// method is only tried when the advanced host can't be resolved or
+    /// connected to. Only the keys with a uid for the email address are
+    /// returned, failing if there are none
+    pub fn fetch_key(&self, email: &str) -> Result<WkdKey> {
+        let mut failures = Vec::new();
+
+        for (method, url, local_part) in lookup_urls(email)? {
+            let url = self.route(method, &url);
+            match self.get(&url, &local_part) {
+                Ok(data) => {
+                    let data = select_matching_keys(&self.gpg, &data, email)?;
+                    return Ok(WkdKey { method, data });
+                }
+                Err(e) => {
+                    let unreachable = is_unreachable(&e);
+                    failures.push(format!("{method}: {e}"));
+                    if !unreachable {
+                        break;
+                    }
+                }
+            }
+        }
+
+        bail!(WkdError::KeyNotFound(
+            email.to_string(),
+            failures.join("; ")
+        ))
+    }
+
+    fn route(&self, method: WkdMethod, url: &str) -> String {
+        let base_url = match method {
+            WkdMethod::Advanced => self.advanced_base_url.as_ref().or(self.base_url.as_ref()),
+            WkdMethod::Direct => self.base_url.as_ref(),
+        };
+        match base_url {
+            Some(base_url) => {
+                let path = url
+                    .strip_prefix("https://")
+                    .and_then(|rest| rest.find('/').map(|idx| &rest[idx..]))
+                    .unwrap_or_default();
+                format!("{base_url}{path}")
+            }
+            None => url.to_string(),
+        }
+    }
+
+    fn get(&self, url: &str, local_part: &str) -> Result<Vec<u8>> {
+        let response = self.agent.get(url).query("l", local_part).call()?;
+
+        // Read one byte beyond the limit, so an oversized response is
+        // rejected rather than truncated into a partial key
+        let mut data = Vec::new();
+        response
+            .into_reader()
+            .take(MAX_KEY_SIZE + 1)
+            .read_to_end(&mut data)?;
+        if data.len() as u64 > MAX_KEY_SIZE {
+            bail!(WkdError::ResponseTooLarge(MAX_KEY_SIZE));
+        }
+        if data.is_empty() {
+            bail!("empty response");
+        }
+        Ok(data)
+    }
+}
+
+/// Whether a request failed because the host couldn't be resolved or
+/// connected to, rather than answering with an error
+fn is_unreachable(e: &anyhow::Error) -> bool {
+    matches!(
+        e.downcast_ref::<ureq::Error>(),
+        Some(ureq::Error::Transport(transport))
+            if matches!(
+                transport.kind(),
+                ureq::ErrorKind::Dns | ureq::ErrorKind::ConnectionFailed
+            )
+    )
+}
+
+/// Builds the advanced and direct lookup URLs for an email address, in the
+/// order they should be tried, along with the (unhashed) local part that is
+/// passed as the `l` query parameter
+pub fn lookup_urls(email: &str) -> Result<Vec<(WkdMethod, String, String)>, WkdError> {
+    let (local_part, domain) = email
+        .trim()
+        .rsplit_once('@')
+        .filter(|(local_part, domain)| !local_part.is_empty() && !domain.is_empty())
+        .ok_or_else(|| WkdError::InvalidEmail(email.to_string()))?;
+
+    let domain = domain.to_lowercase();
+    let hash = hash_local_part(local_part);
+
+    Ok(vec![
+        (
+            WkdMethod::Advanced,
+            format!("https://openpgpkey.{domain}/.well-known/openpgpkey/{domain}/hu/{hash}"),
+            local_part.to_string(),
+        ),
+        (
+            WkdMethod::Direct,
+            format!("https://{domain}/.well-known/openpgpkey/hu/{hash}"),
+            local_part.to_string(),
+        ),
+    ])
+}
+
+/// Hashes the local part of an email address as defined by WKD: the
+/// z-base-32 encoding of the SHA-1 digest of the lowercased local part
+pub fn hash_local_part(local_part: &str) -> String {
+    let digest = Sha1::from(local_part.to_lowercase()).digest().bytes();
+    zbase32_encode(&digest)
+}
+
+fn zbase32_encode(data: &[u8]) -> String {
+    let mut encoded = String::with_capacity(data.len().div_ceil(5) * 8);
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+
+    for byte in data {
+        buffer = (buffer << 8) | u32::from(*byte);
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            encoded.push(ZBASE32_ALPHABET[((buffer >> bits) & 0x1f) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        encoded.push(ZBASE32_ALPHABET[((buffer << (5 - bits)) & 0x1f) as usize] as char);
+    }
+
+    encoded
+}
+
+/// Narrows the keys retrieved for an email address down to those with a
+/// uid for that email address, so a misconfigured or compromised directory
+/// can neither substitute an unrelated key nor add extra keys alongside
+fn select_matching_keys(ctx: &gpg::GpgContext, data: &[u8], email: &str) -> Result<Vec<u8>> {
+    let keys = gpg::preview_public_key_data(ctx, data)?;
+    let matching: Vec<String> = keys
+        .iter()
+        .filter(|key| {
+            key.uids
+                .iter()
+                .any(|uid| uid.email.eq_ignore_ascii_case(email.trim()))
+        })
+        .map(|key| key.fingerprint.clone())
+        .collect();
+
+    if matching.is_empty() {
+        bail!(WkdError::UidMismatch(email.to_string()));
+    }
+    if matching.len() == keys.len() {
+        return Ok(data.to_vec());
+    }
+    gpg::select_public_key_data(ctx, data, &matching)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hash_local_part_matches_specification_example() {
+        // Example taken from draft-koch-openpgp-webkey-service
+        assert_eq!(
+            hash_local_part("Joe.Doe"),
+            "iy9q119eutrkn8s1mk4r39qejnbu3n5q"
+        );
+    }
+
+    #[test]
+    fn zbase32_encode_pads_trailing_bits() {
+        assert_eq!(zbase32_encode(&[]), "");
+        assert_eq!(zbase32_encode(&[0x00]), "yy");
+        assert_eq!(zbase32_encode(&[0xff]), "9h");
+    }
+
+    #[test]
+    fn lookup_urls_for_advanced_then_direct_method() {
+        let urls = lookup_urls("Joe.Doe@Example.ORG").unwrap();
+        assert_eq!(
+            urls,
+            vec![
+                (
+                    WkdMethod::Advanced,
+                    "https://openpgpkey.example.org/.well-known/openpgpkey/example.org/hu/iy9q119eutrkn8s1mk4r39qejnbu3n5q".to_string(),
+                    "Joe.Doe".to_string(),
+                ),
+                (
+                    WkdMethod::Direct,
+                    "https://example.org/.well-known/openpgpkey/hu/iy9q119eutrkn8s1mk4r39qejnbu3n5q".to_string(),
+                    "Joe.Doe".to_string(),
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn lookup_urls_rejects_invalid_email() {
+        for email in ["batman", "@dc.com", "batman@"] {
+            assert_eq!(
+                lookup_urls(email),
+                Err(WkdError::InvalidEmail(email.to_string()))
+            );
+        }
+    }
+
+    #[test]
+    fn route_replaces_host_with_base_url() {
+        let wkd = Wkd::new().with_base_url("http://127.0.0.1:8080/");
+        assert_eq!(
+            wkd.route(
+                WkdMethod::Advanced,
+                "https://openpgpkey.dc.com/.well-known/openpgpkey/dc.com/hu/abc"
+            ),
+            "http://127.0.0.1:8080/.well-known/openpgpkey/dc.com/hu/abc"
+        );
+    }
+}
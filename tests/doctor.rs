@@ -0,0 +1,147 @@
+use git2::Repository;
+use gpg_import::{
+    doctor::{Check, CheckStatus, Doctor},
+    gpg::{self, GpgContext},
+    import::GpgImport,
+};
+use serial_test::serial;
+use std::env;
+use tempfile::TempDir;
+
+mod fixture;
+use fixture::GpgTestFixture;
+
+fn status_of(checks: &[Check], name: &str) -> CheckStatus {
+    checks
+        .iter()
+        .find(|check| check.name == name)
+        .unwrap_or_else(|| panic!("No {name} check in {checks:?}"))
+        .status
+}
+
+#[test]
+#[serial]
+fn doctor_passes_for_configured_signing_key() {
+    let fixture = GpgTestFixture::new();
+    assert!(fixture.is_ok(), "Failed to create GPG test fixture");
+    let fixture = fixture.unwrap();
+    let fingerprint = fixture.generate_signing_key().unwrap();
+
+    let repo_dir = TempDir::new().unwrap();
+    let repo = Repository::init(repo_dir.path()).unwrap();
+    repo.config()
+        .unwrap()
+        .set_str("user.signingKey", &fingerprint)
+        .unwrap();
+
+    let checks = Doctor::new(Some(&repo)).run();
+    for name in [
+        "gpg",
+        "homedir",
+        "gpg.conf",
+        "gpg-agent.conf",
+        "gpg-agent",
+        "signing key",
+        "expiry",
+        "passphrase",
+    ] {
+        assert_eq!(
+            status_of(&checks, name),
+            CheckStatus::Pass,
+            "{name} should pass: {checks:?}"
+        );
+    }
+}
+
+#[test]
+#[serial]
+fn doctor_fails_for_unknown_signing_key() {
+    let fixture = GpgTestFixture::new();
+    assert!(fixture.is_ok(), "Failed to create GPG test fixture");
+    let _fixture = fixture.unwrap();
+
+    let repo_dir = TempDir::new().unwrap();
+    let repo = Repository::init(repo_dir.path()).unwrap();
+    repo.config()
+        .unwrap()
+        .set_str(
+            "user.signingKey",
+            "24DA69B1615F5F3C6A9C3A60B8527C5AED483BE3",
+        )
+        .unwrap();
+
+    let checks = Doctor::new(Some(&repo)).run();
+    for name in ["signing key", "expiry", "passphrase"] {
+        assert_eq!(
+            status_of(&checks, name),
+            CheckStatus::Fail,
+            "{name} should fail: {checks:?}"
+        );
+    }
+}
+
+#[test]
+#[serial]
+fn doctor_checks_ssh_signing_key_against_agent() {
+    let fixture = GpgTestFixture::new();
+    assert!(fixture.is_ok(), "Failed to create GPG test fixture");
+    let fixture = fixture.unwrap();
+    let fingerprint = fixture.generate_signing_key().unwrap();
+    fixture.add_subkey(&fingerprint, "auth").unwrap();
+    let armored = fixture.export_secret_key(&fingerprint).unwrap();
+
+    let result = GpgImport::new(armored)
+        .with_agent_config(gpg::AgentConfig {
+            enable_ssh_support: true,
+            ..Default::default()
+        })
+        .skip_git(true)
+        .import();
+    assert!(result.is_ok(), "Import failed: {:?}", result.err());
+
+    // Only the authentication subkey is served by the gpg-agent
+    let ctx = GpgContext::default();
+    let auth_subkey = &gpg::extract_key_info(&ctx, &fingerprint).unwrap().subkeys[0];
+    let served = gpg::export_ssh_key(&ctx, &auth_subkey.fingerprint).unwrap();
+    let not_served = gpg::export_ssh_key(&ctx, &fingerprint).unwrap();
+
+    let repo_dir = TempDir::new().unwrap();
+    let repo = Repository::init(repo_dir.path()).unwrap();
+    let mut config = repo.config().unwrap();
+    config.set_str("gpg.format", "ssh").unwrap();
+
+    let original_sock = env::var_os("SSH_AUTH_SOCK");
+    env::remove_var("SSH_AUTH_SOCK");
+    let mut doctor_checks = Vec::new();
+    for ssh_key in [&served, &not_served] {
+        config
+            .set_str("user.signingKey", &format!("key::{ssh_key}"))
+            .unwrap();
+        doctor_checks.push(Doctor::new(Some(&repo)).run());
+    }
+    if let Some(original) = original_sock {
+        env::set_var("SSH_AUTH_SOCK", original);
+    }
+
+    // The key isn't looked up within the gpg keyring
+    for checks in &doctor_checks {
+        assert!(
+            !checks
+                .iter()
+                .any(|check| check.name == "expiry" || check.name == "passphrase"),
+            "gpg key checks should be skipped in SSH mode: {checks:?}"
+        );
+    }
+    assert_eq!(
+        status_of(&doctor_checks[0], "signing key"),
+        CheckStatus::Pass,
+        "{:?}",
+        doctor_checks[0]
+    );
+    assert_eq!(
+        status_of(&doctor_checks[1], "signing key"),
+        CheckStatus::Fail,
+        "{:?}",
+        doctor_checks[1]
+    );
+}
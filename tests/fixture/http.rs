@@ -0,0 +1,98 @@
+//! A minimal HTTP server, standing in for remote key servers during tests.
+//! Serves a fixed set of paths (ignoring any query string) and responds
+//! with a 404 to everything else. Request bodies are recorded, but always
+//! answered with a 200.
+
+use std::{
+    collections::HashMap,
+    io::{BufRead, BufReader, Read, Write},
+    net::TcpListener,
+    sync::{Arc, Mutex},
+    thread,
+};
+
+/// A stand-in HTTP server listening on an ephemeral local port. The server
+/// thread is left running until the test binary exits
+pub struct HttpStandIn {
+    base_url: String,
+    requests: Arc<Mutex<Vec<String>>>,
+    bodies: Arc<Mutex<Vec<Vec<u8>>>>,
+}
+
+impl HttpStandIn {
+    /// Start serving each `(path, body)` pair
+    pub fn serve(routes: Vec<(String, Vec<u8>)>) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind stand-in server");
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let routes: HashMap<String, Vec<u8>> = routes.into_iter().collect();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let bodies = Arc::new(Mutex::new(Vec::new()));
+
+        let recorded = Arc::clone(&requests);
+        let recorded_bodies = Arc::clone(&bodies);
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else { continue };
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+                let mut request_line = String::new();
+                if reader.read_line(&mut request_line).is_err() {
+                    continue;
+                }
+                // Drain the headers, as the client expects them to be read
+                let mut content_length = 0;
+                let mut header = String::new();
+                while reader.read_line(&mut header).is_ok_and(|n| n > 2) {
+                    if let Some((name, value)) = header.split_once(':') {
+                        if name.eq_ignore_ascii_case("content-length") {
+                            content_length = value.trim().parse().unwrap_or_default();
+                        }
+                    }
+                    header.clear();
+                }
+
+                let mut request_body = vec![0; content_length];
+                if content_length > 0 && reader.read_exact(&mut request_body).is_ok() {
+                    recorded_bodies.lock().unwrap().push(request_body);
+                }
+
+                let target = request_line.split_whitespace().nth(1).unwrap_or_default();
+                recorded.lock().unwrap().push(target.to_string());
+                let path = target.split('?').next().unwrap_or_default();
+
+                let (status, body) = match routes.get(path) {
+                    Some(body) => ("200 OK", body.as_slice()),
+                    None if content_length > 0 => ("200 OK", &b""[..]),
+                    None => ("404 Not Found", &b""[..]),
+                };
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 {status}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    body.len()
+                );
+                let _ = stream.write_all(body);
+            }
+        });
+
+        Self {
+            base_url,
+            requests,
+            bodies,
+        }
+    }
+
+    /// The base URL of the server, e.g. `http://127.0.0.1:34567`
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// Every request target (path and query) received so far, in order
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    /// Every request body received so far, in order
+    pub fn bodies(&self) -> Vec<Vec<u8>> {
+        self.bodies.lock().unwrap().clone()
+    }
+}
@@ -3,6 +3,8 @@
 //! than have every binary warn about the helpers it doesn't call.
 #![allow(dead_code)]
 
+pub mod http;
+
 use anyhow::{bail, Ok, Result};
 use gpg_import::gpg;
 use std::{env, fs, process::Command};
@@ -25,6 +27,25 @@ fn freeze_at(date: &str) -> String {
     format!("{date} 00:00:00")
 }
 
+/// The batch config of an RSA primary key that can only sign, with a single
+/// uid, protected by the given passphrase if any
+fn signing_key_batch_config(name: &str, email: &str, passphrase: Option<&str>) -> String {
+    let protection = match passphrase {
+        Some(passphrase) => format!("Passphrase: {passphrase}"),
+        None => "%no-protection".to_string(),
+    };
+    format!(
+        "Key-Type: RSA
+Key-Length: 2048
+Key-Usage: sign
+Name-Real: {name}
+Name-Email: {email}
+{protection}
+%commit
+"
+    )
+}
+
 /// Restores the process's original GNUPGHOME on drop. Installed as a local
 /// variable before any fallible configuration step during fixture
 /// construction, so an early `?` return still restores the environment
@@ -78,8 +99,8 @@ impl GpgTestFixture {
 
         let gnupghome_guard = GnupghomeGuard::install(&gnupg_home);
 
-        gpg::configure_defaults(&gnupg_home)?;
-        gpg::configure_agent_defaults(&gnupg_home)?;
+        gpg::configure_defaults(temp_dir.path())?;
+        gpg::configure_agent_defaults(&gpg::GpgContext::default(), temp_dir.path())?;
 
         Ok(Self {
             temp_dir,
@@ -130,6 +151,37 @@ impl GpgTestFixture {
         self.get_latest_key_fingerprint()
     }
 
+    /// Generates an unprotected RSA key that can only sign, with a uid for
+    /// batman@dc.com, returning its fingerprint
+    pub fn generate_signing_key(&self) -> Result<String> {
+        self.generate_signing_key_for("batman", "batman@dc.com")
+    }
+
+    /// Generates an unprotected RSA key that can only sign, with a uid for
+    /// the given name and email, returning its fingerprint
+    pub fn generate_signing_key_for(&self, name: &str, email: &str) -> Result<String> {
+        self.generate_key(&signing_key_batch_config(name, email, None), None)
+    }
+
+    /// Generates an RSA key that can only sign, with a uid for
+    /// batman@dc.com, protected by the given passphrase, returning its
+    /// fingerprint
+    pub fn generate_protected_signing_key(&self, passphrase: &str) -> Result<String> {
+        let batch_config = signing_key_batch_config("batman", "batman@dc.com", Some(passphrase));
+        self.generate_key(&batch_config, None)
+    }
+
+    /// Generates a passphrase protected signing key, as with
+    /// `generate_protected_signing_key`, and exports it, returning its
+    /// fingerprint and armored secret key. The agent is then killed, so no
+    /// passphrase cached while exporting unlocks the key later on
+    pub fn generate_protected_secret_key(&self, passphrase: &str) -> Result<(String, String)> {
+        let fingerprint = self.generate_protected_signing_key(passphrase)?;
+        let armored = self.export_protected_secret_key(&fingerprint, passphrase)?;
+        self.kill_agent()?;
+        Ok((fingerprint, armored))
+    }
+
     /// Adds an additional subkey with the given usage (e.g. "sign", "encrypt",
     /// "auth") to an existing, unprotected primary key
     pub fn add_subkey(&self, fingerprint: &str, usage: &str) -> Result<()> {
@@ -187,6 +239,34 @@ impl GpgTestFixture {
         Ok(())
     }
 
+    /// Changes the passphrase protecting a single key or subkey, leaving the
+    /// rest of the key untouched, by answering the agent's `PASSWD` inquiries
+    /// in loopback mode
+    pub fn change_key_passphrase(&self, keygrip: &str, old: &str, new: &str) -> Result<()> {
+        let old_path = self.temp_dir.path().join("old-passphrase");
+        let new_path = self.temp_dir.path().join("new-passphrase");
+        fs::write(&old_path, old)?;
+        fs::write(&new_path, new)?;
+
+        let output = Command::new("gpg-connect-agent")
+            .arg("OPTION pinentry-mode=loopback")
+            .arg(format!("/definqfile PASSPHRASE {}", old_path.display()))
+            .arg(format!("/definqfile NEW_PASSPHRASE {}", new_path.display()))
+            .arg(format!("PASSWD {keygrip}"))
+            .arg("/bye")
+            .output();
+        fs::remove_file(&old_path)?;
+        fs::remove_file(&new_path)?;
+
+        let output = output?;
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        if !output.status.success() || stdout.lines().any(|line| line.starts_with("ERR")) {
+            bail!("Failed to change passphrase of {}: {}", keygrip, stdout);
+        }
+
+        Ok(())
+    }
+
     /// Exports the ASCII-armored secret key material for a fingerprint
     /// already present in the keyring
     pub fn export_secret_key(&self, fingerprint: &str) -> Result<String> {
@@ -202,6 +282,22 @@ impl GpgTestFixture {
         Ok(String::from_utf8(output.stdout)?)
     }
 
+    /// Exports the ASCII-armored public keys for fingerprints already present
+    /// in the keyring, as a single block
+    pub fn export_public_keys(&self, fingerprints: &[String]) -> Result<String> {
+        let output = Command::new("gpg")
+            .args(["--armor", "--export"])
+            .args(fingerprints)
+            .output()?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            bail!("Failed to export public keys: {}", stderr);
+        }
+
+        Ok(String::from_utf8(output.stdout)?)
+    }
+
     /// Exports the ASCII-armored secret key material for a passphrase-protected
     /// fingerprint already present in the keyring
     pub fn export_protected_secret_key(
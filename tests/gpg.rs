@@ -56,7 +56,7 @@ fn import_secret_key_base64() {
     let fixture = fixture.unwrap();
 
     let gpg_key = include_str!("testdata/no-passphrase.base64.key");
-    let result = gpg::import_secret_key(gpg_key);
+    let result = gpg::import_secret_key(&gpg::GpgContext::default(), gpg_key);
     assert!(result.is_ok(), "Failed to import GPG key");
 
     let fingerprint = result.unwrap();
@@ -113,7 +113,7 @@ fn import_secret_key_under_non_c_locale() {
     ]);
 
     let gpg_key = include_str!("testdata/no-passphrase.base64.key");
-    let result = gpg::import_secret_key(gpg_key);
+    let result = gpg::import_secret_key(&gpg::GpgContext::default(), gpg_key);
     assert!(
         result.is_ok(),
         "Failed to import GPG key under non-C locale: {:?}",
@@ -133,16 +133,26 @@ fn import_secret_key_base64_with_passphrase() {
     let fixture = fixture.unwrap();
 
     let gpg_key = include_str!("testdata/passphrase.base64.key");
-    let result = gpg::import_secret_key(gpg_key);
+    let result = gpg::import_secret_key(&gpg::GpgContext::default(), gpg_key);
     assert!(result.is_ok(), "Failed to import GPG key");
 
     let passphrase = "gotham";
     assert!(
-        gpg::preset_passphrase("A38A309DBDD35F6597F3AB132ECDE01CCA68D62F", passphrase).is_ok(),
+        gpg::preset_passphrase(
+            &gpg::GpgContext::default(),
+            "A38A309DBDD35F6597F3AB132ECDE01CCA68D62F",
+            passphrase
+        )
+        .is_ok(),
         "Failed to preset passphrase"
     );
     assert!(
-        gpg::preset_passphrase("60C07F604DC06BA2F6DF829A8CF2F7380089C409", passphrase).is_ok(),
+        gpg::preset_passphrase(
+            &gpg::GpgContext::default(),
+            "60C07F604DC06BA2F6DF829A8CF2F7380089C409",
+            passphrase
+        )
+        .is_ok(),
         "Failed to preset passphrase"
     );
 
@@ -169,7 +179,7 @@ fn extract_key_info_expired_secret_key() {
         fixture.batch_generate_key_on(&batch_config, &created_on.format("%Y-%m-%d").to_string());
     assert!(result.is_ok(), "Failed to generate GPG key");
 
-    let result = gpg::extract_key_info(&result.unwrap());
+    let result = gpg::extract_key_info(&gpg::GpgContext::default(), &result.unwrap());
     assert!(
         result.is_err(),
         "Failed to extract key info for expired secret key"
@@ -209,7 +219,7 @@ fn extract_key_info_does_not_reject_expired_subkey() {
         fixture.batch_generate_key_on(&batch_config, &created_on.format("%Y-%m-%d").to_string());
     assert!(result.is_ok(), "Failed to generate GPG key");
 
-    let result = gpg::extract_key_info(&result.unwrap());
+    let result = gpg::extract_key_info(&gpg::GpgContext::default(), &result.unwrap());
     assert!(
         result.is_ok(),
         "extract_key_info should not fail on an expired subkey: {:?}",
@@ -224,20 +234,11 @@ fn extract_key_info_sign_only_no_subkey() {
     assert!(fixture.is_ok(), "Failed to create GPG test fixture");
     let fixture = fixture.unwrap();
 
-    let batch_config = "Key-Type: RSA
-Key-Length: 2048
-Key-Usage: sign
-Name-Real: robin
-Name-Email: robin@dc.com
-%no-protection
-%commit
-";
-
-    let fingerprint = fixture.generate_key(batch_config, None);
+    let fingerprint = fixture.generate_signing_key_for("robin", "robin@dc.com");
     assert!(fingerprint.is_ok(), "Failed to generate sign-only GPG key");
     let fingerprint = fingerprint.unwrap();
 
-    let key_info = gpg::extract_key_info(&fingerprint);
+    let key_info = gpg::extract_key_info(&gpg::GpgContext::default(), &fingerprint);
     assert!(
         key_info.is_ok(),
         "Should extract info for a sign-only key with no subkey"
@@ -264,16 +265,7 @@ fn extract_key_info_multiple_subkeys() {
     assert!(fixture.is_ok(), "Failed to create GPG test fixture");
     let fixture = fixture.unwrap();
 
-    let batch_config = "Key-Type: RSA
-Key-Length: 2048
-Key-Usage: sign
-Name-Real: batman
-Name-Email: batman@dc.com
-%no-protection
-%commit
-";
-
-    let fingerprint = fixture.generate_key(batch_config, None);
+    let fingerprint = fixture.generate_signing_key();
     assert!(fingerprint.is_ok(), "Failed to generate primary key");
     let fingerprint = fingerprint.unwrap();
 
@@ -293,7 +285,7 @@ Name-Email: batman@dc.com
         "Failed to add auth subkey"
     );
 
-    let key_info = gpg::extract_key_info(&fingerprint);
+    let key_info = gpg::extract_key_info(&gpg::GpgContext::default(), &fingerprint);
     assert!(
         key_info.is_ok(),
         "Should extract info for a key with 3 subkeys"
@@ -320,18 +312,7 @@ fn preset_passphrase_for_every_subkey_allows_non_first_subkey_to_sign() {
     let fixture = fixture.unwrap();
 
     let passphrase = "gotham";
-    let batch_config = format!(
-        "Key-Type: RSA
-Key-Length: 2048
-Key-Usage: sign
-Name-Real: batman
-Name-Email: batman@dc.com
-Passphrase: {passphrase}
-%commit
-"
-    );
-
-    let fingerprint = fixture.generate_key(&batch_config, None);
+    let fingerprint = fixture.generate_protected_signing_key(passphrase);
     assert!(fingerprint.is_ok(), "Failed to generate primary key");
     let fingerprint = fingerprint.unwrap();
 
@@ -350,7 +331,7 @@ Passphrase: {passphrase}
         "Failed to add sign subkey"
     );
 
-    let key_info = gpg::extract_key_info(&fingerprint);
+    let key_info = gpg::extract_key_info(&gpg::GpgContext::default(), &fingerprint);
     assert!(key_info.is_ok(), "Failed to extract key info");
     let key_info = key_info.unwrap();
     assert_eq!(key_info.subkeys.len(), 2);
@@ -361,10 +342,16 @@ Passphrase: {passphrase}
     assert!(fixture.kill_agent().is_ok(), "Failed to kill gpg-agent");
 
     // Mirrors GpgImport::configure_gpg_passphrase: preset primary + every subkey.
-    assert!(gpg::preset_passphrase(&key_info.secret_key.keygrip, passphrase).is_ok());
+    assert!(gpg::preset_passphrase(
+        &gpg::GpgContext::default(),
+        &key_info.secret_key.keygrip,
+        passphrase
+    )
+    .is_ok());
     for subkey in &key_info.subkeys {
         assert!(
-            gpg::preset_passphrase(&subkey.keygrip, passphrase).is_ok(),
+            gpg::preset_passphrase(&gpg::GpgContext::default(), &subkey.keygrip, passphrase)
+                .is_ok(),
             "Failed to preset passphrase for subkey {}",
             subkey.key_id
         );
@@ -389,16 +376,7 @@ fn preview_key_sign_only_no_subkey() {
     assert!(fixture.is_ok(), "Failed to create GPG test fixture");
     let fixture = fixture.unwrap();
 
-    let batch_config = "Key-Type: RSA
-Key-Length: 2048
-Key-Usage: sign
-Name-Real: robin
-Name-Email: robin@dc.com
-%no-protection
-%commit
-";
-
-    let fingerprint = fixture.generate_key(batch_config, None);
+    let fingerprint = fixture.generate_signing_key_for("robin", "robin@dc.com");
     assert!(fingerprint.is_ok(), "Failed to generate sign-only GPG key");
     let fingerprint = fingerprint.unwrap();
 
@@ -408,7 +386,7 @@ Name-Email: robin@dc.com
     // preview_key uses a different gpg invocation (`--import-options show-only`,
     // without --fixed-list-mode) to extract_key_info's (`--list-secret-keys`),
     // so the zero-subkey case is verified independently here.
-    let result = gpg::preview_key(&armored.unwrap());
+    let result = gpg::preview_key(&gpg::GpgContext::default(), &armored.unwrap());
     assert!(result.is_ok(), "Should preview a sign-only key");
 
     let key = result.unwrap();
@@ -426,16 +404,7 @@ fn preview_key_multiple_subkeys() {
     assert!(fixture.is_ok(), "Failed to create GPG test fixture");
     let fixture = fixture.unwrap();
 
-    let batch_config = "Key-Type: RSA
-Key-Length: 2048
-Key-Usage: sign
-Name-Real: batman
-Name-Email: batman@dc.com
-%no-protection
-%commit
-";
-
-    let fingerprint = fixture.generate_key(batch_config, None);
+    let fingerprint = fixture.generate_signing_key();
     assert!(fingerprint.is_ok(), "Failed to generate primary key");
     let fingerprint = fingerprint.unwrap();
 
@@ -457,7 +426,7 @@ Name-Email: batman@dc.com
     let armored = fixture.export_secret_key(&fingerprint);
     assert!(armored.is_ok(), "Failed to export multi-subkey GPG key");
 
-    let result = gpg::preview_key(&armored.unwrap());
+    let result = gpg::preview_key(&gpg::GpgContext::default(), &armored.unwrap());
     assert!(result.is_ok(), "Should preview a key with 3 subkeys");
 
     let key = result.unwrap();
@@ -475,23 +444,170 @@ fn assign_trust_level() {
     let _fixture = fixture.unwrap();
 
     let gpg_key = include_str!("testdata/no-passphrase.base64.key");
-    let result = gpg::import_secret_key(gpg_key);
+    let result = gpg::import_secret_key(&gpg::GpgContext::default(), gpg_key);
     assert!(result.is_ok(), "Failed to import GPG key");
 
     let key_id = result.unwrap();
-    let key_info = gpg::extract_key_info(&key_id);
+    let key_info = gpg::extract_key_info(&gpg::GpgContext::default(), &key_id);
     assert!(key_info.is_ok(), "Failed to extract key info");
 
     let key_info = key_info.unwrap();
-    let result = gpg::assign_trust_level(&key_info.secret_key.key_id, 5);
-    assert!(result.is_ok(), "Failed to assign trust level");
+    let fingerprint = &key_info.secret_key.fingerprint;
+    let result = gpg::assign_trust_level(&gpg::GpgContext::default(), fingerprint, 5);
+    assert!(
+        result.is_ok(),
+        "Failed to assign trust level: {:?}",
+        result.err()
+    );
+
+    let ownertrust = gpg::read_ownertrust(&gpg::GpgContext::default(), fingerprint);
+    assert!(ownertrust.is_ok(), "Failed to read back ownertrust");
+    assert_eq!(ownertrust.unwrap(), Some(6), "Expected ultimate ownertrust");
+
+    // Lowering the trust level must replace, not merge with, the previous value
+    let result = gpg::assign_trust_level(&gpg::GpgContext::default(), fingerprint, 3);
+    assert!(result.is_ok(), "Failed to lower trust level");
+    assert_eq!(
+        gpg::read_ownertrust(&gpg::GpgContext::default(), fingerprint).unwrap(),
+        Some(4)
+    );
+}
+
+#[test]
+fn assign_trust_level_rejects_out_of_range_level() {
+    let result = gpg::assign_trust_level(
+        &gpg::GpgContext::default(),
+        "BEEA4CDB4B0A80CBABB99B45FDEFE8AB8796E127",
+        9,
+    );
+    let err = result.unwrap_err();
+    let gpg_err = err.downcast_ref::<gpg::GpgError>().unwrap();
+    assert_eq!(gpg_err, &gpg::GpgError::InvalidTrustLevel(9));
+}
+
+#[test]
+#[serial]
+fn import_public_keys_without_secret_keys() {
+    // Generate the public key of each colleague within its own keyring, then
+    // concatenate their armored blocks, as found in a shared keys file
+    let mut armored = String::new();
+    let mut fingerprints = Vec::new();
+    for name in ["robin", "alfred"] {
+        let fixture = GpgTestFixture::new();
+        assert!(fixture.is_ok(), "Failed to create GPG test fixture");
+        let fixture = fixture.unwrap();
+
+        let fingerprint = fixture.generate_signing_key_for(name, &format!("{name}@dc.com"));
+        assert!(fingerprint.is_ok(), "Failed to generate {name}'s key");
+        let fingerprint = fingerprint.unwrap();
+
+        let public_key = fixture.export_public_keys(std::slice::from_ref(&fingerprint));
+        assert!(public_key.is_ok(), "Failed to export {name}'s public key");
+        armored.push_str(&public_key.unwrap());
+        fingerprints.push(fingerprint);
+    }
+
+    let fixture = GpgTestFixture::new();
+    assert!(fixture.is_ok(), "Failed to create GPG test fixture");
+    let _fixture = fixture.unwrap();
+
+    let preview = gpg::preview_public_keys(&gpg::GpgContext::default(), &armored);
+    assert!(preview.is_ok(), "Should preview public keys");
+    assert_eq!(preview.unwrap().len(), 2);
+
+    let result = gpg::import_public_keys(&gpg::GpgContext::default(), &armored);
+    assert!(
+        result.is_ok(),
+        "Failed to import public keys: {:?}",
+        result.err()
+    );
+    let mut imported = result.unwrap();
+    imported.sort();
+    let mut expected = fingerprints.clone();
+    expected.sort();
+    assert_eq!(imported, expected);
+
+    for fingerprint in &fingerprints {
+        let public_key = gpg::extract_public_key_info(&gpg::GpgContext::default(), fingerprint);
+        assert!(public_key.is_ok(), "Imported public key should be listed");
+        assert_eq!(&public_key.unwrap().fingerprint, fingerprint);
+        assert!(
+            gpg::extract_key_info(&gpg::GpgContext::default(), fingerprint).is_err(),
+            "Only the public key should have been imported"
+        );
+    }
+}
+
+#[test]
+#[serial]
+fn import_public_keys_rejects_secret_keys() {
+    let fixture = GpgTestFixture::new();
+    assert!(fixture.is_ok(), "Failed to create GPG test fixture");
+    let _fixture = fixture.unwrap();
+
+    let gpg_key = include_str!("testdata/no-passphrase.asc");
+    let fingerprint = gpg::preview_key(&gpg::GpgContext::default(), gpg_key)
+        .unwrap()
+        .secret_key
+        .fingerprint;
+
+    for result in [
+        gpg::import_public_keys(&gpg::GpgContext::default(), gpg_key).map(|_| ()),
+        gpg::preview_public_keys(&gpg::GpgContext::default(), gpg_key).map(|_| ()),
+        gpg::preview_public_key_data_isolated(&gpg::GpgContext::default(), gpg_key.as_bytes())
+            .map(|_| ()),
+    ] {
+        let err = result.unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<gpg::GpgError>(),
+            Some(&gpg::GpgError::UnexpectedSecretKey)
+        );
+    }
+    assert!(
+        gpg::extract_public_key_info(&gpg::GpgContext::default(), &fingerprint).is_err(),
+        "Nothing should have been imported"
+    );
+}
+
+#[test]
+#[serial]
+fn preview_public_key_data_isolated_does_not_touch_keyring() {
+    let robin = GpgTestFixture::new().unwrap();
+    let fingerprint = robin
+        .generate_signing_key_for("robin", "robin@dc.com")
+        .unwrap();
+    let public_key = robin
+        .export_public_keys(std::slice::from_ref(&fingerprint))
+        .unwrap();
+    drop(robin);
+
+    let fixture = GpgTestFixture::new();
+    assert!(fixture.is_ok(), "Failed to create GPG test fixture");
+    let _fixture = fixture.unwrap();
+
+    // The fallback for GnuPG versions without `--import-options show-only`
+    let result =
+        gpg::preview_public_key_data_isolated(&gpg::GpgContext::default(), public_key.as_bytes());
+    assert!(
+        result.is_ok(),
+        "Should preview public key: {:?}",
+        result.err()
+    );
+    let keys = result.unwrap();
+    assert_eq!(keys.len(), 1);
+    assert_eq!(keys[0].fingerprint, fingerprint);
+    assert_eq!(keys[0].uids[0].email, "robin@dc.com");
+    assert!(
+        gpg::extract_public_key_info(&gpg::GpgContext::default(), &fingerprint).is_err(),
+        "Previewing must not import the key into the fixture's keyring"
+    );
 }
 
 #[test]
 #[serial]
 fn preview_key_base64() {
     let gpg_key = include_str!("testdata/no-passphrase.base64.key");
-    let result = gpg::preview_key(gpg_key);
+    let result = gpg::preview_key(&gpg::GpgContext::default(), gpg_key);
     assert!(result.is_ok(), "Should preview GPG key");
 
     let key = result.unwrap();
@@ -505,10 +621,31 @@ fn preview_key_base64() {
     assert!(!key.subkeys[0].keygrip.is_empty());
 }
 
+#[test]
+#[serial]
+fn preview_key_isolated_does_not_touch_keyring() {
+    let fixture = GpgTestFixture::new();
+    assert!(fixture.is_ok(), "Failed to create GPG test fixture");
+    let _fixture = fixture.unwrap();
+
+    // The fallback for GnuPG versions without `--import-options show-only`
+    let gpg_key = include_str!("testdata/no-passphrase.base64.key");
+    let result = gpg::preview_key_isolated(&gpg::GpgContext::default(), gpg_key);
+    assert!(result.is_ok(), "Should preview GPG key: {:?}", result.err());
+
+    let key = result.unwrap();
+    assert_eq!(key.primary_uid().name, "batman");
+    assert_eq!(key.subkeys.len(), 1);
+    assert!(
+        gpg::extract_key_info(&gpg::GpgContext::default(), &key.secret_key.fingerprint).is_err(),
+        "Previewing must not import the key into the fixture's keyring"
+    );
+}
+
 #[test]
 fn import_secret_key_invalid_base64() {
     let invalid_base64 = "not-valid-base64!!!";
-    let result = gpg::import_secret_key(invalid_base64);
+    let result = gpg::import_secret_key(&gpg::GpgContext::default(), invalid_base64);
 
     let err = result.unwrap_err();
     let gpg_err = err.downcast_ref::<gpg::GpgError>().unwrap();
@@ -521,7 +658,7 @@ fn import_secret_key_invalid_base64() {
 
 #[test]
 fn import_secret_key_empty_input() {
-    let result = gpg::import_secret_key("");
+    let result = gpg::import_secret_key(&gpg::GpgContext::default(), "");
 
     let err = result.unwrap_err();
     let gpg_err = err.downcast_ref::<gpg::GpgError>().unwrap();
@@ -542,7 +679,7 @@ fn import_secret_key_valid_base64_invalid_gpg_data() {
     use base64::{engine::general_purpose, Engine as _};
     let invalid_gpg_data = general_purpose::STANDARD.encode("not a gpg key");
 
-    let result = gpg::import_secret_key(&invalid_gpg_data);
+    let result = gpg::import_secret_key(&gpg::GpgContext::default(), &invalid_gpg_data);
 
     let err = result.unwrap_err();
     let gpg_err = err.downcast_ref::<gpg::GpgError>().unwrap();
@@ -560,7 +697,7 @@ fn extract_key_info_nonexistent_key() {
     assert!(fixture.is_ok(), "Failed to create GPG test fixture");
     let _fixture = fixture.unwrap();
 
-    let result = gpg::extract_key_info("NONEXISTENT1234567890");
+    let result = gpg::extract_key_info(&gpg::GpgContext::default(), "NONEXISTENT1234567890");
 
     let err = result.unwrap_err();
     let gpg_err = err.downcast_ref::<gpg::GpgError>().unwrap();
@@ -579,7 +716,7 @@ fn import_secret_key_ascii_armored() {
     let fixture = fixture.unwrap();
 
     let gpg_key = include_str!("testdata/no-passphrase.asc");
-    let result = gpg::import_secret_key(gpg_key);
+    let result = gpg::import_secret_key(&gpg::GpgContext::default(), gpg_key);
     assert!(result.is_ok(), "Failed to import ASCII armored GPG key");
 
     let fingerprint = result.unwrap();
@@ -591,7 +728,7 @@ fn import_secret_key_ascii_armored() {
 #[serial]
 fn preview_key_ascii_armored() {
     let gpg_key = include_str!("testdata/no-passphrase.asc");
-    let result = gpg::preview_key(gpg_key);
+    let result = gpg::preview_key(&gpg::GpgContext::default(), gpg_key);
     assert!(result.is_ok(), "Should preview ASCII armored GPG key");
 
     let key = result.unwrap();
@@ -610,3 +747,124 @@ fn fingerprint_not_found_error_format() {
     let err = gpg::GpgError::FingerprintNotFound("ABC123".to_string());
     assert_eq!(format!("{}", err), "fingerprint not found in key: ABC123");
 }
+
+#[test]
+#[serial]
+fn agent_key_info_reports_unprotected_keys() {
+    let fixture = GpgTestFixture::new();
+    assert!(fixture.is_ok(), "Failed to create GPG test fixture");
+    let fixture = fixture.unwrap();
+
+    let fingerprint = fixture
+        .generate_signing_key_for("robin", "robin@dc.com")
+        .unwrap();
+    let key_info = gpg::extract_key_info(&gpg::GpgContext::default(), &fingerprint).unwrap();
+
+    let agent_keys = gpg::agent_key_info(&gpg::GpgContext::default());
+    assert!(
+        agent_keys.is_ok(),
+        "Failed to list agent keys: {:?}",
+        agent_keys.err()
+    );
+    let agent_keys = agent_keys.unwrap();
+    let info = agent_keys
+        .iter()
+        .find(|info| info.keygrip == key_info.secret_key.keygrip);
+    assert!(
+        info.is_some_and(|info| info.protection == gpg::KeyProtection::Unprotected),
+        "An unprotected key should be reported as such: {agent_keys:?}"
+    );
+}
+
+#[test]
+#[serial]
+fn reload_agent_launches_and_confirms_agent() {
+    let fixture = GpgTestFixture::new();
+    assert!(fixture.is_ok(), "Failed to create GPG test fixture");
+    let fixture = fixture.unwrap();
+
+    // Reloading must start an agent that isn't running, rather than
+    // silently doing nothing
+    assert!(fixture.kill_agent().is_ok(), "Failed to kill gpg-agent");
+
+    let agent = gpg::reload_agent(&gpg::GpgContext::default());
+    assert!(
+        agent.is_ok(),
+        "Failed to reload gpg-agent: {:?}",
+        agent.err()
+    );
+    let agent = agent.unwrap();
+    assert!(agent.pid > 0);
+    assert!(!agent.version.is_empty());
+    assert!(
+        agent.socket_name.ends_with("S.gpg-agent"),
+        "Unexpected agent socket: {}",
+        agent.socket_name.display()
+    );
+}
+
+#[test]
+#[serial]
+fn change_passphrase_sends_passphrases_verbatim() {
+    let fixture = GpgTestFixture::new();
+    assert!(fixture.is_ok(), "Failed to create GPG test fixture");
+    let fixture = fixture.unwrap();
+
+    let passphrase = "gotham";
+    let fingerprint = fixture.generate_protected_signing_key(passphrase);
+    assert!(fingerprint.is_ok(), "Failed to generate key");
+    let key_info =
+        gpg::extract_key_info(&gpg::GpgContext::default(), &fingerprint.unwrap()).unwrap();
+    let keygrip = &key_info.secret_key.keygrip;
+
+    // Whitespace is kept, and a newline can't smuggle in agent commands
+    let new_passphrase = " robin\n/bye\nPASSWD 0000 ";
+    let changed = gpg::change_passphrase(
+        &gpg::GpgContext::default(),
+        keygrip,
+        passphrase,
+        new_passphrase,
+    );
+    assert!(
+        changed.is_ok(),
+        "Failed to change passphrase: {:?}",
+        changed.err()
+    );
+
+    assert!(fixture.kill_agent().is_ok(), "Failed to kill gpg-agent");
+    assert!(
+        fixture
+            .change_key_passphrase(keygrip, new_passphrase.trim(), passphrase)
+            .is_err(),
+        "The new passphrase should not have been trimmed"
+    );
+    assert!(
+        fixture
+            .change_key_passphrase(keygrip, new_passphrase, passphrase)
+            .is_ok(),
+        "The new passphrase should unlock the key exactly as given"
+    );
+}
+
+#[test]
+#[serial]
+fn reload_agent_fails_when_loopback_pinentry_is_disallowed() {
+    let fixture = GpgTestFixture::new();
+    assert!(fixture.is_ok(), "Failed to create GPG test fixture");
+    let _fixture = fixture.unwrap();
+
+    let home_dir = std::path::PathBuf::from(env::var("GNUPGHOME").unwrap());
+    std::fs::write(
+        home_dir.join("gpg-agent.conf"),
+        "allow-preset-passphrase\nno-allow-loopback-pinentry\n",
+    )
+    .expect("Failed to write gpg-agent.conf");
+
+    let agent = gpg::reload_agent(&gpg::GpgContext::default());
+    assert_eq!(
+        agent.unwrap_err().downcast_ref::<gpg::GpgError>(),
+        Some(&gpg::GpgError::AgentOptionNotInForce(
+            "allow-loopback-pinentry".to_string()
+        ))
+    );
+}
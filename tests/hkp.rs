@@ -0,0 +1,164 @@
+use gpg_import::{
+    gpg,
+    hkp::{HkpError, Keyserver},
+};
+use serial_test::serial;
+
+mod fixture;
+use fixture::{http::HttpStandIn, GpgTestFixture};
+
+/// Generates a key within its own keyring, returning its fingerprint and
+/// armored public key
+fn generate_public_key() -> (String, String) {
+    let fixture = GpgTestFixture::new();
+    assert!(fixture.is_ok(), "Failed to create GPG test fixture");
+    let fixture = fixture.unwrap();
+
+    let fingerprint = fixture.generate_signing_key_for("Robin", "robin@dc.com");
+    assert!(fingerprint.is_ok(), "Failed to generate key");
+    let fingerprint = fingerprint.unwrap();
+
+    let public_key = fixture.export_public_keys(std::slice::from_ref(&fingerprint));
+    assert!(public_key.is_ok(), "Failed to export public key");
+    (fingerprint, public_key.unwrap())
+}
+
+#[test]
+#[serial]
+fn fetch_key_by_fingerprint() {
+    let (fingerprint, public_key) = generate_public_key();
+    let server = HttpStandIn::serve(vec![("/pks/lookup".to_string(), public_key.into_bytes())]);
+
+    let fixture = GpgTestFixture::new();
+    assert!(fixture.is_ok(), "Failed to create GPG test fixture");
+    let _fixture = fixture.unwrap();
+
+    let keyserver = Keyserver::new(&format!(
+        "hkp://{}",
+        server.base_url().trim_start_matches("http://")
+    ));
+    assert!(keyserver.is_ok(), "Failed to parse keyserver url");
+
+    let key = keyserver.unwrap().fetch_key(&fingerprint.to_lowercase());
+    assert!(key.is_ok(), "Failed to fetch key: {:?}", key.err());
+    assert_eq!(
+        server.requests(),
+        vec![format!(
+            "/pks/lookup?op=get&options=mr&search=0x{fingerprint}"
+        )]
+    );
+
+    let imported = gpg::import_public_key_data(&gpg::GpgContext::default(), &key.unwrap());
+    assert!(
+        imported.is_ok(),
+        "Failed to import key: {:?}",
+        imported.err()
+    );
+    assert_eq!(imported.unwrap(), vec![fingerprint]);
+}
+
+#[test]
+#[serial]
+fn fetch_key_only_returns_requested_key() {
+    let (fingerprint, public_key) = generate_public_key();
+    let (_, other_public_key) = generate_public_key();
+    let server = HttpStandIn::serve(vec![(
+        "/pks/lookup".to_string(),
+        format!("{other_public_key}{public_key}").into_bytes(),
+    )]);
+
+    let fixture = GpgTestFixture::new();
+    assert!(fixture.is_ok(), "Failed to create GPG test fixture");
+    let _fixture = fixture.unwrap();
+
+    let key = Keyserver::new(server.base_url())
+        .unwrap()
+        .fetch_key(&fingerprint);
+    assert!(key.is_ok(), "Failed to fetch key: {:?}", key.err());
+
+    let imported = gpg::import_public_key_data(&gpg::GpgContext::default(), &key.unwrap());
+    assert!(
+        imported.is_ok(),
+        "Failed to import key: {:?}",
+        imported.err()
+    );
+    assert_eq!(imported.unwrap(), vec![fingerprint]);
+}
+
+#[test]
+#[serial]
+fn fetch_key_rejects_oversized_response() {
+    let server = HttpStandIn::serve(vec![(
+        "/pks/lookup".to_string(),
+        vec![b'A'; 1024 * 1024 + 1],
+    )]);
+
+    let result = Keyserver::new(server.base_url())
+        .unwrap()
+        .fetch_key("24DA69B1615F5F3C6A9C3A60B8527C5AED483BE3");
+    assert_eq!(
+        result.unwrap_err().downcast_ref::<HkpError>(),
+        Some(&HkpError::ResponseTooLarge(1024 * 1024))
+    );
+}
+
+#[test]
+#[serial]
+fn fetch_key_rejects_mismatched_fingerprint() {
+    let (_, public_key) = generate_public_key();
+    let server = HttpStandIn::serve(vec![("/pks/lookup".to_string(), public_key.into_bytes())]);
+
+    let fixture = GpgTestFixture::new();
+    assert!(fixture.is_ok(), "Failed to create GPG test fixture");
+    let _fixture = fixture.unwrap();
+
+    let fingerprint = "24DA69B1615F5F3C6A9C3A60B8527C5AED483BE3";
+    let result = Keyserver::new(server.base_url())
+        .unwrap()
+        .fetch_key(fingerprint);
+    assert_eq!(
+        result.unwrap_err().downcast_ref::<HkpError>(),
+        Some(&HkpError::FingerprintMismatch(fingerprint.to_string()))
+    );
+}
+
+#[test]
+#[serial]
+fn fetch_key_not_found() {
+    let server = HttpStandIn::serve(Vec::new());
+
+    let result = Keyserver::new(server.base_url())
+        .unwrap()
+        .fetch_key("24DA69B1615F5F3C6A9C3A60B8527C5AED483BE3");
+    assert!(matches!(
+        result.unwrap_err().downcast_ref::<HkpError>(),
+        Some(HkpError::KeyNotFound(..))
+    ));
+}
+
+#[test]
+#[serial]
+fn publish_key_uploads_armored_public_key() {
+    let fixture = GpgTestFixture::new();
+    assert!(fixture.is_ok(), "Failed to create GPG test fixture");
+    let fixture = fixture.unwrap();
+
+    let fingerprint = fixture.generate_signing_key_for("Robin", "robin@dc.com");
+    assert!(fingerprint.is_ok(), "Failed to generate key");
+    let fingerprint = fingerprint.unwrap();
+
+    let armored = gpg::export_public_key(&gpg::GpgContext::default(), &fingerprint, false);
+    assert!(armored.is_ok(), "Failed to export public key");
+
+    let server = HttpStandIn::serve(Vec::new());
+    let result = Keyserver::new(server.base_url())
+        .unwrap()
+        .publish_key(&fingerprint, &armored.unwrap());
+    assert!(result.is_ok(), "Failed to publish key: {:?}", result.err());
+
+    assert_eq!(server.requests(), vec!["/pks/add".to_string()]);
+    let bodies = server.bodies();
+    assert_eq!(bodies.len(), 1);
+    assert!(String::from_utf8_lossy(&bodies[0])
+        .starts_with("keytext=-----BEGIN+PGP+PUBLIC+KEY+BLOCK-----"));
+}
@@ -1,14 +1,18 @@
 use git2::Repository;
-use gpg_import::{gpg, import::GpgImport};
+use gpg_import::{
+    gpg,
+    import::GpgImport,
+    passphrase::{KeyPassphrase, PassphraseChange, PassphraseSource},
+};
 use serial_test::serial;
 use std::{env, path::Path};
 use tempfile::TempDir;
 
 mod fixture;
-use fixture::GpgTestFixture;
+use fixture::{http::HttpStandIn, GpgTestFixture};
 
 /// Temporarily changes the process's current directory, restoring it when
-/// dropped. `git::is_repo` resolves the repo via `Repository::open(".")`,
+/// dropped. `git::is_repo` discovers the repo from the current directory,
 /// so tests that exercise git configuration need to point the process at a
 /// throwaway repo instead of this project's own checkout.
 struct CwdGuard {
@@ -37,18 +41,7 @@ fn import_resolves_and_signs_with_non_first_subkey_via_fingerprint() {
     let fixture = fixture.unwrap();
 
     let passphrase = "gotham";
-    let batch_config = format!(
-        "Key-Type: RSA
-Key-Length: 2048
-Key-Usage: sign
-Name-Real: batman
-Name-Email: batman@dc.com
-Passphrase: {passphrase}
-%commit
-"
-    );
-
-    let fingerprint = fixture.generate_key(&batch_config, None);
+    let fingerprint = fixture.generate_protected_signing_key(passphrase);
     assert!(fingerprint.is_ok(), "Failed to generate primary key");
     let fingerprint = fingerprint.unwrap();
 
@@ -78,7 +71,7 @@ Passphrase: {passphrase}
     // GpgImport::import()'s own passphrase-preset step actually works.
     assert!(fixture.kill_agent().is_ok(), "Failed to kill gpg-agent");
 
-    let key_info = gpg::extract_key_info(&fingerprint);
+    let key_info = gpg::extract_key_info(&gpg::GpgContext::default(), &fingerprint);
     assert!(key_info.is_ok(), "Failed to extract key info");
     let key_info = key_info.unwrap();
     assert_eq!(key_info.subkeys.len(), 2, "Expected 2 subkeys");
@@ -113,8 +106,9 @@ Passphrase: {passphrase}
         .get_string("user.signingKey")
         .expect("user.signingKey should be set");
     assert_eq!(
-        signing_key, signing_subkey_fingerprint,
-        "git should be configured to sign with the requested non-first subkey"
+        signing_key,
+        format!("{signing_subkey_fingerprint}!"),
+        "git should be configured to sign with exactly the requested non-first subkey"
     );
 
     let sign_result = fixture.create_and_sign_file(&signing_subkey_fingerprint);
@@ -124,3 +118,830 @@ Passphrase: {passphrase}
         sign_result.err()
     );
 }
+
+#[test]
+#[serial]
+fn import_with_auto_trust_ultimately_trusts_imported_key() {
+    let fixture = GpgTestFixture::new();
+    assert!(fixture.is_ok(), "Failed to create GPG test fixture");
+    let _fixture = fixture.unwrap();
+
+    let gpg_key = include_str!("testdata/no-passphrase.asc");
+    let preview = gpg::preview_key(&gpg::GpgContext::default(), gpg_key);
+    assert!(preview.is_ok(), "Failed to preview GPG key");
+    let fingerprint = preview.unwrap().secret_key.fingerprint;
+
+    let result = GpgImport::new(gpg_key.to_string())
+        .auto_trust(true)
+        .skip_git(true)
+        .import();
+    assert!(
+        result.is_ok(),
+        "GpgImport::import() with auto-trust should succeed: {:?}",
+        result.err()
+    );
+
+    let ownertrust = gpg::read_ownertrust(&gpg::GpgContext::default(), &fingerprint);
+    assert!(ownertrust.is_ok(), "Failed to read back ownertrust");
+    assert_eq!(
+        ownertrust.unwrap(),
+        Some(6),
+        "The imported key should be ultimately trusted"
+    );
+}
+
+#[test]
+#[serial]
+fn import_with_publish_uploads_public_key_to_keyserver() {
+    let fixture = GpgTestFixture::new();
+    assert!(fixture.is_ok(), "Failed to create GPG test fixture");
+    let _fixture = fixture.unwrap();
+
+    let server = HttpStandIn::serve(Vec::new());
+    let result = GpgImport::new(include_str!("testdata/no-passphrase.asc").to_string())
+        .with_keyserver(Some(server.base_url().to_string()))
+        .publish(true)
+        .skip_git(true)
+        .import();
+    assert!(
+        result.is_ok(),
+        "GpgImport::import() with publish should succeed: {:?}",
+        result.err()
+    );
+
+    assert_eq!(server.requests(), vec!["/pks/add".to_string()]);
+}
+
+#[test]
+#[serial]
+fn import_exports_public_key_to_file_and_step_output() {
+    let fixture = GpgTestFixture::new();
+    assert!(fixture.is_ok(), "Failed to create GPG test fixture");
+    let _fixture = fixture.unwrap();
+
+    let gpg_key = include_str!("testdata/no-passphrase.asc");
+    let preview = gpg::preview_key(&gpg::GpgContext::default(), gpg_key);
+    assert!(preview.is_ok(), "Failed to preview GPG key");
+    let fingerprint = preview.unwrap().secret_key.fingerprint;
+
+    let export_dir = TempDir::new().unwrap();
+    let export_path = export_dir.path().join("public.asc");
+    let output_path = export_dir.path().join("github_output");
+    let original_output = env::var_os("GITHUB_OUTPUT");
+    env::set_var("GITHUB_OUTPUT", &output_path);
+
+    let result = GpgImport::new(gpg_key.to_string())
+        .with_export_public(Some(export_path.clone()))
+        .with_export_public_output(Some("public_key".to_string()))
+        .export_minimal(true)
+        .skip_git(true)
+        .import();
+
+    match original_output {
+        Some(original) => env::set_var("GITHUB_OUTPUT", original),
+        None => env::remove_var("GITHUB_OUTPUT"),
+    }
+    assert!(
+        result.is_ok(),
+        "GpgImport::import() with an export should succeed: {:?}",
+        result.err()
+    );
+
+    let exported = std::fs::read_to_string(&export_path).expect("Failed to read exported key");
+    assert!(exported.starts_with("-----BEGIN PGP PUBLIC KEY BLOCK-----"));
+    let public_keys = gpg::preview_public_keys(&gpg::GpgContext::default(), &exported);
+    assert!(
+        public_keys.is_ok(),
+        "Exported key should be a valid public key"
+    );
+    assert_eq!(public_keys.unwrap()[0].fingerprint, fingerprint);
+
+    let output = std::fs::read_to_string(&output_path).expect("Failed to read step output");
+    let delimiter = output
+        .lines()
+        .next()
+        .and_then(|line| line.strip_prefix("public_key<<"))
+        .expect("Step output should start with a heredoc");
+    assert!(delimiter.starts_with("ghadelimiter_"));
+    assert_eq!(delimiter.len(), "ghadelimiter_".len() + 32);
+    assert!(output.contains(&exported));
+    assert!(output.ends_with(&format!("\n{delimiter}\n")));
+}
+
+#[test]
+#[serial]
+fn import_maintains_allowed_signers_and_configures_ssh_mode() {
+    let fixture = GpgTestFixture::new();
+    assert!(fixture.is_ok(), "Failed to create GPG test fixture");
+    let fixture = fixture.unwrap();
+
+    let fingerprint = fixture.generate_signing_key().unwrap();
+    fixture.add_subkey(&fingerprint, "auth").unwrap();
+    let armored = fixture.export_secret_key(&fingerprint).unwrap();
+    let email = gpg::preview_key(&gpg::GpgContext::default(), &armored)
+        .unwrap()
+        .primary_uid()
+        .email
+        .clone();
+
+    let repo_dir = TempDir::new().unwrap();
+    let repo = Repository::init(repo_dir.path()).expect("Failed to init throwaway git repo");
+    repo.config()
+        .expect("Failed to read throwaway repo config")
+        .set_str("gpg.format", "ssh")
+        .expect("Failed to enable ssh signing");
+
+    let allowed_signers = repo_dir.path().join("allowed_signers");
+    std::fs::write(&allowed_signers, "# maintained by ci\n")
+        .expect("Failed to seed allowed signers file");
+
+    // Without ssh support, the agent wouldn't serve the key git signs with
+    let result = GpgImport::new(armored.clone())
+        .with_repos(vec![repo_dir.path().to_path_buf()])
+        .import();
+    let error = format!("{:#}", result.unwrap_err());
+    assert!(
+        error.contains("requires ssh support"),
+        "Unexpected error: {error}"
+    );
+
+    // Importing twice should leave a single entry for the key
+    for _ in 0..2 {
+        let result = GpgImport::new(armored.clone())
+            .with_repos(vec![repo_dir.path().to_path_buf()])
+            .with_allowed_signers(Some(allowed_signers.clone()))
+            .with_agent_config(gpg::AgentConfig {
+                enable_ssh_support: true,
+                ..Default::default()
+            })
+            .import();
+        assert!(
+            result.is_ok(),
+            "GpgImport::import() with allowed signers should succeed: {:?}",
+            result.err()
+        );
+    }
+
+    let contents =
+        std::fs::read_to_string(&allowed_signers).expect("Failed to read allowed signers file");
+    let lines: Vec<&str> = contents.lines().collect();
+    assert_eq!(lines.len(), 2, "Unexpected allowed signers: {contents}");
+    assert_eq!(lines[0], "# maintained by ci");
+    assert!(lines[1].starts_with(&format!("{email} namespaces=\"git\" ssh-")));
+
+    let config = repo.config().expect("Failed to read throwaway repo config");
+    assert_eq!(
+        config.get_string("gpg.ssh.allowedSignersFile").unwrap(),
+        allowed_signers.to_string_lossy()
+    );
+
+    // Both name the authentication subkey, which the agent serves
+    let signing_key = config.get_string("user.signingKey").unwrap();
+    let ssh_key = lines[1]
+        .split_whitespace()
+        .skip(2)
+        .take(2)
+        .collect::<Vec<_>>()
+        .join(" ");
+    assert!(
+        signing_key.starts_with(&format!("key::{ssh_key} ")),
+        "Unexpected signing key: {signing_key}"
+    );
+    assert!(lines[1].ends_with(&format!(" gpg-import:{fingerprint}")));
+
+    let ssh_auth_sock = gpg::ssh_auth_sock(&gpg::GpgContext::default()).unwrap();
+    let git = |args: &[&str]| {
+        std::process::Command::new("git")
+            .current_dir(repo_dir.path())
+            .env("SSH_AUTH_SOCK", &ssh_auth_sock)
+            .args(args)
+            .output()
+            .expect("Failed to run git")
+    };
+
+    let commit = git(&["commit", "--allow-empty", "-S", "-m", "signed over ssh"]);
+    assert!(
+        commit.status.success(),
+        "An SSH signed commit should be made: {}",
+        String::from_utf8_lossy(&commit.stderr)
+    );
+    let verify = git(&["verify-commit", "HEAD"]);
+    assert!(
+        verify.status.success(),
+        "The SSH signed commit should verify against the allowed signers: {}",
+        String::from_utf8_lossy(&verify.stderr)
+    );
+}
+
+#[test]
+#[serial]
+fn import_installs_pre_push_hook_rejecting_unsigned_commits() {
+    let fixture = GpgTestFixture::new();
+    assert!(fixture.is_ok(), "Failed to create GPG test fixture");
+    let _fixture = fixture.unwrap();
+
+    let remote_dir = TempDir::new().unwrap();
+    Repository::init_bare(remote_dir.path()).expect("Failed to init bare remote");
+    let repo_dir = TempDir::new().unwrap();
+    let repo = Repository::init(repo_dir.path()).expect("Failed to init throwaway git repo");
+    repo.remote("origin", &remote_dir.path().to_string_lossy())
+        .expect("Failed to add remote");
+    repo.config()
+        .expect("Failed to read throwaway repo config")
+        .set_str("core.hooksPath", ".githooks")
+        .expect("Failed to set core.hooksPath");
+
+    let result = GpgImport::new(include_str!("testdata/no-passphrase.asc").to_string())
+        .with_repos(vec![repo_dir.path().to_path_buf()])
+        .install_pre_push_hook(true)
+        .import();
+    assert!(
+        result.is_ok(),
+        "GpgImport::import() with a pre-push hook should succeed: {:?}",
+        result.err()
+    );
+    assert!(repo_dir.path().join(".githooks/pre-push").exists());
+
+    let git = |args: &[&str]| {
+        std::process::Command::new("git")
+            .current_dir(repo_dir.path())
+            .args(args)
+            .output()
+            .expect("Failed to run git")
+    };
+
+    assert!(git(&["commit", "--allow-empty", "-m", "signed"])
+        .status
+        .success());
+    let push = git(&["push", "origin", "HEAD:refs/heads/main"]);
+    assert!(
+        push.status.success(),
+        "A signed commit should be pushed: {}",
+        String::from_utf8_lossy(&push.stderr)
+    );
+
+    // An email resolves to the key's fingerprint, rather than being matched
+    // against the end of the signing fingerprint
+    let email = gpg::preview_key(
+        &gpg::GpgContext::default(),
+        include_str!("testdata/no-passphrase.asc"),
+    )
+    .unwrap()
+    .primary_uid()
+    .email
+    .clone();
+    assert!(git(&["config", "user.signingKey", &email]).status.success());
+    assert!(git(&["commit", "--allow-empty", "-m", "signed by email"])
+        .status
+        .success());
+    let push = git(&["push", "origin", "HEAD:refs/heads/main"]);
+    assert!(
+        push.status.success(),
+        "A commit signed by the key of an email should be pushed: {}",
+        String::from_utf8_lossy(&push.stderr)
+    );
+
+    assert!(
+        git(&["commit", "--allow-empty", "--no-gpg-sign", "-m", "unsigned"])
+            .status
+            .success()
+    );
+    let push = git(&["push", "origin", "HEAD:refs/heads/main"]);
+    assert!(
+        !push.status.success(),
+        "An unsigned commit must be rejected"
+    );
+    assert!(String::from_utf8_lossy(&push.stderr).contains("pre-push: refusing to push"));
+}
+
+#[test]
+#[serial]
+fn pre_push_hook_resolves_ssh_key_path_and_pushes_to_url() {
+    let fixture = GpgTestFixture::new();
+    assert!(fixture.is_ok(), "Failed to create GPG test fixture");
+    let fixture = fixture.unwrap();
+
+    let fingerprint = fixture.generate_signing_key().unwrap();
+    fixture.add_subkey(&fingerprint, "auth").unwrap();
+    let armored = fixture.export_secret_key(&fingerprint).unwrap();
+
+    let remote_dir = TempDir::new().unwrap();
+    Repository::init_bare(remote_dir.path()).expect("Failed to init bare remote");
+    let remote_url = remote_dir.path().to_string_lossy().to_string();
+    let repo_dir = TempDir::new().unwrap();
+    let repo = Repository::init(repo_dir.path()).expect("Failed to init throwaway git repo");
+    repo.remote("origin", &remote_url)
+        .expect("Failed to add remote");
+    let mut config = repo.config().expect("Failed to read throwaway repo config");
+    config
+        .set_str("gpg.format", "ssh")
+        .expect("Failed to enable ssh signing");
+    config
+        .set_str("user.name", "Batman")
+        .expect("Failed to set user.name");
+    config
+        .set_str("user.email", "batman@dc.com")
+        .expect("Failed to set user.email");
+
+    let ssh_auth_sock = gpg::ssh_auth_sock(&gpg::GpgContext::default()).unwrap();
+    let git = |args: &[&str]| {
+        std::process::Command::new("git")
+            .current_dir(repo_dir.path())
+            .env("SSH_AUTH_SOCK", &ssh_auth_sock)
+            .args(args)
+            .output()
+            .expect("Failed to run git")
+    };
+
+    // History pushed before the hook was installed isn't signed
+    assert!(
+        git(&["commit", "--allow-empty", "--no-gpg-sign", "-m", "unsigned"])
+            .status
+            .success()
+    );
+    assert!(git(&["push", "origin", "HEAD:refs/heads/main"])
+        .status
+        .success());
+
+    let allowed_signers = repo_dir.path().join(".git/allowed_signers");
+    let result = GpgImport::new(armored)
+        .with_repos(vec![repo_dir.path().to_path_buf()])
+        .with_allowed_signers(Some(allowed_signers))
+        .with_agent_config(gpg::AgentConfig {
+            enable_ssh_support: true,
+            ..Default::default()
+        })
+        .install_pre_push_hook(true)
+        .import();
+    assert!(
+        result.is_ok(),
+        "GpgImport::import() with a pre-push hook should succeed: {:?}",
+        result.err()
+    );
+
+    // Point user.signingKey at a public key file, rather than key::...
+    let signing_key = repo
+        .config()
+        .unwrap()
+        .get_string("user.signingKey")
+        .unwrap();
+    let key_path = repo_dir.path().join(".git/signing_key.pub");
+    std::fs::write(&key_path, signing_key.strip_prefix("key::").unwrap())
+        .expect("Failed to write ssh public key");
+    assert!(
+        git(&["config", "user.signingKey", &key_path.to_string_lossy()])
+            .status
+            .success()
+    );
+
+    // Pushing a new branch to a URL only checks commits missing from the
+    // remotes
+    assert!(git(&["commit", "--allow-empty", "-m", "signed"])
+        .status
+        .success());
+    let push = git(&["push", &remote_url, "HEAD:refs/heads/signed"]);
+    assert!(
+        push.status.success(),
+        "A commit signed by the key file should be pushed: {}",
+        String::from_utf8_lossy(&push.stderr)
+    );
+
+    assert!(
+        git(&["commit", "--allow-empty", "--no-gpg-sign", "-m", "unsigned"])
+            .status
+            .success()
+    );
+    let push = git(&["push", &remote_url, "HEAD:refs/heads/unsigned"]);
+    assert!(
+        !push.status.success(),
+        "An unsigned commit must be rejected"
+    );
+    assert!(String::from_utf8_lossy(&push.stderr).contains("pre-push: refusing to push"));
+}
+
+#[test]
+#[serial]
+fn import_signs_artifacts_with_selected_subkey() {
+    let fixture = GpgTestFixture::new();
+    assert!(fixture.is_ok(), "Failed to create GPG test fixture");
+    let fixture = fixture.unwrap();
+
+    let passphrase = "gotham";
+    let fingerprint = fixture.generate_protected_signing_key(passphrase);
+    assert!(fingerprint.is_ok(), "Failed to generate primary key");
+    let fingerprint = fingerprint.unwrap();
+
+    // gpg signs with the most recent signing subkey unless the first one is
+    // explicitly forced
+    for _ in 0..2 {
+        assert!(
+            fixture
+                .add_protected_subkey(&fingerprint, "sign", passphrase)
+                .is_ok(),
+            "Failed to add sign subkey"
+        );
+    }
+    let armored = fixture.export_protected_secret_key(&fingerprint, passphrase);
+    assert!(armored.is_ok(), "Failed to export multi-subkey GPG key");
+    assert!(fixture.kill_agent().is_ok(), "Failed to kill gpg-agent");
+
+    let key_info = gpg::extract_key_info(&gpg::GpgContext::default(), &fingerprint);
+    assert!(key_info.is_ok(), "Failed to extract key info");
+    let first_subkey_fingerprint = key_info.unwrap().subkeys[0].fingerprint.clone();
+
+    let artifacts_dir = TempDir::new().unwrap();
+    let tarball = artifacts_dir.path().join("release.tar.gz");
+    let checksums = artifacts_dir.path().join("SHA256SUMS");
+    std::fs::write(&tarball, "tarball").expect("Failed to write tarball");
+    std::fs::write(&checksums, "abc123  release.tar.gz\n").expect("Failed to write checksums");
+
+    let result = GpgImport::new(armored.unwrap())
+        .with_passphrase(Some(passphrase.to_string()))
+        .with_fingerprint(Some(first_subkey_fingerprint.clone()))
+        .skip_git(true)
+        .with_detached_signatures(vec![tarball.clone()])
+        .binary_signatures(true)
+        .with_clearsigned(vec![checksums.clone()])
+        .import();
+    assert!(
+        result.is_ok(),
+        "GpgImport::import() with artifacts to sign should succeed: {:?}",
+        result.err()
+    );
+
+    let signature = std::fs::read(artifacts_dir.path().join("release.tar.gz.sig"))
+        .expect("Failed to read detached signature");
+    let verified =
+        gpg::verify_detached_signature(&gpg::GpgContext::default(), &signature, b"tarball");
+    assert!(verified.is_ok(), "Failed to verify detached signature");
+    let verified = verified.unwrap();
+    assert_eq!(verified.status, gpg::SignatureStatus::Good);
+    assert_eq!(
+        verified.fingerprint.as_deref(),
+        Some(first_subkey_fingerprint.as_str()),
+        "The selected subkey should have been forced with the ! suffix"
+    );
+
+    let clearsigned = std::fs::read_to_string(artifacts_dir.path().join("SHA256SUMS.asc"))
+        .expect("Failed to read clearsigned checksums");
+    assert!(clearsigned.starts_with("-----BEGIN PGP SIGNED MESSAGE-----"));
+    assert!(clearsigned.contains("abc123  release.tar.gz"));
+}
+
+#[test]
+#[serial]
+fn import_presets_a_different_passphrase_for_each_subkey() {
+    let fixture = GpgTestFixture::new();
+    assert!(fixture.is_ok(), "Failed to create GPG test fixture");
+    let fixture = fixture.unwrap();
+
+    let passphrase = "gotham";
+    let fingerprint = fixture.generate_protected_signing_key(passphrase).unwrap();
+    fixture
+        .add_protected_subkey(&fingerprint, "sign", passphrase)
+        .unwrap();
+    let armored = fixture
+        .export_protected_secret_key(&fingerprint, passphrase)
+        .unwrap();
+
+    // Re-protect the subkey only, so a single passphrase can no longer
+    // unlock both the primary key and the subkey
+    let key_info = gpg::extract_key_info(&gpg::GpgContext::default(), &fingerprint).unwrap();
+    let subkey = &key_info.subkeys[0];
+    fixture
+        .change_key_passphrase(&subkey.keygrip, passphrase, "robin")
+        .unwrap();
+    assert!(fixture.kill_agent().is_ok(), "Failed to kill gpg-agent");
+
+    let key_passphrase: KeyPassphrase = format!("{}=robin", subkey.fingerprint).parse().unwrap();
+    let result = GpgImport::new(armored)
+        .with_passphrase(Some(passphrase.to_string()))
+        .with_key_passphrases(vec![key_passphrase])
+        .with_fingerprint(Some(subkey.fingerprint.clone()))
+        .skip_git(true)
+        .import();
+    assert!(result.is_ok(), "Import failed: {:?}", result.err());
+
+    let agent_keys = gpg::agent_key_info(&gpg::GpgContext::default()).unwrap();
+    for keygrip in [&key_info.secret_key.keygrip, &subkey.keygrip] {
+        let info = agent_keys.iter().find(|info| &info.keygrip == keygrip);
+        assert!(
+            info.is_some_and(|info| info.protection == gpg::KeyProtection::Protected),
+            "{keygrip} should be reported as protected: {agent_keys:?}"
+        );
+    }
+
+    let sign_result = fixture.create_and_sign_file(&subkey.fingerprint);
+    assert!(
+        sign_result.is_ok(),
+        "The subkey should sign with its own preset passphrase: {:?}",
+        sign_result.err()
+    );
+}
+
+#[test]
+#[serial]
+fn import_strips_passphrase_so_signing_survives_agent_restart() {
+    let fixture = GpgTestFixture::new();
+    assert!(fixture.is_ok(), "Failed to create GPG test fixture");
+    let fixture = fixture.unwrap();
+
+    let passphrase = "gotham";
+    let (fingerprint, armored) = fixture.generate_protected_secret_key(passphrase).unwrap();
+
+    let result = GpgImport::new(armored)
+        .with_passphrase(Some(passphrase.to_string()))
+        .with_passphrase_change(Some(PassphraseChange::Strip))
+        .skip_git(true)
+        .import();
+    assert!(result.is_ok(), "Import failed: {:?}", result.err());
+
+    let key_info = gpg::extract_key_info(&gpg::GpgContext::default(), &fingerprint).unwrap();
+    let agent_keys = gpg::agent_key_info(&gpg::GpgContext::default()).unwrap();
+    assert!(
+        agent_keys
+            .iter()
+            .any(|info| info.keygrip == key_info.secret_key.keygrip
+                && info.protection == gpg::KeyProtection::Unprotected),
+        "The key should no longer be protected: {agent_keys:?}"
+    );
+
+    // Restarting the agent flushes every cached passphrase
+    assert!(fixture.kill_agent().is_ok(), "Failed to kill gpg-agent");
+    let sign_result = fixture.create_and_sign_file(&fingerprint);
+    assert!(
+        sign_result.is_ok(),
+        "A stripped key should sign without any passphrase: {:?}",
+        sign_result.err()
+    );
+}
+
+#[test]
+#[serial]
+fn import_rotates_passphrase_to_one_held_only_in_agent() {
+    let fixture = GpgTestFixture::new();
+    assert!(fixture.is_ok(), "Failed to create GPG test fixture");
+    let fixture = fixture.unwrap();
+
+    let passphrase = "gotham";
+    let (fingerprint, armored) = fixture.generate_protected_secret_key(passphrase).unwrap();
+
+    let result = GpgImport::new(armored)
+        .with_passphrase(Some(passphrase.to_string()))
+        .with_passphrase_change(Some(PassphraseChange::Rotate))
+        .skip_git(true)
+        .import();
+    assert!(result.is_ok(), "Import failed: {:?}", result.err());
+
+    let sign_result = fixture.create_and_sign_file(&fingerprint);
+    assert!(
+        sign_result.is_ok(),
+        "The rotated passphrase should be preset within the agent: {:?}",
+        sign_result.err()
+    );
+
+    // Once the agent forgets the rotated passphrase, the original one no
+    // longer unlocks the key
+    assert!(fixture.kill_agent().is_ok(), "Failed to kill gpg-agent");
+    let key_info = gpg::extract_key_info(&gpg::GpgContext::default(), &fingerprint).unwrap();
+    let original = fixture.change_key_passphrase(&key_info.secret_key.keygrip, passphrase, "robin");
+    assert!(
+        original.is_err(),
+        "The original passphrase should have been replaced"
+    );
+}
+
+#[test]
+#[serial]
+fn import_writes_rotated_passphrase_file() {
+    let fixture = GpgTestFixture::new();
+    assert!(fixture.is_ok(), "Failed to create GPG test fixture");
+    let fixture = fixture.unwrap();
+
+    let passphrase = "gotham";
+    let (fingerprint, armored) = fixture.generate_protected_secret_key(passphrase).unwrap();
+
+    let output_dir = TempDir::new().unwrap();
+    let passphrase_file = output_dir.path().join("rotated");
+    let result = GpgImport::new(armored)
+        .with_passphrase(Some(passphrase.to_string()))
+        .with_passphrase_change(Some(PassphraseChange::Rotate))
+        .with_rotated_passphrase_file(Some(passphrase_file.clone()))
+        .skip_git(true)
+        .import();
+    assert!(result.is_ok(), "Import failed: {:?}", result.err());
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = std::fs::metadata(&passphrase_file)
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    // The written passphrase still unlocks the key once the agent forgets it
+    assert!(fixture.kill_agent().is_ok(), "Failed to kill gpg-agent");
+    let rotated = std::fs::read_to_string(&passphrase_file).unwrap();
+    let key_info = gpg::extract_key_info(&gpg::GpgContext::default(), &fingerprint).unwrap();
+    let unlocked =
+        fixture.change_key_passphrase(&key_info.secret_key.keygrip, rotated.trim_end(), "robin");
+    assert!(
+        unlocked.is_ok(),
+        "The rotated passphrase should unlock the key: {:?}",
+        unlocked.err()
+    );
+}
+
+#[test]
+#[serial]
+fn import_enables_ssh_support_for_authentication_subkey() {
+    let fixture = GpgTestFixture::new();
+    assert!(fixture.is_ok(), "Failed to create GPG test fixture");
+    let fixture = fixture.unwrap();
+
+    let fingerprint = fixture.generate_signing_key().unwrap();
+    fixture.add_subkey(&fingerprint, "auth").unwrap();
+    let armored = fixture.export_secret_key(&fingerprint).unwrap();
+
+    let env_dir = TempDir::new().unwrap();
+    let env_path = env_dir.path().join("github_env");
+    let original_env = env::var_os("GITHUB_ENV");
+    env::set_var("GITHUB_ENV", &env_path);
+
+    let result = GpgImport::new(armored)
+        .with_agent_config(gpg::AgentConfig {
+            enable_ssh_support: true,
+            ..Default::default()
+        })
+        .skip_git(true)
+        .import();
+
+    match original_env {
+        Some(original) => env::set_var("GITHUB_ENV", original),
+        None => env::remove_var("GITHUB_ENV"),
+    }
+    assert!(result.is_ok(), "Import failed: {:?}", result.err());
+
+    let ssh_auth_sock = gpg::ssh_auth_sock(&gpg::GpgContext::default()).unwrap();
+    assert_eq!(
+        std::fs::read_to_string(&env_path).unwrap(),
+        format!("SSH_AUTH_SOCK={}\n", ssh_auth_sock.display())
+    );
+
+    let key_info = gpg::extract_key_info(&gpg::GpgContext::default(), &fingerprint).unwrap();
+    let auth_subkey = &key_info.subkeys[0];
+    assert!(auth_subkey.capabilities.authenticate);
+    let ssh_key =
+        gpg::export_ssh_key(&gpg::GpgContext::default(), &auth_subkey.fingerprint).unwrap();
+    let ssh_key_material = ssh_key.split_whitespace().nth(1).unwrap();
+
+    let identities = std::process::Command::new("ssh-add")
+        .arg("-L")
+        .env("SSH_AUTH_SOCK", &ssh_auth_sock)
+        .output()
+        .expect("Failed to run ssh-add");
+    let identities = String::from_utf8_lossy(&identities.stdout);
+    assert!(
+        identities.contains(ssh_key_material),
+        "The agent should offer the authentication subkey to ssh clients: {identities}"
+    );
+}
+
+#[test]
+#[serial]
+fn import_rejects_ssh_support_without_preset_passphrase() {
+    let fixture = GpgTestFixture::new();
+    assert!(fixture.is_ok(), "Failed to create GPG test fixture");
+    let fixture = fixture.unwrap();
+
+    let passphrase = "gotham";
+    let fingerprint = fixture.generate_protected_signing_key(passphrase).unwrap();
+    fixture
+        .add_protected_subkey(&fingerprint, "auth", passphrase)
+        .unwrap();
+    let armored = fixture
+        .export_protected_secret_key(&fingerprint, passphrase)
+        .unwrap();
+    assert!(fixture.kill_agent().is_ok(), "Failed to kill gpg-agent");
+
+    // Only the primary key's passphrase is preset, leaving the
+    // authentication subkey locked
+    let primary = gpg::preview_key(&gpg::GpgContext::default(), &armored)
+        .unwrap()
+        .secret_key;
+    let result = GpgImport::new(armored)
+        .with_key_passphrases(vec![KeyPassphrase {
+            key: primary.fingerprint,
+            source: PassphraseSource::Value(passphrase.to_string()),
+        }])
+        .with_agent_config(gpg::AgentConfig {
+            enable_ssh_support: true,
+            ..Default::default()
+        })
+        .skip_git(true)
+        .import();
+
+    let error = format!("{:#}", result.unwrap_err());
+    assert!(
+        error.contains("was not preset"),
+        "Unexpected error: {error}"
+    );
+    let home_dir = std::path::PathBuf::from(env::var("GNUPGHOME").unwrap());
+    assert!(!home_dir.join("sshcontrol").exists());
+}
+
+#[cfg(unix)]
+#[test]
+#[serial]
+fn import_into_pinned_home_dir_with_non_utf8_path() {
+    use std::os::unix::ffi::{OsStrExt, OsStringExt};
+
+    let fixture = GpgTestFixture::new();
+    assert!(fixture.is_ok(), "Failed to create GPG test fixture");
+    let _fixture = fixture.unwrap();
+
+    let gpg_key = include_str!("testdata/no-passphrase.asc");
+    let fingerprint = gpg::preview_key(&gpg::GpgContext::default(), gpg_key)
+        .unwrap()
+        .secret_key
+        .fingerprint;
+
+    // A space and a byte that isn't valid UTF-8, neither of which survive
+    // being scraped from the Home: line of gpg --version
+    let home_parent = TempDir::new().unwrap();
+    let home_dir = home_parent
+        .path()
+        .join(std::ffi::OsString::from_vec(b"gotham city \xff".to_vec()));
+
+    let env_dir = TempDir::new().unwrap();
+    let env_path = env_dir.path().join("github_env");
+    let original_env = env::var_os("GITHUB_ENV");
+    env::set_var("GITHUB_ENV", &env_path);
+
+    let result = GpgImport::new(gpg_key.to_string())
+        .with_home_dir(Some(home_dir.clone()))
+        .skip_git(true)
+        .import();
+
+    match original_env {
+        Some(original) => env::set_var("GITHUB_ENV", original),
+        None => env::remove_var("GITHUB_ENV"),
+    }
+    let pinned = gpg::GpgContext::default().with_home_dir(Some(home_dir.clone()));
+    let pinned_key = gpg::extract_key_info(&pinned, &fingerprint);
+    let _ = std::process::Command::new("gpgconf")
+        .arg("--homedir")
+        .arg(&home_dir)
+        .args(["--kill", "gpg-agent"])
+        .output();
+
+    assert!(result.is_ok(), "Import failed: {:?}", result.err());
+    assert!(
+        pinned_key.is_ok(),
+        "The key should be within the pinned homedir"
+    );
+    assert!(
+        gpg::extract_key_info(&gpg::GpgContext::default(), &fingerprint).is_err(),
+        "The key shouldn't be imported into GNUPGHOME"
+    );
+    assert!(home_dir.join("gpg.conf").exists());
+    assert!(home_dir.join("gpg-agent.conf").exists());
+
+    let mut expected = b"GNUPGHOME=".to_vec();
+    expected.extend_from_slice(home_dir.as_os_str().as_bytes());
+    expected.push(b'\n');
+    assert_eq!(std::fs::read(&env_path).unwrap(), expected);
+}
+
+#[test]
+#[serial]
+fn import_rejects_pinned_home_dir_git_would_not_reach() {
+    let fixture = GpgTestFixture::new();
+    assert!(fixture.is_ok(), "Failed to create GPG test fixture");
+    let _fixture = fixture.unwrap();
+
+    let repo_dir = TempDir::new().unwrap();
+    Repository::init(repo_dir.path()).expect("Failed to init throwaway git repo");
+    let home_parent = TempDir::new().unwrap();
+    let home_dir = home_parent.path().join("gnupg");
+
+    let original_env = env::var_os("GITHUB_ENV");
+    env::remove_var("GITHUB_ENV");
+
+    let result = GpgImport::new(include_str!("testdata/no-passphrase.asc").to_string())
+        .with_home_dir(Some(home_dir.clone()))
+        .with_repos(vec![repo_dir.path().to_path_buf()])
+        .import();
+
+    if let Some(original) = original_env {
+        env::set_var("GITHUB_ENV", original);
+    }
+    let err = result.expect_err("git can't reach the pinned homedir");
+    assert!(
+        err.to_string().contains("git runs gpg without --homedir"),
+        "Unexpected error: {err}"
+    );
+    assert!(!home_dir.exists(), "Nothing should be imported");
+}
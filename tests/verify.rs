@@ -0,0 +1,204 @@
+use git2::Repository;
+use gpg_import::{
+    gpg::SignatureStatus,
+    verify::{ObjectKind, Verifier},
+};
+use serial_test::serial;
+use std::{path::Path, process::Command};
+use tempfile::TempDir;
+
+mod fixture;
+use fixture::GpgTestFixture;
+
+/// Runs git within the repository, signing with the given key
+fn git(repo_dir: &Path, signing_key: &str, args: &[&str]) {
+    let output = Command::new("git")
+        .current_dir(repo_dir)
+        .args(["-c", "user.name=batman", "-c", "user.email=batman@dc.com"])
+        .args(["-c", &format!("user.signingKey={signing_key}")])
+        .args(args)
+        .output()
+        .expect("Failed to run git");
+    assert!(
+        output.status.success(),
+        "git {args:?} failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+/// Creates a repository with a signed commit, followed by an unsigned commit,
+/// with a signed tag pointing at the first
+fn signed_then_unsigned_repo(fingerprint: &str) -> (TempDir, Repository) {
+    let repo_dir = TempDir::new().unwrap();
+    let repo = Repository::init(repo_dir.path()).expect("Failed to init throwaway git repo");
+
+    git(
+        repo_dir.path(),
+        fingerprint,
+        &["commit", "--allow-empty", "-S", "-m", "signed"],
+    );
+    git(
+        repo_dir.path(),
+        fingerprint,
+        &["tag", "-s", "v1.0.0", "-m", "release"],
+    );
+    git(
+        repo_dir.path(),
+        fingerprint,
+        &["commit", "--allow-empty", "--no-gpg-sign", "-m", "unsigned"],
+    );
+
+    (repo_dir, repo)
+}
+
+#[test]
+#[serial]
+fn verify_range_reports_signed_and_unsigned_commits() {
+    let fixture = GpgTestFixture::new();
+    assert!(fixture.is_ok(), "Failed to create GPG test fixture");
+    let fixture = fixture.unwrap();
+
+    let fingerprint = fixture.generate_signing_key();
+    assert!(fingerprint.is_ok(), "Failed to generate key");
+    let fingerprint = fingerprint.unwrap();
+    let (_repo_dir, repo) = signed_then_unsigned_repo(&fingerprint);
+
+    let verifications = Verifier::new(&repo).verify_tags(true).verify_range("HEAD");
+    assert!(
+        verifications.is_ok(),
+        "Failed to verify: {:?}",
+        verifications.err()
+    );
+    let verifications = verifications.unwrap();
+    assert_eq!(verifications.len(), 3);
+
+    let signed = &verifications[0];
+    assert_eq!(signed.kind, ObjectKind::Commit);
+    assert_eq!(signed.summary, "signed");
+    assert!(signed.passed());
+    let signature = signed.signature.as_ref().unwrap();
+    assert_eq!(signature.status, SignatureStatus::Good);
+    assert_eq!(
+        signature.primary_fingerprint.as_deref(),
+        Some(fingerprint.as_str())
+    );
+    assert_eq!(signature.uid.as_deref(), Some("batman <batman@dc.com>"));
+    assert_eq!(signature.validity.as_deref(), Some("ultimate"));
+
+    let unsigned = &verifications[1];
+    assert_eq!(unsigned.summary, "unsigned");
+    assert!(unsigned.signature.is_none());
+    assert!(!unsigned.passed());
+
+    let tag = &verifications[2];
+    assert_eq!(tag.kind, ObjectKind::Tag);
+    assert_eq!(tag.summary, "v1.0.0");
+    assert!(tag.passed());
+
+    assert!(gpg_import::verify::report(&verifications).is_err());
+    let signed_only = Verifier::new(&repo).verify_range("HEAD~1");
+    assert!(gpg_import::verify::report(&signed_only.unwrap()).is_ok());
+}
+
+#[test]
+#[serial]
+fn verify_range_rejects_signer_not_in_allowlist() {
+    let fixture = GpgTestFixture::new();
+    assert!(fixture.is_ok(), "Failed to create GPG test fixture");
+    let fixture = fixture.unwrap();
+
+    let fingerprint = fixture.generate_signing_key();
+    assert!(fingerprint.is_ok(), "Failed to generate key");
+    let fingerprint = fingerprint.unwrap();
+    let (_repo_dir, repo) = signed_then_unsigned_repo(&fingerprint);
+
+    let verifications = Verifier::new(&repo)
+        .with_allowed_signers(vec!["24DA69B1615F5F3C6A9C3A60B8527C5AED483BE3".to_string()])
+        .verify_range("HEAD~1");
+    assert!(
+        verifications.is_ok(),
+        "Failed to verify: {:?}",
+        verifications.err()
+    );
+    let verifications = verifications.unwrap();
+    assert_eq!(verifications.len(), 1);
+    assert!(!verifications[0].allowed);
+    assert!(!verifications[0].passed());
+
+    let verifications = Verifier::new(&repo)
+        .with_allowed_signers(vec![fingerprint.to_lowercase()])
+        .verify_range("HEAD~1..HEAD");
+    let verifications = verifications.unwrap();
+    assert_eq!(verifications.len(), 1, "Only the commit within the range");
+    assert_eq!(verifications[0].summary, "unsigned");
+}
+
+#[test]
+#[serial]
+fn verify_range_skips_tags_not_pointing_at_commits() {
+    let fixture = GpgTestFixture::new();
+    assert!(fixture.is_ok(), "Failed to create GPG test fixture");
+    let fixture = fixture.unwrap();
+
+    let fingerprint = fixture.generate_signing_key();
+    assert!(fingerprint.is_ok(), "Failed to generate key");
+    let fingerprint = fingerprint.unwrap();
+    let (repo_dir, repo) = signed_then_unsigned_repo(&fingerprint);
+    git(
+        repo_dir.path(),
+        &fingerprint,
+        &["tag", "-s", "tree", "-m", "tree", "HEAD^{tree}"],
+    );
+
+    let verifications = Verifier::new(&repo).verify_tags(true).verify_range("HEAD");
+    assert!(
+        verifications.is_ok(),
+        "Failed to verify: {:?}",
+        verifications.err()
+    );
+    let tags: Vec<String> = verifications
+        .unwrap()
+        .into_iter()
+        .filter(|verification| verification.kind == ObjectKind::Tag)
+        .map(|verification| verification.summary)
+        .collect();
+    assert_eq!(tags, vec!["v1.0.0"]);
+}
+
+#[test]
+#[serial]
+fn verify_range_supports_symmetric_range() {
+    let fixture = GpgTestFixture::new();
+    assert!(fixture.is_ok(), "Failed to create GPG test fixture");
+    let fixture = fixture.unwrap();
+
+    let fingerprint = fixture.generate_signing_key();
+    assert!(fingerprint.is_ok(), "Failed to generate key");
+    let fingerprint = fingerprint.unwrap();
+    let (repo_dir, repo) = signed_then_unsigned_repo(&fingerprint);
+    git(repo_dir.path(), &fingerprint, &["branch", "-M", "main"]);
+    git(
+        repo_dir.path(),
+        &fingerprint,
+        &["checkout", "-q", "-b", "topic", "HEAD~1"],
+    );
+    git(
+        repo_dir.path(),
+        &fingerprint,
+        &["commit", "--allow-empty", "-S", "-m", "topic"],
+    );
+
+    let verifications = Verifier::new(&repo).verify_range("main...topic");
+    assert!(
+        verifications.is_ok(),
+        "Failed to verify: {:?}",
+        verifications.err()
+    );
+    let mut summaries: Vec<String> = verifications
+        .unwrap()
+        .into_iter()
+        .map(|verification| verification.summary)
+        .collect();
+    summaries.sort();
+    assert_eq!(summaries, vec!["topic", "unsigned"]);
+}
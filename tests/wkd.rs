@@ -0,0 +1,205 @@
+use gpg_import::{
+    gpg,
+    wkd::{self, Wkd, WkdError, WkdMethod},
+};
+use serial_test::serial;
+
+mod fixture;
+use fixture::{http::HttpStandIn, GpgTestFixture};
+
+/// Generates a key for the given email address within its own keyring,
+/// returning its fingerprint and armored public key
+fn generate_public_key(email: &str) -> (String, String) {
+    let fixture = GpgTestFixture::new();
+    assert!(fixture.is_ok(), "Failed to create GPG test fixture");
+    let fixture = fixture.unwrap();
+
+    let fingerprint = fixture.generate_signing_key_for("WKD Test", email);
+    assert!(fingerprint.is_ok(), "Failed to generate key for {email}");
+    let fingerprint = fingerprint.unwrap();
+
+    let public_key = fixture.export_public_keys(std::slice::from_ref(&fingerprint));
+    assert!(
+        public_key.is_ok(),
+        "Failed to export public key for {email}"
+    );
+    (fingerprint, public_key.unwrap())
+}
+
+/// A base URL that refuses connections, standing in for an `openpgpkey`
+/// subdomain that doesn't exist
+fn unreachable_base_url() -> String {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    format!("http://{}", listener.local_addr().unwrap())
+}
+
+#[test]
+#[serial]
+fn fetch_key_falls_back_to_direct_method() {
+    let (fingerprint, public_key) = generate_public_key("robin@dc.com");
+    let hash = wkd::hash_local_part("robin");
+
+    let server = HttpStandIn::serve(vec![(
+        format!("/.well-known/openpgpkey/hu/{hash}"),
+        public_key.into_bytes(),
+    )]);
+
+    let fixture = GpgTestFixture::new();
+    assert!(fixture.is_ok(), "Failed to create GPG test fixture");
+    let _fixture = fixture.unwrap();
+
+    let key = Wkd::new()
+        .with_base_url(server.base_url())
+        .with_advanced_base_url(unreachable_base_url())
+        .fetch_key("Robin@DC.com");
+    assert!(key.is_ok(), "Failed to fetch key: {:?}", key.err());
+    let key = key.unwrap();
+    assert_eq!(key.method, WkdMethod::Direct);
+    assert_eq!(
+        server.requests(),
+        vec![format!("/.well-known/openpgpkey/hu/{hash}?l=Robin")]
+    );
+
+    let imported = gpg::import_public_key_data(&gpg::GpgContext::default(), &key.data);
+    assert!(
+        imported.is_ok(),
+        "Failed to import key: {:?}",
+        imported.err()
+    );
+    assert_eq!(imported.unwrap(), vec![fingerprint]);
+}
+
+#[test]
+#[serial]
+fn fetch_key_prefers_advanced_method() {
+    let (_, public_key) = generate_public_key("alfred@dc.com");
+    let hash = wkd::hash_local_part("alfred");
+
+    let server = HttpStandIn::serve(vec![(
+        format!("/.well-known/openpgpkey/dc.com/hu/{hash}"),
+        public_key.into_bytes(),
+    )]);
+
+    let fixture = GpgTestFixture::new();
+    assert!(fixture.is_ok(), "Failed to create GPG test fixture");
+    let _fixture = fixture.unwrap();
+
+    let key = Wkd::new()
+        .with_base_url(server.base_url())
+        .fetch_key("alfred@dc.com");
+    assert!(key.is_ok(), "Failed to fetch key: {:?}", key.err());
+    assert_eq!(key.unwrap().method, WkdMethod::Advanced);
+    assert_eq!(server.requests().len(), 1);
+}
+
+#[test]
+#[serial]
+fn fetch_key_does_not_fall_back_when_advanced_method_answers() {
+    let (_, public_key) = generate_public_key("robin@dc.com");
+    let hash = wkd::hash_local_part("robin");
+
+    let server = HttpStandIn::serve(vec![(
+        format!("/.well-known/openpgpkey/hu/{hash}"),
+        public_key.into_bytes(),
+    )]);
+
+    let result = Wkd::new()
+        .with_base_url(server.base_url())
+        .fetch_key("robin@dc.com");
+    assert!(matches!(
+        result.unwrap_err().downcast_ref::<WkdError>(),
+        Some(WkdError::KeyNotFound(email, _)) if email == "robin@dc.com"
+    ));
+    assert_eq!(
+        server.requests(),
+        vec![format!("/.well-known/openpgpkey/dc.com/hu/{hash}?l=robin")]
+    );
+}
+
+#[test]
+#[serial]
+fn fetch_key_only_returns_matching_keys() {
+    let (fingerprint, public_key) = generate_public_key("robin@dc.com");
+    let (_, other_public_key) = generate_public_key("joker@dc.com");
+    let hash = wkd::hash_local_part("robin");
+
+    let server = HttpStandIn::serve(vec![(
+        format!("/.well-known/openpgpkey/dc.com/hu/{hash}"),
+        format!("{public_key}{other_public_key}").into_bytes(),
+    )]);
+
+    let fixture = GpgTestFixture::new();
+    assert!(fixture.is_ok(), "Failed to create GPG test fixture");
+    let _fixture = fixture.unwrap();
+
+    let key = Wkd::new()
+        .with_base_url(server.base_url())
+        .fetch_key("robin@dc.com");
+    assert!(key.is_ok(), "Failed to fetch key: {:?}", key.err());
+
+    let imported = gpg::import_public_key_data(&gpg::GpgContext::default(), &key.unwrap().data);
+    assert!(
+        imported.is_ok(),
+        "Failed to import key: {:?}",
+        imported.err()
+    );
+    assert_eq!(imported.unwrap(), vec![fingerprint]);
+}
+
+#[test]
+#[serial]
+fn fetch_key_rejects_oversized_response() {
+    let hash = wkd::hash_local_part("robin");
+
+    let server = HttpStandIn::serve(vec![(
+        format!("/.well-known/openpgpkey/dc.com/hu/{hash}"),
+        vec![b'A'; 1024 * 1024 + 1],
+    )]);
+
+    let result = Wkd::new()
+        .with_base_url(server.base_url())
+        .fetch_key("robin@dc.com");
+    let error = format!("{:#}", result.unwrap_err());
+    assert!(
+        error.contains("wkd response exceeds 1048576 bytes"),
+        "Unexpected error: {error}"
+    );
+}
+
+#[test]
+#[serial]
+fn fetch_key_rejects_key_without_matching_uid() {
+    let (_, public_key) = generate_public_key("joker@dc.com");
+    let hash = wkd::hash_local_part("robin");
+
+    let server = HttpStandIn::serve(vec![(
+        format!("/.well-known/openpgpkey/dc.com/hu/{hash}"),
+        public_key.into_bytes(),
+    )]);
+
+    let fixture = GpgTestFixture::new();
+    assert!(fixture.is_ok(), "Failed to create GPG test fixture");
+    let _fixture = fixture.unwrap();
+
+    let result = Wkd::new()
+        .with_base_url(server.base_url())
+        .fetch_key("robin@dc.com");
+    assert_eq!(
+        result.unwrap_err().downcast_ref::<WkdError>(),
+        Some(&WkdError::UidMismatch("robin@dc.com".to_string()))
+    );
+}
+
+#[test]
+#[serial]
+fn fetch_key_not_found() {
+    let server = HttpStandIn::serve(Vec::new());
+
+    let result = Wkd::new()
+        .with_base_url(server.base_url())
+        .fetch_key("batman@dc.com");
+    assert!(matches!(
+        result.unwrap_err().downcast_ref::<WkdError>(),
+        Some(WkdError::KeyNotFound(email, _)) if email == "batman@dc.com"
+    ));
+}